@@ -1,4 +1,4 @@
-use clap::{Arg, ArgMatches, Command as ClapCommand};
+use clap::{Arg, ArgAction, ArgMatches, Command as ClapCommand};
 
 pub fn parse_args() -> ArgMatches {
     ClapCommand::new("arrayify")
@@ -7,117 +7,1287 @@ pub fn parse_args() -> ArgMatches {
         .about("Submits and checks bsub job arrays from a CSV file or directory")
         .subcommand_required(true)
         .arg_required_else_help(true)
+        .arg(
+            Arg::new("scheduler")
+                .long("scheduler")
+                .value_name("SCHEDULER")
+                .help("Batch scheduler to use; 'auto' detects it from bsub/sbatch/qsub on PATH")
+                .value_parser(["auto", "lsf", "slurm", "sge"])
+                .default_value("auto")
+                .global(true)
+        )
+        .arg(
+            Arg::new("no_emoji")
+                .long("no-emoji")
+                .help("Print ASCII tags such as [OK] and [FAIL] instead of emoji (or set NO_EMOJI)")
+                .action(ArgAction::SetTrue)
+                .global(true)
+        )
+        .subcommand(sub_command())
         .subcommand(
-            ClapCommand::new("sub")
-                .about("Submit a job array from a CSV file or a directory")
+            sub_command()
+                .name("run-until-done")
+                .about("Submit, wait and resubmit failed tasks until every task succeeds")
                 .long_about(
-                    "This command allows you to submit a job array using a CSV file or a directory. \
-                    You must provide a command template with placeholders. \
-                    Example: 'echo {ID} {R1} {R2}'"
+                    "Takes the same options as sub. After submitting, it polls the array until every \
+                    task has finished, then resubmits the commands of any failed tasks as a new array, \
+                    with more memory if any of them ran out of it. This repeats until all tasks succeed \
+                    or --max-attempts submissions have been made, in which case it exits non-zero."
                 )
                 .arg(
-                    Arg::new("csv")
-                        .short('s')
-                        .long("csv")
-                        .value_name("CSV_FILE")
-                        .help("Path to the CSV file containing job information")
-                        .long_help(
-                            "Specify a CSV file containing job details. \
-                            Each row represents a separate job, and headers can be used as placeholders \
-                            in the command template."
-                        )
-                        .conflicts_with("dir")
-                        .required_unless_present("dir")
+                    Arg::new("max_attempts")
+                        .long("max-attempts")
+                        .value_name("N")
+                        .help("Give up after N submissions in total")
+                        .default_value("3")
+                        .value_parser(clap::value_parser!(u32).range(1..))
                 )
                 .arg(
-                    Arg::new("dir")
-                        .short('d')
-                        .long("dir")
-                        .value_name("DIRECTORY")
-                        .help("Path to the directory containing input files")
-                        .long_help(
-                            "Specify a directory that contains input files for job processing. \
-                            This option is mutually exclusive with --csv. \
-                            Headers are always ID, R1, R2 extracted from _1* _2* and ID being the prefix"
-                        )
-                        .conflicts_with("csv")
-                        .required_unless_present("csv")
+                    Arg::new("memory_factor")
+                        .long("memory-factor")
+                        .value_name("FACTOR")
+                        .help("Multiply the memory by FACTOR when retrying tasks that ran out of it")
+                        .default_value("2")
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                )
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .value_name("SECS")
+                        .help("Seconds between status checks")
+                        .default_value("60")
+                        .value_parser(clap::value_parser!(u64))
+                )
+        )
+        .subcommand(
+            ClapCommand::new("export")
+                .about("Print the rendered jobs as a Snakemake or Nextflow workflow")
+                .long_about(
+                    "Takes the same input and rendering options as sub, renders the commands and \
+                    prints them as a workflow skeleton instead of submitting them: one rule per \
+                    command for Snakemake, or one process fed every command for Nextflow."
+                )
+                .args(input_args())
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("ENGINE")
+                        .help("Workflow engine to write for")
+                        .value_parser(["snakemake", "nextflow"])
+                        .required(true)
+                )
+        )
+        .subcommand(
+            ClapCommand::new("render")
+                .about("Render a command template against sample values")
+                .long_about(
+                    "Prints the command a template renders to for the placeholder values given with \
+                    --set, without any input file, to check a template before sharing or \
+                    submitting it. Placeholders with no value are left as they are."
                 )
                 .arg(
                     Arg::new("command")
                         .short('c')
                         .long("command")
                         .value_name("COMMAND_TEMPLATE")
-                        .help("Command template using placeholders for CSV headers")
+                        .help("The command template to render")
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("set")
+                        .long("set")
+                        .value_name("KEY=VALUE")
+                        .help("A placeholder value; repeat for each placeholder")
+                        .action(ArgAction::Append)
+                )
+        )
+        .subcommand(
+            ClapCommand::new("check")
+                .about("Check the status of a submitted job")
+                .long_about(
+                    "Use this command to check the status of a job by providing its LSF Job ID."
+                )
+                .arg(
+                    Arg::new("job_id")
+                        .value_name("JOB_ID")
+                        .help("The LSF Job ID or array name to check; give several (or a comma list) for a combined summary")
+                        .required(true)
+                        .num_args(1..)
+                        .value_delimiter(',')
+                )
+                .arg(
+                    Arg::new("log")
+                        .short('l')
+                        .long("log")
+                        .value_name("LOG_DIR")
+                        .help("Directory holding the submission logs, used to show the submission's note")
+                        .default_value("logs")
+                )
+                .arg(
+                    Arg::new("watch")
+                        .short('w')
+                        .long("watch")
+                        .help("Keep polling until every task has finished")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("tui")
+                        .long("tui")
+                        .help("Show a live dashboard of task states, exit reasons and failed tasks")
+                        .long_help(
+                            "Show a live terminal dashboard, refreshed every --interval seconds, with the \
+                            number of tasks in each state, a histogram of exit reasons and a scrollable \
+                            list of failed tasks. Press q to leave it. When stdout isn't a terminal this \
+                            falls back to --watch."
+                        )
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("watch")
+                )
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .value_name("SECS")
+                        .help("Seconds between polls with --watch or --tui")
+                        .default_value("60")
+                        .value_parser(clap::value_parser!(u64))
+                )
+                .arg(
+                    Arg::new("why")
+                        .long("why")
+                        .help("Show why pending tasks are still pending, from bjobs -p")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["watch", "tui"])
+                )
+                .arg(
+                    Arg::new("dump_failed")
+                        .long("dump-failed")
+                        .value_name("FILE")
+                        .help("Write the commands of failed tasks to FILE, one per line")
                         .long_help(
-                            "Define the command template that will be executed for each job. \
-                            Placeholders enclosed in {} (e.g., {ID}, {R1}, {R2}) will be replaced with \
-                            values from the CSV or directory listing. \
-                            Example: 'echo {ID} {R1} {R2}'"
+                            "Write the commands of the failed tasks to FILE, one per line, ready to run \
+                            by hand or to resubmit with 'arrayify sub --jobs-file FILE'. The commands are \
+                            looked up in the command log recorded in the submission's manifest in --log."
                         )
+                )
+                .arg(
+                    Arg::new("on_success")
+                        .long("on-success")
+                        .value_name("CMD")
+                        .help("Command to run once the array finishes with no failures ({job_id}, {failed_count})")
+                )
+                .arg(
+                    Arg::new("on_failure")
+                        .long("on-failure")
+                        .value_name("CMD")
+                        .help("Command to run once the array finishes with failures ({job_id}, {failed_count})")
+                )
+        )
+        .subcommand(
+            ClapCommand::new("release")
+                .about("Release an array submitted with --hold")
+                .long_about(
+                    "Use this command to let the tasks of a held array start, by providing its LSF Job ID."
+                )
+                .arg(
+                    Arg::new("job_id")
+                        .value_name("JOB_ID")
+                        .help("The LSF Job ID to release")
+                        .required(true)
+                )
+        )
+        .subcommand(
+            ClapCommand::new("usage")
+                .about("Summarise the memory and run time a finished array actually used")
+                .long_about(
+                    "Reports the median, 90th percentile and maximum memory and run time of an array's \
+                    finished tasks, as recorded by LSF, to help right-size the next submission."
+                )
+                .arg(
+                    Arg::new("job_id")
+                        .value_name("JOB_ID")
+                        .help("The LSF Job ID to summarise")
                         .required(true)
                 )
                 .arg(
-                    Arg::new("job_prefix")
-                    .short('p')
-                    .long("job_prefix")
-                    .value_name("PREFIX")
-                    .help("prefix for job submission name i.e. prefix_job_array")
-                    .default_value("arrayify")
+                    Arg::new("log")
+                        .short('l')
+                        .long("log")
+                        .value_name("LOG_DIR")
+                        .help("Directory holding the submission logs, used to compare with the requested memory")
+                        .default_value("logs")
+                )
+        )
+        .subcommand(
+            ClapCommand::new("rerun")
+                .about("Resubmit a previous array from its original input")
+                .long_about(
+                    "Re-reads the input recorded in the manifest of a previous submission and submits \
+                    it as a fresh array. Anything not given on the command line is inherited from the \
+                    original submission."
+                )
+                .arg(
+                    Arg::new("job_id")
+                        .value_name("JOB_ID")
+                        .help("The LSF Job ID of the submission to rerun")
+                        .required(true)
                 )
                 .arg(
                     Arg::new("log")
                         .short('l')
                         .long("log")
                         .value_name("LOG_DIR")
-                        .help("Directory to store log files")
+                        .help("Directory holding the original submission's logs")
                         .default_value("logs")
                 )
+                .arg(
+                    Arg::new("command")
+                        .short('c')
+                        .long("command")
+                        .value_name("COMMAND_TEMPLATE")
+                        .help("Replacement command template")
+                )
                 .arg(
                     Arg::new("memory")
                         .short('m')
                         .long("memory")
                         .value_name("MEMORY_GB")
-                        .help("Amount of memory per job in GB")
-                        .default_value("1")
+                        .help("Replacement memory per job in GB")
+                        .value_parser(clap::value_parser!(u32))
                 )
                 .arg(
                     Arg::new("threads")
                         .short('t')
                         .long("threads")
                         .value_name("THREADS")
-                        .help("Number of threads per job")
-                        .default_value("1")
+                        .help("Replacement number of threads per job")
+                        .value_parser(clap::value_parser!(u32))
                 )
                 .arg(
-                    Arg::new("batch_size")
-                        .short('b')
-                        .long("batch")
-                        .value_name("BATCH_SIZE")
-                        .help("Number of jobs running concurrently (default: 20% of array)")
-                        .default_value("auto")
+                    Arg::new("queue")
+                        .short('q')
+                        .long("queue")
+                        .value_name("QUEUE")
+                        .help("Replacement bsub queue")
                 )
                 .arg(
-                    Arg::new("queue")
-                    .short('q')
-                    .long("queue")
-                    .value_name("QUEUE")
-                    .help("Bsub queue to submit to")
-                    .default_value("normal")
+                    Arg::new("exclude_host")
+                        .long("exclude-host")
+                        .value_name("HOST")
+                        .help("Additionally never run tasks on HOST (repeatable)")
+                        .action(ArgAction::Append)
                 )
         )
         .subcommand(
-            ClapCommand::new("check")
-                .about("Check the status of a submitted job")
+            ClapCommand::new("history")
+                .about("List recent submissions")
                 .long_about(
-                    "Use this command to check the status of a job by providing its LSF Job ID."
+                    "Lists the submissions recorded in a log directory's manifests, most recent first."
+                )
+                .arg(
+                    Arg::new("log")
+                        .short('l')
+                        .long("log")
+                        .value_name("LOG_DIR")
+                        .help("Directory holding the submission logs")
+                        .default_value("logs")
+                )
+                .arg(
+                    Arg::new("limit")
+                        .short('n')
+                        .long("limit")
+                        .value_name("N")
+                        .help("Show at most N submissions")
+                        .default_value("20")
+                        .value_parser(clap::value_parser!(usize))
+                )
+        )
+        .subcommand(
+            ClapCommand::new("collect")
+                .about("Concatenate an array's task output in index order")
+                .long_about(
+                    "Concatenates the job_<ID>_<INDEX>.out and .err files LSF wrote for an array into \
+                    one stream, ordered by array index, each file headed by its name."
                 )
                 .arg(
                     Arg::new("job_id")
                         .value_name("JOB_ID")
-                        .help("The LSF Job ID to check")
+                        .help("The LSF Job ID whose output to collect")
+                        .required(true)
+                )
+                .arg(
+                    Arg::new("log")
+                        .short('l')
+                        .long("log")
+                        .value_name("LOG_DIR")
+                        .help("Directory holding the task output")
+                        .default_value("logs")
+                )
+                .arg(
+                    Arg::new("stream")
+                        .long("stream")
+                        .value_name("STREAM")
+                        .help("Collect stdout (out), stderr (err), both (each task's stdout first) or the exit codes from --exit-codes (code)")
+                        .value_parser(["out", "err", "both", "code"])
+                        .default_value("out")
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Write to FILE instead of stdout")
+                )
+        )
+        .subcommand(
+            ClapCommand::new("clean")
+                .about("Remove old arrayify files from a log directory")
+                .long_about(
+                    "Removes the task reports (job_<ID>_<INDEX>.out/.err), command logs, manifests and \
+                    kept scripts arrayify wrote to a log directory that were last modified longer ago \
+                    than --older-than. Files not named like arrayify's are never touched."
+                )
+                .arg(
+                    Arg::new("log")
+                        .short('l')
+                        .long("log")
+                        .visible_alias("log-dir")
+                        .value_name("LOG_DIR")
+                        .help("Directory holding the submission logs")
+                        .default_value("logs")
+                )
+                .arg(
+                    Arg::new("older_than")
+                        .long("older-than")
+                        .value_name("AGE")
+                        .help("Only remove files older than AGE, e.g. 30d, 2w, 12h")
                         .required(true)
+                        .value_parser(crate::clean::parse_age)
+                )
+                .arg(
+                    Arg::new("dry_run")
+                        .long("dry-run")
+                        .help("List the files that would be removed without removing them")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            ClapCommand::new("doctor")
+                .about("Print diagnostics to include in bug reports")
+                .long_about(
+                    "Prints the arrayify version, the detected scheduler and any LSB_* environment \
+                    variables. Given an input and a command template, it also renders the first \
+                    command exactly as sub would."
+                )
+                .arg(
+                    Arg::new("csv")
+                        .short('s')
+                        .long("csv")
+                        .value_name("CSV_FILE")
+                        .help("CSV file to render a sample command from")
+                        .conflicts_with_all(["dir", "glob", "list"])
+                        .requires("command")
+                )
+                .arg(
+                    Arg::new("dir")
+                        .short('d')
+                        .long("dir")
+                        .value_name("DIRECTORY")
+                        .help("Directory of paired files to render a sample command from")
+                        .conflicts_with_all(["glob", "list"])
+                        .requires("command")
+                )
+                .arg(
+                    Arg::new("glob")
+                        .short('g')
+                        .long("glob")
+                        .value_name("PATTERN")
+                        .help("Glob pattern to render a sample command from")
+                        .conflicts_with("list")
+                        .requires("command")
+                )
+                .arg(
+                    Arg::new("list")
+                        .long("list")
+                        .value_name("FILE")
+                        .help("List file to render a sample command from")
+                        .requires("command")
+                )
+                .arg(
+                    Arg::new("command")
+                        .short('c')
+                        .long("command")
+                        .value_name("COMMAND_TEMPLATE")
+                        .help("Command template to render against the input")
                 )
         )
         .get_matches()
 }
+
+/// Arguments of `sub`, shared with `run-until-done`.
+/// Options of `sub` that pick the input and render the commands from it, and
+/// so also apply to `export`.
+const INPUT_ARGS: &[&str] = &[
+    "csv",
+    "dir",
+    "glob",
+    "list",
+    "jobs_file",
+    "json_input",
+    "command",
+    "pad_short_rows",
+    "encoding",
+    "require_columns",
+    "template_column",
+    "comment_char",
+    "task_log",
+    "absolute_paths",
+    "merge_lanes",
+    "reads_separator",
+    "placeholder_alias",
+    "vars",
+    "delim",
+    "pre_submit_validate",
+    "dedup",
+    "no_skip_empty",
+];
+
+/// The input and rendering arguments of `sub`.
+fn input_args() -> Vec<Arg> {
+    sub_command()
+        .get_arguments()
+        .filter(|arg| INPUT_ARGS.contains(&arg.get_id().as_str()))
+        .cloned()
+        .collect()
+}
+
+fn sub_command() -> ClapCommand {
+    ClapCommand::new("sub")
+        .about("Submit a job array from a CSV file or a directory")
+        .long_about(
+            "This command allows you to submit a job array using a CSV file or a directory. \
+            You must provide a command template with placeholders. \
+            Example: 'echo {ID} {R1} {R2}'"
+        )
+        .arg(
+            Arg::new("csv")
+                .short('s')
+                .long("csv")
+                .value_name("CSV_FILE")
+                .help("Path to the CSV file containing job information")
+                .long_help(
+                    "Specify a CSV file containing job details. \
+                    Each row represents a separate job, and headers can be used as placeholders \
+                    in the command template. {ROW} is replaced with the 1-based data row number \
+                    (the header row is not counted). Repeat --csv to combine several CSVs with the \
+                    same columns into one array."
+                )
+                .action(ArgAction::Append)
+                .conflicts_with_all(["dir", "glob", "list", "jobs_file", "json_input"])
+                .required_unless_present_any(["dir", "glob", "list", "jobs_file", "json_input"])
+        )
+        .arg(
+            Arg::new("dir")
+                .short('d')
+                .long("dir")
+                .value_name("DIRECTORY")
+                .help("Path to the directory containing input files")
+                .long_help(
+                    "Specify a directory that contains input files for job processing. \
+                    This option is mutually exclusive with --csv. \
+                    Headers are always ID, R1, R2 extracted from _1* _2* and ID being the prefix"
+                )
+                .conflicts_with_all(["csv", "glob", "list", "jobs_file", "json_input"])
+                .required_unless_present_any(["csv", "glob", "list", "jobs_file", "json_input"])
+        )
+        .arg(
+            Arg::new("glob")
+                .short('g')
+                .long("glob")
+                .value_name("PATTERN")
+                .help("Glob pattern matching input files anywhere, e.g. '/data/**/*.fastq.gz'")
+                .long_help(
+                    "Specify a glob pattern (quoted, ** recurses) matching input files for job processing. \
+                    Matches are paired exactly like --dir, so headers are always ID, R1, R2. \
+                    This option is mutually exclusive with --csv and --dir."
+                )
+                .conflicts_with_all(["csv", "dir", "list", "jobs_file", "json_input"])
+                .required_unless_present_any(["csv", "dir", "list", "jobs_file", "json_input"])
+        )
+        .arg(
+            Arg::new("list")
+                .long("list")
+                .value_name("FILE")
+                .help("File with one item per line, substituted into {ITEM} ('-' reads stdin)")
+                .long_help(
+                    "Specify a plain list with one item (e.g. a file path) per line; each line \
+                    becomes a job with the item substituted into {ITEM}. Use '-' to read the list \
+                    from stdin. Blank lines and lines starting with the comment character are skipped. \
+                    This option is mutually exclusive with --csv, --dir and --glob."
+                )
+                .conflicts_with_all(["csv", "dir", "glob", "jobs_file", "json_input"])
+                .required_unless_present_any(["csv", "dir", "glob", "jobs_file", "json_input"])
+        )
+        .arg(
+            Arg::new("jobs_file")
+                .long("jobs-file")
+                .value_name("FILE")
+                .help("Submit a file of ready-made commands, one per line, as is")
+                .long_help(
+                    "Submit a file that already holds exactly one command per line, such as a \
+                    hand-curated commands.txt. No input is read and no template is rendered: each \
+                    task runs its line of the file directly, and the file itself serves as the \
+                    command log. This option is mutually exclusive with --csv, --dir, --glob, \
+                    --list and --command."
+                )
+                .conflicts_with_all(["csv", "dir", "glob", "list", "json_input", "command"])
+        )
+        .arg(
+            Arg::new("json_input")
+                .long("json-input")
+                .value_name("FILE")
+                .help("JSON array of commands, or of objects whose fields fill the template")
+                .long_help(
+                    "Read jobs from a JSON array, as emitted by many upstream tools. String \
+                    elements are used as ready-made commands. The fields of object elements are \
+                    substituted into the command template like CSV columns, with {ROW} as the \
+                    element number. This option is mutually exclusive with --csv, --dir, --glob, \
+                    --list and --jobs-file."
+                )
+                .conflicts_with_all(["csv", "dir", "glob", "list"])
+        )
+        .arg(
+            Arg::new("index_range")
+                .long("index-range")
+                .value_name("START-END")
+                .help("With --jobs-file, submit only lines START to END (1-based, inclusive)")
+                .long_help(
+                    "Submit only lines START to END of a --jobs-file, e.g. to finish off a \
+                    submission that died partway. Array indices match the line numbers, so \
+                    task 250 still runs line 250. The range must lie within the file."
+                )
+                .requires("jobs_file")
+                .conflicts_with("chunk")
+                .value_parser(crate::submission::parse_index_range)
+        )
+        .arg(
+            Arg::new("pad_short_rows")
+                .long("pad-short-rows")
+                .help("Fill missing trailing CSV columns with empty values instead of erroring")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("encoding")
+                .long("encoding")
+                .value_name("ENCODING")
+                .help("Character encoding of the CSV, e.g. latin1 (default: UTF-8)")
+        )
+        .arg(
+            Arg::new("hold")
+                .long("hold")
+                .help("Submit the array held (bsub -H); start it later with 'arrayify release'")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("verify")
+                .long("verify")
+                .help("After submitting, check bjobs reports every array element")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("task_log")
+                .long("task-log")
+                .value_name("PATH_TEMPLATE")
+                .help("Per-task log path template, e.g. '{ID}' or '{R1}'; writes <path>.out and <path>.err")
+                .long_help(
+                    "Send each task's stdout and stderr to a path rendered from the same placeholders \
+                    as the command, e.g. 'results/{ID}' writes results/<ID>.out and results/<ID>.err. \
+                    LSF still writes its own job reports to the --log directory."
+                )
+        )
+        .arg(
+            Arg::new("tee")
+                .long("tee")
+                .value_name("FILE")
+                .help("Also append every task's stdout to FILE as it runs, prefixed with its index")
+                .long_help(
+                    "Mirror each task's stdout into one shared file while it runs, every line \
+                    prefixed with the task index, e.g. '[12] done'. The per-task .out files are \
+                    still written. Output a command sends to --task-log isn't mirrored."
+                )
+        )
+        .arg(
+            Arg::new("absolute_paths")
+                .long("absolute-paths")
+                .help("Substitute absolute R1/R2 paths in --dir and --glob mode")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("merge_lanes")
+                .long("merge-lanes")
+                .value_name("SEP")
+                .help("Make one task per sample from lane-split files (S1_L001_R1...), joining lanes with SEP (default space)")
+                .long_help(
+                    "Group files named like S1_L001_R1_001.fastq.gz or S1_L002_2.fq by sample, \
+                    ignoring the lane, so each sample is one task. {R1} and {R2} hold all the \
+                    sample's lane files for that read, in lane order, joined by SEP: a space by \
+                    default, e.g. for 'cat {R1} > merged_R1.fq', or --merge-lanes=, for a comma list."
+                )
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value(" ")
+        )
+        .arg(
+            Arg::new("reads_separator")
+                .long("reads-separator")
+                .value_name("SEP")
+                .help("Text joining R1 and R2 in the {READS} placeholder of --dir and --glob mode")
+                .default_value(",")
+        )
+        .arg(
+            Arg::new("placeholder_alias")
+                .long("placeholder-alias")
+                .value_name("NAME=ALIAS")
+                .help("Treat {ALIAS} and {NAME} as the same placeholder (repeatable), e.g. ID=sample")
+                .long_help(
+                    "Make {NAME} and {ALIAS} interchangeable, using whichever of the two the input \
+                    provides. With ID=sample, the template 'align {sample} {R1} {R2}' works both for \
+                    a CSV with a 'sample' column and for --dir, which provides {ID}. Repeat the flag \
+                    for several aliases."
+                )
+                .action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("vars")
+                .long("vars")
+                .alias("template-vars")
+                .value_name("FILE")
+                .help("TOML file of constants usable as {NAME} placeholders in every row")
+                .long_help(
+                    "Read a flat TOML table of constants, e.g. ref = \"/refs/GRCh38.fa\", and fill \
+                    {ref} in the command and --task-log templates of every row. An input column \
+                    of the same name takes precedence over the var."
+                )
+        )
+        .arg(
+            Arg::new("chunk")
+                .long("chunk")
+                .value_name("K")
+                .help("Run K consecutive commands per array task, one after another")
+                .long_help(
+                    "Have each array task run K consecutive commands from the command log in turn, \
+                    shrinking the array by a factor of K. Worth it when each command only takes \
+                    seconds and scheduler overhead dominates. Concurrency (--batch) then counts tasks."
+                )
+                .default_value("1")
+                .value_parser(clap::value_parser!(usize))
+        )
+        .arg(
+            Arg::new("rusage")
+                .long("rusage")
+                .value_name("TEMPLATE")
+                .help("Custom -R resource string, with {mem} replaced by the memory in MB")
+                .long_help(
+                    "Replace the default resource string 'select[mem>{mem}] rusage[mem={mem}]' \
+                    with your own, e.g. 'select[mem>{mem}] rusage[mem={mem},tmp=20000]' to also \
+                    reserve scratch space. {mem} is replaced by the memory per job in MB. \
+                    The template replaces the whole string, including any --exclude-host entries."
+                )
+        )
+        .arg(
+            Arg::new("check_paths")
+                .long("check-paths")
+                .help("Warn about file paths referenced by the jobs that don't exist")
+                .long_help(
+                    "Before submitting, look for file paths referenced by the jobs and warn about \
+                    any that don't exist on this host. By default every absolute path in the rendered \
+                    commands is checked; use --path-columns to check specific columns instead. \
+                    Pairs well with --dry-run."
+                )
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("path_columns")
+                .long("path-columns")
+                .value_name("COLUMNS")
+                .help("Comma-separated columns holding file paths, checked by --check-paths")
+                .value_delimiter(',')
+                .requires("check_paths")
+        )
+        .arg(
+            Arg::new("pre_submit_validate")
+                .long("pre-submit-validate")
+                .help("Check every CSV row and report all the malformed ones, not just the first")
+                .long_help(
+                    "Check every CSV row and report all the malformed ones, not just the first. \
+                    Nothing is submitted unless the whole input renders, with or without this flag; \
+                    it only changes how much is reported, so one run lists every short, long or \
+                    incomplete row to fix. Pairs well with --dry-run."
+                )
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("warn_collisions")
+                .long("warn-collisions")
+                .help("Warn when several tasks write the same output path")
+                .long_help(
+                    "Before submitting, warn when several tasks write the same output path, e.g. a \
+                    template writing out.bam rather than {ID}.bam, so the tasks would overwrite \
+                    each other. By default the targets of >, -o and --output in the rendered commands \
+                    are compared; use --output-columns to compare specific columns instead."
+                )
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("output_columns")
+                .long("output-columns")
+                .value_name("COLUMNS")
+                .help("Comma-separated columns holding output paths, compared by --warn-collisions")
+                .value_delimiter(',')
+                .requires("warn_collisions")
+        )
+        .arg(
+            Arg::new("delim")
+                .long("delim")
+                .value_name("MARKERS")
+                .help("Placeholder markers to use instead of braces, e.g. '%%' or '<< >>'")
+                .long_help(
+                    "Write placeholders with other markers, for commands that contain literal braces \
+                    such as awk programs or JSON. Give one marker used on both sides ('%%' for \
+                    %%ID%%) or an opening and closing marker separated by a space ('<< >>' for \
+                    <<ID>>). Braces in the template are then left exactly as written."
+                )
+        )
+        .arg(
+            Arg::new("template_column")
+                .long("template-column")
+                .value_name("COLUMN")
+                .help("With --csv, treat COLUMN's values as templates rendered against their row")
+                .long_help(
+                    "Render the placeholders in each row's COLUMN value against that row, then splice \
+                    the result in wherever the command template uses {COLUMN}. This lets part of the \
+                    command vary per row while still using other columns. Only one level is rendered: \
+                    values substituted into COLUMN are never expanded again."
+                )
+        )
+        .arg(
+            Arg::new("require_columns")
+                .long("require-columns")
+                .value_name("COLUMNS")
+                .help("Comma-separated CSV columns that must exist and be non-empty on every row")
+                .value_delimiter(',')
+        )
+        .arg(
+            Arg::new("note")
+                .long("note")
+                .value_name("TEXT")
+                .help("Free-text note stored with the submission and shown by history and check")
+        )
+        .arg(
+            Arg::new("smoke_test")
+                .long("smoke-test")
+                .help("Run the first command locally and only submit if it succeeds")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("smoke_timeout")
+                .long("smoke-timeout")
+                .value_name("SECS")
+                .help("With --smoke-test, accept a first command still running after SECS seconds")
+                .requires("smoke_test")
+                .value_parser(clap::value_parser!(u64))
+        )
+        .arg(
+            Arg::new("keep_script")
+                .long("keep-script")
+                .value_name("PATH")
+                .help("Keep the task script given to bsub (default: next to the command log)")
+                .num_args(0..=1)
+                .default_missing_value("")
+        )
+        .arg(
+            Arg::new("no_duplicate")
+                .long("no-duplicate")
+                .help("Refuse to repeat an identical recent submission unless --force is given")
+                .long_help(
+                    "Fingerprint the rendered commands and resources and compare them with the \
+                    manifests in the log directory. If an identical submission was made within \
+                    --duplicate-window hours, warn and refuse unless --force is given."
+                )
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("duplicate_window")
+                .long("duplicate-window")
+                .value_name("HOURS")
+                .help("How far back --no-duplicate looks for identical submissions")
+                .default_value("24")
+                .value_parser(clap::value_parser!(u32))
+        )
+        .arg(
+            Arg::new("profile_readers")
+                .long("profile-readers")
+                .help("Time reading the input (rows/sec) without submitting")
+                .hide(true)
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("comment_char")
+                .long("comment-char")
+                .value_name("CHAR")
+                .help("Lines of a --list starting with CHAR are skipped")
+                .default_value("#")
+                .value_parser(clap::value_parser!(char))
+        )
+        .arg(
+            Arg::new("command")
+                .short('c')
+                .long("command")
+                .value_name("COMMAND_TEMPLATE")
+                .help("Command template using placeholders for CSV headers")
+                .long_help(
+                    "Define the command template that will be executed for each job. \
+                    Placeholders enclosed in {} (e.g., {ID}, {R1}, {R2}) will be replaced with \
+                    values from the CSV or directory listing. \
+                    Example: 'echo {ID} {R1} {R2}'"
+                )
+                .required_unless_present_any(["jobs_file", "json_input"])
+        )
+        .arg(
+            Arg::new("job_prefix")
+            .short('p')
+            .long("job_prefix")
+            .value_name("PREFIX")
+            .help("prefix for job submission name i.e. prefix_job_array")
+            .default_value("arrayify")
+        )
+        .arg(
+            Arg::new("log")
+                .short('l')
+                .long("log")
+                .value_name("LOG_DIR")
+                .help("Directory to store log files")
+                .default_value("logs")
+        )
+        .arg(
+            Arg::new("memory")
+                .short('m')
+                .long("memory")
+                .value_name("MEMORY_GB")
+                .help("Amount of memory per job in GB")
+                .default_value("1")
+        )
+        .arg(
+            Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .value_name("THREADS")
+                .help("Number of threads per job")
+                .default_value("1")
+        )
+        .arg(
+            Arg::new("time")
+                .short('W')
+                .long("time")
+                .value_name("MINUTES")
+                .help("Run time limit per job in minutes (default: queue limit)")
+                .value_parser(clap::value_parser!(u32))
+        )
+        .arg(
+            Arg::new("min_time")
+                .long("min-time")
+                .value_name("MINUTES")
+                .help("Warn if --time is below this many minutes")
+                .default_value("10")
+                .value_parser(clap::value_parser!(u32))
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("Named queue/memory/threads/time profile from the config file")
+                .long_help(
+                    "Use a named resource profile from the config file ($ARRAYIFY_CONFIG or \
+                    ~/.config/arrayify/config.toml). Flags given explicitly on the command line \
+                    override the profile, which overrides the built-in defaults."
+                )
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("CONFIG_FILE")
+                .help("Config file to read profiles from")
+        )
+        .arg(
+            Arg::new("batch_size")
+                .short('b')
+                .long("batch")
+                .value_name("BATCH_SIZE")
+                .help("Number of jobs running concurrently (default: 20% of array)")
+                .default_value("auto")
+        )
+        .arg(
+            Arg::new("local")
+                .long("local")
+                .help("Run the tasks on this machine instead of submitting them")
+                .long_help(
+                    "Run the tasks on this machine instead of submitting them to the scheduler, \
+                    several at a time (see --local-jobs). Each task runs the script the array would \
+                    get, prolog, epilog, retries and chunking included, with LSB_JOBID set to \
+                    arrayify's process ID. Its output goes to <LOG_DIR>/job_<ID>_<INDEX>.out and .err. Useful for small inputs or for trying a \
+                    pipeline out before it goes to the farm."
+                )
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["dry_run", "emit_script", "submit_host"])
+        )
+        .arg(
+            Arg::new("local_jobs")
+                .long("local-jobs")
+                .value_name("N")
+                .help("With --local, how many tasks run at once [default: number of CPUs]")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .requires("local")
+        )
+        .arg(
+            Arg::new("submit_host")
+                .long("submit-host")
+                .value_name("USER@HOST")
+                .help("Run bsub on USER@HOST over SSH instead of locally")
+                .long_help(
+                    "Submit from another machine by running bsub on USER@HOST over SSH. The command log \
+                    is copied to the same path on that host first, and the task script is sent on \
+                    stdin. Use a --log directory that exists on the cluster, ideally an absolute path."
+                )
+        )
+        .arg(
+            Arg::new("batch_of_queue")
+                .long("batch-of-queue")
+                .value_name("PERCENT")
+                .help("Size the automatic batch as PERCENT of the queue's slot limit instead of the array")
+                .long_help(
+                    "Query bqueues for the queue's slot limit and run PERCENT of it concurrently, \
+                    capped at the array size. Falls back to 20% of the array if the queue has no \
+                    limit or can't be queried. Ignored when --batch is given."
+                )
+                .value_parser(clap::value_parser!(u32).range(1..=100))
+        )
+        .arg(
+            Arg::new("max_total_mem")
+                .long("max-total-mem")
+                .value_name("GB")
+                .help("Lower the batch so running tasks use at most GB of memory between them")
+                .value_parser(clap::value_parser!(u32).range(1..))
+        )
+        .arg(
+            Arg::new("max_total_slots")
+                .long("max-total-slots")
+                .value_name("N")
+                .help("Lower the batch so running tasks use at most N slots between them")
+                .value_parser(clap::value_parser!(u32).range(1..))
+        )
+        .arg(
+            Arg::new("min_batch")
+                .long("min-batch")
+                .value_name("N")
+                .help("Never let the automatic batch size drop below N (capped at the number of jobs)")
+                .value_parser(clap::value_parser!(usize))
+        )
+        .arg(
+            Arg::new("queue")
+            .short('q')
+            .long("queue")
+            .value_name("QUEUE")
+            .help("Bsub queue to submit to")
+            .default_value("normal")
+        )
+        .arg(
+            Arg::new("dedup")
+                .long("dedup")
+                .help("Drop duplicate commands after rendering, keeping the first occurrence")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("prolog")
+                .long("prolog")
+                .value_name("FILE")
+                .help("Script run before each task's command; any failure aborts the task")
+        )
+        .arg(
+            Arg::new("epilog")
+                .long("epilog")
+                .value_name("FILE")
+                .help("Script run after each task's command; the task keeps the command's exit code")
+        )
+        .arg(
+            Arg::new("max_array_size")
+                .long("max-array-size")
+                .value_name("N")
+                .help("Largest array the scheduler accepts (default: LSF's MAX_JOB_ARRAY_SIZE, or 1000)")
+                .long_help(
+                    "When there are more commands than this, run several per task (as with --chunk) so \
+                    the array stays within the limit instead of being rejected. By default the limit is \
+                    read from 'badmin showconf mbd', falling back to LSF's default of 1000."
+                )
+                .value_parser(clap::value_parser!(u32).range(1..))
+        )
+        .arg(
+            Arg::new("priority")
+                .long("priority")
+                .value_name("N")
+                .help("Job priority among your own jobs, passed to bsub as -sp N (1 to MAX_USER_PRIORITY)")
+                .value_parser(clap::value_parser!(u32).range(1..))
+        )
+        .arg(
+            Arg::new("reservation")
+                .long("reservation")
+                .value_name("ID")
+                .help("Run the tasks on the hosts of advance reservation ID, passed to bsub as -U ID")
+        )
+        .arg(
+            Arg::new("group")
+                .long("group")
+                .value_name("PATH")
+                .help("LSF job group to submit to, passed to bsub as -g PATH (e.g. /myproject)")
+                .long_help(
+                    "Submit the array into an LSF job group, passed to bsub as -g PATH, so it is \
+                    accounted to the right project for fair share and the group's own limits on \
+                    running jobs apply."
+                )
+        )
+        .arg(
+            Arg::new("after_array")
+                .long("after-array")
+                .value_name("JOB_ID")
+                .help("Start each task only once the same index of array JOB_ID has finished successfully")
+                .long_help(
+                    "Make task N of this array wait for task N of the array JOB_ID to finish \
+                    successfully, passed to bsub as -w \"done(JOB_ID[*])\". Use it to chain \
+                    index-aligned steps, e.g. an align array followed by a sort array over the same \
+                    samples. Both arrays should have the same size and order, so it is refused \
+                    with anything that renumbers the tasks: --chunk, dropped duplicate or empty \
+                    commands, or a submission split into several arrays."
+                )
+                .value_parser(clap::value_parser!(u64))
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("N")
+                .help("Derive generated names and timestamps from N instead of the clock")
+                .long_help(
+                    "Make every generated artifact reproducible: the timestamp in command log, \
+                    manifest and script names, and in emitted script and log headers, is fixed at N \
+                    seconds after the Unix epoch instead of the current time. Two runs with the same \
+                    seed and input write identical files, which suits snapshot tests and audits."
+                )
+                .value_parser(clap::value_parser!(u32))
+        )
+        .arg(
+            Arg::new("no_skip_empty")
+                .long("no-skip-empty")
+                .help("Submit commands that render to nothing but whitespace instead of dropping them")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("no_strict_bash")
+                .long("no-strict-bash")
+                .help("Don't add 'set -euo pipefail' to the generated task script; commands are still eval'd")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("max_pending")
+                .long("max-pending")
+                .value_name("N")
+                .help("Refuse to submit if your pending jobs plus this array would exceed N")
+                .value_parser(clap::value_parser!(usize))
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry-run")
+                .help("Print the jobs, batch size, bsub command and task script without submitting")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("parallel_submit")
+                .long("parallel-submit")
+                .help("When a submission is split into several arrays, submit them in parallel")
+                .long_help(
+                    "When a submission is split into several arrays (e.g. by --mem-per-gb-input or \
+                    --memory-column), make their bsub calls concurrently, up to 4 at a time, rather \
+                    than one after another. Output is still printed in array order. Arrays that \
+                    were accepted are recorded even if others fail, and the failures are listed."
+                )
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("log_header")
+                .long("log-header")
+                .help("Start the command log with comment lines giving the job ID, time, queue and template")
+                .long_help(
+                    "Begin the command log with '# arrayify' comment lines recording the job ID, \
+                    submission time, queue and command template, so the log explains itself when \
+                    opened later. Tasks skip these lines when picking their command, and check \
+                    ignores them when looking up failed commands."
+                )
+                .action(ArgAction::SetTrue)
+                .conflicts_with("jobs_file")
+        )
+        .arg(
+            Arg::new("no_log")
+                .long("no-log")
+                .help("Embed the commands in the task script instead of writing a command log")
+                .long_help(
+                    "Don't persist the rendered commands, e.g. when they contain sensitive paths or \
+                    tokens. The commands are embedded in the script handed to bsub instead of being \
+                    written to a command log, so check and dump-failed can't show which command \
+                    failed and run-until-done can't retry. The commands still reach the scheduler, \
+                    which may keep its own copy of the script."
+                )
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["jobs_file", "emit_script", "keep_script"])
+        )
+        .arg(
+            Arg::new("diff_against")
+                .long("diff-against")
+                .value_name("PREV_LOG")
+                .help("With --dry-run, show a diff of the commands against a previous command log")
+                .long_help(
+                    "With --dry-run, print a unified diff between the commands in a previous run's \
+                    command log and the newly rendered ones instead of listing them all, to catch \
+                    unintended template changes."
+                )
+                .requires("dry_run")
+        )
+        .arg(
+            Arg::new("stagger")
+                .long("stagger")
+                .value_name("SECS")
+                .help("Delay each task's start by SECS times its index (capped at 10 minutes)")
+                .value_parser(clap::value_parser!(u64))
+        )
+        .arg(
+            Arg::new("summary_file")
+                .long("summary-file")
+                .value_name("PATH")
+                .help("Also write the run statistics printed after submission to PATH as JSON")
+                .long_help(
+                    "Write the job ID, submission time, job count, concurrency and log locations \
+                    printed after submission to PATH as JSON, for scripts to pick up. When the \
+                    submission is split into several arrays, each gets its own file with the \
+                    array's suffix added (e.g. summary-mem8G.json)."
+                )
+        )
+        .arg(
+            Arg::new("task_retries")
+                .long("task-retries")
+                .value_name("N")
+                .help("Rerun a failed command up to N more times within its task")
+                .long_help(
+                    "Wrap each command in a retry loop so that a failure reruns it in place, up to N \
+                    more times, before the task gives up with the last attempt's exit code. Meant for \
+                    flaky network-dependent steps; no resubmission to the scheduler is involved."
+                )
+                .default_value("0")
+                .value_parser(clap::value_parser!(u32))
+        )
+        .arg(
+            Arg::new("retry_sleep")
+                .long("retry-sleep")
+                .value_name("SECS")
+                .help("Seconds to wait between --task-retries attempts")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64))
+        )
+        .arg(
+            Arg::new("emit_script")
+                .long("emit-script")
+                .value_name("SCRIPT")
+                .help("Write a self-contained script that reproduces the submission instead of submitting")
+        )
+        .arg(
+            Arg::new("exclude_host")
+                .long("exclude-host")
+                .value_name("HOST")
+                .help("Never run tasks on HOST (repeatable)")
+                .action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("resource_select")
+                .long("resource-select")
+                .value_name("EXPR")
+                .help("Extra select[] term combined with the memory selection, e.g. 'avx512'")
+                .long_help(
+                    "Add a term to the select[...] part of the resource string, e.g. 'avx512' to \
+                    run only on nodes with AVX-512. It is combined with the memory requirement \
+                    and any --exclude-host entries using &&. 'select[...]' around the term is optional."
+                )
+                .conflicts_with("rusage")
+        )
+        .arg(
+            Arg::new("exit_codes")
+                .long("exit-codes")
+                .help("Have each task write its command's exit code to <LOG_DIR>/job_<ID>_<INDEX>.code")
+                .long_help(
+                    "Have each task write its command's exit code to <LOG_DIR>/job_<ID>_<INDEX>.code, \
+                    next to its .out and .err files, as a record independent of LSF's accounting. \
+                    Read them back with 'arrayify collect <JOB_ID> --stream code'. With --chunk, \
+                    the code is that of the last failing command in the task, or 0."
+                )
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("interpreter")
+                .long("interpreter")
+                .value_name("PROGRAM")
+                .help("Run each command with PROGRAM instead of bash, e.g. 'python3' or '/usr/bin/env Rscript'")
+                .long_help(
+                    "Run each command as a script for PROGRAM instead of evaluating it with bash, \
+                    e.g. --interpreter python3 for Python one-liners. The task script itself stays \
+                    bash; it hands the task's command to PROGRAM as a script file. Can't be combined \
+                    with --task-log or --shellcheck, which assume bash commands."
+                )
+                .conflicts_with_all(["task_log", "shellcheck"])
+        )
+        .arg(
+            Arg::new("shellcheck")
+                .long("shellcheck")
+                .help("Lint the first rendered command with shellcheck before submitting")
+                .long_help(
+                    "Render the first job and run it through shellcheck (if installed) before \
+                    submitting. Warnings are reported; errors abort the submission unless --force \
+                    is given. If shellcheck is not on PATH a warning is printed and submission continues."
+                )
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Submit even if pre-submission checks report errors")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("mem_per_gb_input")
+                .long("mem-per-gb-input")
+                .value_name("MB")
+                .help("With --dir/--glob, request MB of memory per GB of R1+R2 input (never below --memory)")
+                .long_help(
+                    "Scale memory by input size in directory or glob mode. Each task requests MB of \
+                    memory for every GB of its combined R1+R2 file size, rounded up to a whole GB and \
+                    never below --memory. Tasks with the same memory are submitted together, one array \
+                    per memory tier. Leave unset to use --memory for every task."
+                )
+                .value_parser(clap::value_parser!(u32))
+        )
+        .arg(
+            Arg::new("memory_column")
+                .long("memory-column")
+                .value_name("COLUMN")
+                .help("With --csv, take each row's memory in GB from COLUMN")
+                .conflicts_with("mem_per_gb_input")
+        )
+        .arg(
+            Arg::new("threads_column")
+                .long("threads-column")
+                .value_name("COLUMN")
+                .help("With --csv, take each row's thread count from COLUMN")
+        )
+}
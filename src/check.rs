@@ -0,0 +1,1030 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// A task that finished with a non-zero exit code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedJob {
+    pub array_name: String,
+    pub exit_code: String,
+    pub reason: String,
+    pub host: String,
+}
+
+/// Task counts for one array, parsed from `bjobs` output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArrayStatus {
+    pub running: usize,
+    pub pending: usize,
+    pub done: usize,
+    pub other: usize,
+    pub failed: Vec<FailedJob>,
+}
+
+impl ArrayStatus {
+    /// Number of array elements the scheduler reported.
+    pub fn total(&self) -> usize {
+        self.running + self.pending + self.done + self.other + self.failed.len()
+    }
+
+    pub fn all_done(&self) -> bool {
+        self.running == 0 && self.pending == 0 && self.other == 0 && self.failed.is_empty()
+    }
+
+    /// Whether every task has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        self.total() > 0 && self.running == 0 && self.pending == 0 && self.other == 0
+    }
+}
+
+/// Commands run once an array finishes, with `{job_id}` and `{failed_count}`
+/// substituted.
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub on_success: Option<String>,
+    pub on_failure: Option<String>,
+}
+
+impl Hooks {
+    /// Runs the hook matching a finished array's outcome, if one was given.
+    pub fn fire(&self, job_id: &str, status: &ArrayStatus) -> io::Result<()> {
+        let hook = if status.failed.is_empty() {
+            &self.on_success
+        } else {
+            &self.on_failure
+        };
+        let Some(hook) = hook else {
+            return Ok(());
+        };
+        let command = hook
+            .replace("{job_id}", job_id)
+            .replace("{failed_count}", &status.failed.len().to_string());
+        let exit_status = Command::new("bash").arg("-c").arg(&command).status()?;
+        if !exit_status.success() {
+            eoutln!("⚠️ Hook '{}' exited with {}", command, exit_status);
+        }
+        Ok(())
+    }
+}
+
+/// Built-in exit code reasons, in the same format as `exit_codes.toml`.
+const DEFAULT_EXIT_REASONS: &str = r#"
+2 = "Killed 💀"
+130 = "Memory error 🧠💾"
+137 = "Killed (OOM) 💀🛑💾"
+143 = "Timeout ⏳"
+"#;
+
+const UNKNOWN_REASON: &str = "Unknown error ❓🚨";
+
+/// Maps exit codes to the reasons `check` shows, from the built-in table
+/// extended by an optional user file of `CODE = "reason"` lines.
+#[derive(Debug, Clone)]
+pub struct ExitReasons(HashMap<String, String>);
+
+impl Default for ExitReasons {
+    fn default() -> Self {
+        ExitReasons(toml::from_str(DEFAULT_EXIT_REASONS).unwrap())
+    }
+}
+
+impl ExitReasons {
+    /// The built-in reasons, with those in `path` (if it exists) added or
+    /// taking precedence.
+    pub fn load(path: &Path) -> io::Result<ExitReasons> {
+        let mut reasons = ExitReasons::default();
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(reasons),
+            Err(e) => return Err(e),
+        };
+        let custom: HashMap<String, String> = toml::from_str(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+        reasons.0.extend(custom);
+        Ok(reasons)
+    }
+
+    pub fn reason(&self, exit_code: &str) -> &str {
+        self.0.get(exit_code).map_or(UNKNOWN_REASON, String::as_str)
+    }
+}
+
+static EXIT_REASONS: OnceLock<ExitReasons> = OnceLock::new();
+
+/// Sets the reasons used for the rest of the run; the built-in table is used
+/// if this is never called.
+pub fn set_exit_reasons(reasons: ExitReasons) {
+    let _ = EXIT_REASONS.set(reasons);
+}
+
+fn exit_reason(exit_code: &str) -> &'static str {
+    EXIT_REASONS
+        .get_or_init(ExitReasons::default)
+        .reason(exit_code)
+}
+
+/// Whether a task's exit code means it ran out of memory.
+pub fn is_memory_failure(exit_code: &str) -> bool {
+    matches!(exit_code, "130" | "137")
+}
+
+const BJOBS_FIELDS: &str = "job_name stat exit_code exec_host";
+
+/// Queries the array's tasks, as JSON where LSF supports `bjobs -json` and
+/// as whitespace-separated columns otherwise.
+pub fn query_bjobs(job_id: &str) -> io::Result<String> {
+    let json = Command::new("bjobs")
+        .args(["-json", "-o", BJOBS_FIELDS, job_id])
+        .output()?;
+    let json = String::from_utf8_lossy(&json.stdout).into_owned();
+    if json.trim_start().starts_with('{') {
+        return Ok(json);
+    }
+
+    let output = Command::new("bjobs")
+        .args(["-noheader", "-o", BJOBS_FIELDS, job_id])
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// One task from `bjobs -json` output.
+#[derive(Debug, Deserialize)]
+struct BjobsRecord {
+    #[serde(rename = "JOB_NAME", default)]
+    job_name: String,
+    #[serde(rename = "STAT", default)]
+    stat: String,
+    #[serde(rename = "EXIT_CODE", default)]
+    exit_code: String,
+    #[serde(rename = "EXEC_HOST", default)]
+    exec_host: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BjobsJson {
+    #[serde(rename = "RECORDS", default)]
+    records: Vec<BjobsRecord>,
+}
+
+impl ArrayStatus {
+    fn record(&mut self, array_name: &str, stat: &str, exit_code: &str, host: &str) {
+        // Multi-slot hosts are reported as e.g. "4*node01", several as "node01:node02"
+        let host = host.split(':').next().unwrap_or(host);
+        let host = host.rsplit('*').next().unwrap_or(host);
+        let host = if host.is_empty() { "-" } else { host };
+
+        match stat {
+            "EXIT" => self.failed.push(FailedJob {
+                array_name: array_name.to_string(),
+                exit_code: exit_code.to_string(),
+                reason: exit_reason(exit_code).to_string(),
+                host: host.to_string(),
+            }),
+            "RUN" => self.running += 1,
+            "PEND" => self.pending += 1,
+            "DONE" => self.done += 1,
+            _ => self.other += 1,
+        }
+    }
+}
+
+/// Parses `bjobs` output for the fields in `BJOBS_FIELDS`, either the JSON
+/// from `-json` or the columns from `-noheader`.
+pub fn parse_bjobs(output: &str) -> ArrayStatus {
+    let mut status = ArrayStatus::default();
+    let json = if output.trim_start().starts_with('{') {
+        serde_json::from_str::<BjobsJson>(output).ok()
+    } else {
+        None
+    };
+    if let Some(json) = json {
+        for task in json.records {
+            status.record(&task.job_name, &task.stat, &task.exit_code, &task.exec_host);
+        }
+        return status;
+    }
+
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() >= 3 {
+            let host = parts.get(3).copied().unwrap_or("-");
+            status.record(parts[0], parts[1], parts[2], host);
+        }
+    }
+    status
+}
+
+/// Failed tasks sharing an exit code, for spotting systematic failures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureGroup {
+    pub exit_code: String,
+    pub reason: String,
+    /// Array indices of the affected tasks, in ascending order
+    pub indices: Vec<usize>,
+    pub hosts: Vec<String>,
+}
+
+/// Extracts the array index from a job name such as `arrayify_job_array[12]`.
+pub fn array_index(array_name: &str) -> Option<usize> {
+    let (_, index) = array_name.strip_suffix(']')?.rsplit_once('[')?;
+    index.parse().ok()
+}
+
+/// Groups failed tasks by exit code, largest group first.
+pub fn group_failures(failed: &[FailedJob]) -> Vec<FailureGroup> {
+    let mut groups: BTreeMap<&str, FailureGroup> = BTreeMap::new();
+    for job in failed {
+        let group = groups
+            .entry(job.exit_code.as_str())
+            .or_insert_with(|| FailureGroup {
+                exit_code: job.exit_code.clone(),
+                reason: job.reason.clone(),
+                indices: Vec::new(),
+                hosts: Vec::new(),
+            });
+        if let Some(index) = array_index(&job.array_name) {
+            group.indices.push(index);
+        }
+        if !group.hosts.contains(&job.host) {
+            group.hosts.push(job.host.clone());
+        }
+    }
+
+    let mut groups: Vec<FailureGroup> = groups.into_values().collect();
+    for group in &mut groups {
+        group.indices.sort_unstable();
+        group.hosts.sort();
+    }
+    groups.sort_by_key(|group| std::cmp::Reverse(group.indices.len()));
+    groups
+}
+
+pub fn query_bacct(job_id: &str) -> io::Result<String> {
+    let output = Command::new("bacct").args(["-l", job_id]).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parses the final outcome of each task from `bacct -l` output. Each task
+/// has a block starting `Job <ID[N]>, Job Name <...>` with its `Status <...>`,
+/// an `exit code <N>` if it failed and the host it ran on.
+pub fn parse_bacct(output: &str) -> ArrayStatus {
+    // LSF wraps long lines, indenting the continuation by 21 spaces
+    let mut unwrapped = String::new();
+    for line in output.lines() {
+        match line.strip_prefix(&" ".repeat(21)) {
+            Some(rest) => unwrapped.push_str(rest),
+            None => {
+                unwrapped.push('\n');
+                unwrapped.push_str(line);
+            }
+        }
+    }
+
+    let field = |pattern: &str| Regex::new(pattern).unwrap();
+    let name = field(r"Job Name <([^>]*)>");
+    let stat = field(r"Status <(\w+)>");
+    let exit_code = field(r"exit code <(\d+)>");
+    let host = field(r"(?:on Host\(s\)|Dispatched to) <([^>]*)>");
+
+    let mut status = ArrayStatus::default();
+    for block in unwrapped.split("\nJob <").skip(1) {
+        let capture = |pattern: &Regex| pattern.captures(block).map(|caps| caps[1].to_string());
+        let (Some(name), Some(stat)) = (capture(&name), capture(&stat)) else {
+            continue;
+        };
+        let exit_code = capture(&exit_code).unwrap_or_else(|| "-".to_string());
+        let host = capture(&host).unwrap_or_else(|| "-".to_string());
+        status.record(&name, &stat, &exit_code, &host);
+    }
+    status
+}
+
+/// Queries the array with `bjobs`, falling back to `bacct` accounting once
+/// LSF has purged a finished array from `bjobs`. The flag says whether the
+/// status came from accounting.
+pub fn query_status(
+    job_id: &str,
+    bjobs: impl Fn(&str) -> io::Result<String>,
+    bacct: impl Fn(&str) -> io::Result<String>,
+) -> io::Result<(ArrayStatus, bool)> {
+    let status = parse_bjobs(&bjobs(job_id)?);
+    if status.total() > 0 {
+        return Ok((status, false));
+    }
+    let accounted = parse_bacct(&bacct(job_id)?);
+    let from_accounting = accounted.total() > 0;
+    Ok((accounted, from_accounting))
+}
+
+pub fn check_jobs(job_id: &str, hooks: &Hooks) -> ArrayStatus {
+    let (status, from_accounting) =
+        query_status(job_id, query_bjobs, query_bacct).expect("Failed to check job status");
+    if from_accounting {
+        outln!(
+            "📚 Array {} is no longer known to bjobs; showing final outcomes from bacct",
+            job_id
+        );
+    }
+    print_status(job_id, &status);
+
+    let fired = if status.is_finished() {
+        hooks.fire(job_id, &status)
+    } else {
+        Ok(())
+    };
+    if let Err(e) = fired {
+        eoutln!("⚠️ Could not run hook: {}", e);
+    }
+    status
+}
+
+/// Polls the array with `query` every `interval` until all tasks have finished,
+/// then reports the outcome and fires the matching hook.
+pub fn watch_jobs(
+    job_id: &str,
+    interval: Duration,
+    hooks: &Hooks,
+    bjobs: impl Fn(&str) -> io::Result<String>,
+    bacct: impl Fn(&str) -> io::Result<String>,
+) -> io::Result<ArrayStatus> {
+    let status = loop {
+        let (status, from_accounting) = query_status(job_id, &bjobs, &bacct)?;
+        if status.is_finished() {
+            if from_accounting {
+                outln!(
+                    "📚 Array {} is no longer known to bjobs; showing final outcomes from bacct",
+                    job_id
+                );
+            }
+            break status;
+        }
+        outln!(
+            "👀 {} running, {} pending, {} done, {} failed",
+            status.running,
+            status.pending,
+            status.done,
+            status.failed.len()
+        );
+        thread::sleep(interval);
+    };
+
+    print_status(job_id, &status);
+    hooks.fire(job_id, &status)?;
+    Ok(status)
+}
+
+/// Looks up the commands of the failed tasks in the array's command log, where
+/// array index N ran lines `(N-1)*chunk+1` to `N*chunk`. Returns them in order.
+pub fn failed_commands(status: &ArrayStatus, log_content: &str, chunk: usize) -> Vec<String> {
+    let lines: Vec<&str> = log_content
+        .lines()
+        .skip_while(|line| line.starts_with(crate::submission::LOG_HEADER_PREFIX))
+        .collect();
+    let chunk = chunk.max(1);
+    let mut indices: Vec<usize> = status
+        .failed
+        .iter()
+        .filter_map(|job| array_index(&job.array_name))
+        .filter(|&index| index > 0)
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+        .into_iter()
+        .flat_map(|index| lines.iter().skip((index - 1) * chunk).take(chunk))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Writes the failed tasks' commands, one per line, ready to rerun by hand.
+/// Returns how many were written.
+pub fn dump_failed(
+    status: &ArrayStatus,
+    log_file: &str,
+    chunk: usize,
+    dump_path: &str,
+) -> io::Result<usize> {
+    let commands = failed_commands(status, &std::fs::read_to_string(log_file)?, chunk);
+    let mut content = commands.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    std::fs::write(dump_path, content)?;
+    Ok(commands.len())
+}
+
+/// Peak memory and run time of one finished task.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskUsage {
+    pub max_mem_mb: f64,
+    pub run_time_secs: u64,
+}
+
+pub fn query_usage(job_id: &str) -> io::Result<String> {
+    let output = Command::new("bjobs")
+        .args(["-noheader", "-o", "max_mem run_time delimiter='|'", job_id])
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Converts an LSF memory figure such as `3.2 Gbytes` or `512 Mbytes` to MB.
+fn parse_memory_mb(value: &str) -> Option<f64> {
+    let mut parts = value.split_whitespace();
+    let amount: f64 = parts.next()?.parse().ok()?;
+    let scale = match parts.next().unwrap_or("Mbytes") {
+        "Kbytes" => 1.0 / 1000.0,
+        "Mbytes" => 1.0,
+        "Gbytes" => 1000.0,
+        "Tbytes" => 1_000_000.0,
+        _ => return None,
+    };
+    Some(amount * scale)
+}
+
+/// Parses `bjobs -o "max_mem run_time delimiter='|'"` output, e.g.
+/// `3.2 Gbytes|1234 second(s)`. Tasks without figures yet are skipped.
+pub fn parse_usage(output: &str) -> Vec<TaskUsage> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (max_mem, run_time) = line.split_once('|')?;
+            Some(TaskUsage {
+                max_mem_mb: parse_memory_mb(max_mem)?,
+                run_time_secs: run_time.split_whitespace().next()?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of an ascending slice.
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Summarises memory and run time across tasks, comparing memory with what was
+/// requested when it is known.
+pub fn format_usage_report(usage: &[TaskUsage], requested_gb: Option<u32>) -> String {
+    if usage.is_empty() {
+        return "No usage figures reported yet\n".to_string();
+    }
+    let mut memory: Vec<f64> = usage.iter().map(|task| task.max_mem_mb / 1000.0).collect();
+    memory.sort_by(f64::total_cmp);
+    let mut minutes: Vec<f64> = usage
+        .iter()
+        .map(|task| task.run_time_secs as f64 / 60.0)
+        .collect();
+    minutes.sort_by(f64::total_cmp);
+
+    let mut report = format!("📊 Usage across {} tasks\n", usage.len());
+    report.push_str(&format!(
+        "🧠 Memory (GB):    median {:.1}, 90th percentile {:.1}, max {:.1}\n",
+        percentile(&memory, 50.0),
+        percentile(&memory, 90.0),
+        percentile(&memory, 100.0)
+    ));
+    report.push_str(&format!(
+        "⏱️ Run time (min): median {:.1}, 90th percentile {:.1}, max {:.1}\n",
+        percentile(&minutes, 50.0),
+        percentile(&minutes, 90.0),
+        percentile(&minutes, 100.0)
+    ));
+    if let Some(requested_gb) = requested_gb {
+        report.push_str(&format!(
+            "💡 90th percentile memory was {:.1}GB; you requested {}GB\n",
+            percentile(&memory, 90.0),
+            requested_gb
+        ));
+    }
+    report
+}
+
+/// One line per array plus a grand total, for checking several arrays at once.
+pub fn summarise_arrays(
+    job_ids: &[String],
+    query: impl Fn(&str) -> io::Result<String>,
+) -> io::Result<String> {
+    let row = |name: &str, status: &ArrayStatus| {
+        format!(
+            "{:<12} {:>8} {:>8} {:>8} {:>8}\n",
+            name,
+            status.running,
+            status.pending,
+            status.done,
+            status.failed.len()
+        )
+    };
+
+    let mut summary = format!(
+        "{:<12} {:>8} {:>8} {:>8} {:>8}\n",
+        "JOB_ID", "RUNNING", "PENDING", "DONE", "FAILED"
+    );
+    let mut total = ArrayStatus::default();
+    for job_id in job_ids {
+        let status = parse_bjobs(&query(job_id)?);
+        summary.push_str(&row(job_id, &status));
+        total.running += status.running;
+        total.pending += status.pending;
+        total.done += status.done;
+        total.other += status.other;
+        total.failed.extend(status.failed);
+    }
+    summary.push_str(&row("TOTAL", &total));
+    Ok(summary)
+}
+
+pub fn print_status(job_id: &str, status: &ArrayStatus) {
+    out!("{}", status_report(job_id, status));
+}
+
+/// The lines `print_status` shows for `status`, each ending in a newline.
+fn status_report(job_id: &str, status: &ArrayStatus) -> String {
+    let mut report = String::new();
+    if status.all_done() {
+        report += &format!("✅ All jobs in array {} completed successfully!\n", job_id);
+        return report;
+    }
+    if status.running > 0 {
+        report += &format!("🚀 {} jobs are currently running!\n", status.running);
+    }
+    if status.pending > 0 {
+        report += &format!("⏳ {} jobs are still pending!\n", status.pending);
+    }
+    if status.done > 0 {
+        report += &format!("✅ {} jobs have completed successfully!\n", status.done);
+    }
+    if !status.failed.is_empty() {
+        report += &format!(
+            "❌ {} jobs in array {} had issues:\n",
+            status.failed.len(),
+            job_id
+        );
+        for group in group_failures(&status.failed) {
+            let indices: Vec<String> = group.indices.iter().map(usize::to_string).collect();
+            report += &format!(
+                "  - Exit Code {}: {} x{} (indices {}; hosts {})\n",
+                group.exit_code,
+                group.reason,
+                group.indices.len(),
+                indices.join(","),
+                group.hosts.join(",")
+            );
+        }
+        report += &format!(
+            "💡 If a host looks bad, rerun avoiding it with -\n   arrayify rerun {} --exclude-host <HOST>\n",
+            job_id
+        );
+    }
+    report
+}
+
+pub fn query_pending_reasons(job_id: &str) -> io::Result<String> {
+    let output = Command::new("bjobs").args(["-p", job_id]).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Counts the pending reasons in `bjobs -p` output, most common first. Each
+/// pending task's row is followed by indented reason lines such as
+/// ` Not enough job slot(s): 3 hosts;`; the host counts are dropped so the
+/// same reason groups across tasks.
+pub fn parse_pending_reasons(output: &str) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let reason = line.trim().trim_end_matches(';');
+        let reason = match reason.rsplit_once(": ") {
+            Some((text, hosts)) if hosts.ends_with("host") || hosts.ends_with("hosts") => text,
+            _ => reason,
+        };
+        if !reason.is_empty() && reason != "PENDING REASONS:" {
+            *counts.entry(reason.to_string()).or_default() += 1;
+        }
+    }
+    let mut reasons: Vec<(String, usize)> = counts.into_iter().collect();
+    reasons.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    reasons
+}
+
+/// Explains why the array's tasks are still pending, from `query`'s
+/// `bjobs -p` output.
+pub fn explain_pending(
+    job_id: &str,
+    query: impl Fn(&str) -> io::Result<String>,
+) -> io::Result<String> {
+    let reasons = parse_pending_reasons(&query(job_id)?);
+    if reasons.is_empty() {
+        return Ok(format!("🤷 LSF gave no pending reasons for {}\n", job_id));
+    }
+    let mut explanation = String::from("⏳ Why tasks are pending:\n");
+    for (reason, count) in reasons {
+        explanation.push_str(&format!("  - {} x{}\n", reason, count));
+    }
+    Ok(explanation)
+}
+
+/// Lists the arrays submitted under `name`, one `JOBID JOB_NAME` row per task.
+pub fn query_job_name(name: &str) -> io::Result<String> {
+    let output = Command::new("bjobs")
+        .args(["-noheader", "-o", "jobid job_name", "-J", name])
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Resolves a job ID or an array name given to `-J` at submission to the
+/// array's numeric ID. A name matching several arrays is an error listing
+/// their IDs, so the right one can be picked.
+pub fn resolve_job_id(
+    id_or_name: &str,
+    query: impl Fn(&str) -> io::Result<String>,
+) -> Result<String, String> {
+    if id_or_name.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(id_or_name.to_string());
+    }
+    let output =
+        query(id_or_name).map_err(|e| format!("Failed to look up array {}: {}", id_or_name, e))?;
+
+    // Each task of an array is listed under the same ID
+    let mut ids: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let Some(id) = line.split_whitespace().next() else {
+            continue;
+        };
+        if id.chars().all(|c| c.is_ascii_digit()) && !ids.iter().any(|seen| seen == id) {
+            ids.push(id.to_string());
+        }
+    }
+    match ids.as_slice() {
+        [] => Err(format!("No array named {} found", id_or_name)),
+        [id] => Ok(id.clone()),
+        _ => Err(format!(
+            "{} arrays are named {}: {}\n💡 Pass one of these IDs instead",
+            ids.len(),
+            id_or_name,
+            ids.join(", ")
+        )),
+    }
+}
+
+/// Compares the number of array elements the scheduler knows about with the
+/// number submitted, returning a warning when they differ.
+pub fn verify_element_count(bjobs_output: &str, expected: usize) -> Option<String> {
+    let found = parse_bjobs(bjobs_output).total();
+    if found == expected {
+        None
+    } else {
+        Some(format!(
+            "⚠️ Expected {} array elements but bjobs reports {}; the submission may be incomplete",
+            expected, found
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_exit_reasons() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exit_codes.toml");
+        std::fs::write(
+            &path,
+            "99 = \"License error\"\n143 = \"Over the site time limit\"\n",
+        )
+        .unwrap();
+
+        let reasons = ExitReasons::load(&path).unwrap();
+        assert_eq!(reasons.reason("99"), "License error");
+        assert_eq!(reasons.reason("143"), "Over the site time limit");
+        assert_eq!(reasons.reason("137"), "Killed (OOM) 💀🛑💾"); // Built-in kept
+        assert_eq!(reasons.reason("5"), UNKNOWN_REASON);
+
+        // Without a file only the built-in reasons apply
+        let missing = ExitReasons::load(&dir.path().join("none.toml")).unwrap();
+        assert_eq!(missing.reason("99"), UNKNOWN_REASON);
+
+        std::fs::write(&path, "99 = [").unwrap();
+        assert!(ExitReasons::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_parse_bjobs() {
+        let output = "\
+arrayify_job_array[1] DONE - 4*node01
+arrayify_job_array[2] EXIT 137 node02
+arrayify_job_array[3] RUN - node03
+arrayify_job_array[4] PEND - -
+";
+        let status = parse_bjobs(output);
+        assert_eq!((status.done, status.running, status.pending), (1, 1, 1));
+        assert_eq!(status.failed.len(), 1);
+        assert_eq!(status.failed[0].reason, "Killed (OOM) 💀🛑💾");
+        assert_eq!(status.failed[0].host, "node02");
+        assert_eq!(status.total(), 4);
+        assert!(!status.all_done());
+    }
+
+    #[test]
+    fn test_explain_pending_reasons() {
+        let output = "\
+JOBID   USER    STAT  QUEUE      FROM_HOST   JOB_NAME   SUBMIT_TIME
+4242    sam     PEND  normal     login1      arrayify_job_array[1] Oct 16 10:00
+ Not enough job slot(s): 12 hosts;
+ Job's requirements for reserving resource (mem) not satisfied: 3 hosts;
+4242    sam     PEND  normal     login1      arrayify_job_array[2] Oct 16 10:00
+ Not enough job slot(s): 9 hosts;
+4242    sam     PEND  normal     login1      arrayify_job_array[3] Oct 16 10:00
+ The user has reached his/her job slot limit;
+";
+        let explanation = explain_pending("4242", |_| Ok(output.to_string())).unwrap();
+        assert_eq!(
+            explanation,
+            "⏳ Why tasks are pending:
+  - Not enough job slot(s) x2
+  - Job's requirements for reserving resource (mem) not satisfied x1
+  - The user has reached his/her job slot limit x1
+"
+        );
+
+        let nothing = explain_pending("4242", |_| Ok(String::new())).unwrap();
+        assert!(nothing.contains("no pending reasons"));
+    }
+
+    #[test]
+    fn test_resolve_job_id_by_name() {
+        let query = |name: &str| -> io::Result<String> {
+            Ok(match name {
+                "align_job_array" => "4242 align_job_array[1]\n4242 align_job_array[2]\n",
+                "qc" => "4300 qc[1]\n4301 qc[1]\n",
+                _ => "",
+            }
+            .to_string())
+        };
+
+        assert_eq!(resolve_job_id("4242", query).unwrap(), "4242");
+        assert_eq!(resolve_job_id("align_job_array", query).unwrap(), "4242");
+        let ambiguous = resolve_job_id("qc", query).unwrap_err();
+        assert!(ambiguous.contains("4300, 4301"));
+        assert!(resolve_job_id("missing", query).is_err());
+    }
+
+    #[test]
+    fn test_purged_array_falls_back_to_bacct() {
+        let bacct = "
+Accounting information about jobs that are:
+  - submitted by all users.
+------------------------------------------------------------------------------
+
+Job <4242[1]>, Job Name <arrayify_job_array[1]>, User <sam>, Project <default>, S
+                     tatus <DONE>, Queue <normal>, Command <bash>
+Thu Oct 16 10:00:00: Submitted from host <login1>, CWD <$HOME>;
+Thu Oct 16 10:00:05: Dispatched 1 Task(s) on Host(s) <node01>, Allocated 1 Slot(s)
+                     on Host(s) <node01>;
+Thu Oct 16 10:05:00: Completed <done>.
+------------------------------------------------------------------------------
+
+Job <4242[2]>, Job Name <arrayify_job_array[2]>, User <sam>, Project <default>, S
+                     tatus <EXIT>, Queue <normal>, Command <bash>
+Thu Oct 16 10:00:00: Submitted from host <login1>, CWD <$HOME>;
+Thu Oct 16 10:00:05: Dispatched 1 Task(s) on Host(s) <node02>, Allocated 1 Slot(s)
+                     on Host(s) <node02>;
+Thu Oct 16 10:09:00: Completed <exit>; TERM_MEMLIMIT: job killed after reaching LS
+                     F memory usage limit; exit code <137>.
+";
+        let (status, from_accounting) =
+            query_status("4242", |_| Ok(String::new()), |_| Ok(bacct.to_string())).unwrap();
+        assert!(from_accounting);
+        assert_eq!(status.done, 1);
+        assert_eq!(status.failed.len(), 1);
+        assert_eq!(status.failed[0].array_name, "arrayify_job_array[2]");
+        assert_eq!(status.failed[0].exit_code, "137");
+        assert_eq!(status.failed[0].host, "node02");
+        assert!(status.is_finished());
+
+        let (status, from_accounting) = query_status(
+            "4242",
+            |_| Ok("arrayify_job_array[1] RUN - node01\n".to_string()),
+            |_| panic!("bacct is only needed once bjobs has nothing"),
+        )
+        .unwrap();
+        assert!(!from_accounting);
+        assert_eq!(status.running, 1);
+    }
+
+    #[test]
+    fn test_verify_element_count_warns_on_mismatch() {
+        let output = "arrayify_job_array[1] PEND - -\narrayify_job_array[2] PEND - -\n";
+        assert!(verify_element_count(output, 2).is_none());
+
+        let warning = verify_element_count(output, 3).unwrap();
+        assert!(warning.contains("Expected 3 array elements but bjobs reports 2"));
+    }
+
+    #[test]
+    fn test_group_failures_by_exit_code() {
+        let output = "\
+arrayify_job_array[1] EXIT 137 node01
+arrayify_job_array[2] DONE - node01
+arrayify_job_array[7] EXIT 137 node02
+arrayify_job_array[4] EXIT 137 node01
+arrayify_job_array[5] EXIT 1 node03
+";
+        let groups = group_failures(&parse_bjobs(output).failed);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].exit_code, "137");
+        assert_eq!(groups[0].reason, "Killed (OOM) 💀🛑💾");
+        assert_eq!(groups[0].indices, vec![1, 4, 7]);
+        assert_eq!(groups[0].hosts, vec!["node01", "node02"]);
+        assert_eq!(groups[1].exit_code, "1");
+        assert_eq!(groups[1].indices, vec![5]);
+    }
+
+    #[test]
+    fn test_watch_fires_success_hook() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let marker = work_dir.path().join("hook");
+        let hooks = Hooks {
+            on_success: Some(format!(
+                "echo {{job_id}} {{failed_count}} > {}",
+                marker.display()
+            )),
+            on_failure: Some(format!("echo failed > {}", marker.display())),
+        };
+
+        // Pending on the first poll, complete on the second
+        let polls = std::cell::Cell::new(0);
+        let query = |_: &str| {
+            polls.set(polls.get() + 1);
+            Ok(if polls.get() == 1 {
+                "arrayify_job_array[1] PEND - -\n".to_string()
+            } else {
+                "arrayify_job_array[1] DONE - node01\n".to_string()
+            })
+        };
+
+        let no_bacct = |_: &str| -> io::Result<String> { panic!("bjobs still knows the array") };
+        let status = watch_jobs("12345", Duration::ZERO, &hooks, query, no_bacct).unwrap();
+        assert!(status.all_done());
+        assert_eq!(polls.get(), 2);
+        assert_eq!(std::fs::read_to_string(&marker).unwrap(), "12345 0\n");
+    }
+
+    #[test]
+    fn test_summarise_several_arrays() {
+        let query = |job_id: &str| {
+            Ok(match job_id {
+                "100" => "a[1] DONE - n1\na[2] EXIT 137 n2\n".to_string(),
+                _ => "b[1] RUN - n1\nb[2] PEND - -\nb[3] DONE - n3\n".to_string(),
+            })
+        };
+        let summary = summarise_arrays(&["100".to_string(), "200".to_string()], query).unwrap();
+        let lines: Vec<Vec<&str>> = summary
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+
+        assert_eq!(lines[1], vec!["100", "0", "0", "1", "1"]);
+        assert_eq!(lines[2], vec!["200", "1", "1", "1", "0"]);
+        assert_eq!(lines[3], vec!["TOTAL", "1", "1", "2", "1"]);
+    }
+
+    #[test]
+    fn test_dump_failed_commands() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let log_file = work_dir.path().join("arrayify-test.log");
+        std::fs::write(&log_file, "echo one\necho two\necho three\necho four\n").unwrap();
+        let status = parse_bjobs(
+            "\
+arrayify_job_array[4] EXIT 1 node01
+arrayify_job_array[1] DONE - node01
+arrayify_job_array[2] EXIT 137 node02
+",
+        );
+
+        let dump_path = work_dir.path().join("failed.txt");
+        let dumped = dump_failed(
+            &status,
+            log_file.to_str().unwrap(),
+            1,
+            dump_path.to_str().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(dumped, 2);
+        assert_eq!(
+            std::fs::read_to_string(&dump_path).unwrap(),
+            "echo two\necho four\n"
+        );
+
+        // With --chunk 2, task 2 ran lines 3 and 4
+        let log = std::fs::read_to_string(&log_file).unwrap();
+        assert_eq!(
+            failed_commands(&parse_bjobs("a[2] EXIT 1 n1\n"), &log, 2),
+            vec!["echo three", "echo four"]
+        );
+    }
+
+    #[test]
+    fn test_parse_bjobs_json() {
+        let output = r#"{
+  "COMMAND":"bjobs",
+  "JOBS":4,
+  "RECORDS":[
+    {"JOB_NAME":"my sample job[1]","STAT":"DONE","EXIT_CODE":"","EXEC_HOST":"4*node01"},
+    {"JOB_NAME":"my sample job[2]","STAT":"EXIT","EXIT_CODE":"137","EXEC_HOST":"node02:node03"},
+    {"JOB_NAME":"my sample job[3]","STAT":"RUN","EXIT_CODE":"","EXEC_HOST":"node03"},
+    {"JOB_NAME":"my sample job[4]","STAT":"PEND","EXIT_CODE":"","EXEC_HOST":""}
+  ]
+}"#;
+        let status = parse_bjobs(output);
+        assert_eq!((status.done, status.running, status.pending), (1, 1, 1));
+        assert_eq!(status.failed.len(), 1);
+        assert_eq!(status.failed[0].array_name, "my sample job[2]");
+        assert_eq!(status.failed[0].reason, "Killed (OOM) 💀🛑💾");
+        assert_eq!(status.failed[0].host, "node02");
+    }
+
+    #[test]
+    fn test_usage_percentiles() {
+        let mut output = String::new();
+        for i in 1..=10 {
+            output.push_str(&format!("{}00 Mbytes|{} second(s)\n", i, i * 60));
+        }
+        output.push_str("-|-\n3.2 Gbytes|600 second(s)\n");
+        let usage = parse_usage(&output);
+        assert_eq!(usage.len(), 11);
+        assert_eq!(usage[10].max_mem_mb, 3200.0);
+
+        let values: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(percentile(&values, 50.0), 5.0);
+        assert_eq!(percentile(&values, 90.0), 9.0);
+        assert_eq!(percentile(&values, 100.0), 10.0);
+
+        let report = format_usage_report(&usage[..10], Some(8));
+        assert!(report.contains("median 0.5, 90th percentile 0.9, max 1.0"));
+        assert!(report.contains("90th percentile memory was 0.9GB; you requested 8GB"));
+    }
+
+    #[test]
+    fn test_watch_ends_when_array_is_purged() {
+        let bacct = |_: &str| {
+            Ok(
+                "\nJob <9[1]>, Job Name <arrayify_job_array[1]>, User <sam>, Status <DONE>, \
+                Queue <normal>\n"
+                    .to_string(),
+            )
+        };
+        let status = watch_jobs(
+            "9",
+            Duration::ZERO,
+            &Hooks::default(),
+            |_| Ok(String::new()),
+            bacct,
+        )
+        .unwrap();
+        assert!(status.all_done());
+        assert_eq!(status.done, 1);
+    }
+
+    #[test]
+    fn test_status_report_without_emoji() {
+        let failed = |index: usize, host: &str| FailedJob {
+            array_name: format!("arrayify_job_array[{}]", index),
+            exit_code: "137".to_string(),
+            reason: "Killed (OOM) 💀🛑💾".to_string(),
+            host: host.to_string(),
+        };
+        let status = ArrayStatus {
+            running: 1,
+            done: 2,
+            failed: vec![failed(4, "node2"), failed(3, "node1")],
+            ..Default::default()
+        };
+
+        let report = crate::output::plain(&status_report("42", &status));
+        assert!(report.is_ascii(), "{}", report);
+        assert_eq!(
+            report,
+            "[RUN] 1 jobs are currently running!\n\
+             [OK] 2 jobs have completed successfully!\n\
+             [FAIL] 2 jobs in array 42 had issues:\n  \
+             - Exit Code 137: Killed (OOM) x2 (indices 3,4; hosts node1,node2)\n\
+             [HINT] If a host looks bad, rerun avoiding it with -\n   \
+             arrayify rerun 42 --exclude-host <HOST>\n"
+        );
+    }
+}
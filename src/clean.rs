@@ -0,0 +1,115 @@
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Names arrayify gives the files it writes to a log directory: LSF's task
+/// reports, exit code files, command logs, manifests and kept task scripts.
+const ARTIFACT_PATTERN: &str = r"^(job_\d+_\d+\.(out|err|code)|arrayify-.+\.(log|json|sh))$";
+
+/// Parses a human age such as `30d`, `2w`, `12h` or `90m`.
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value.char_indices().last().map_or(0, |(i, _)| i);
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("expected an age like 30d or 2w, got '{}'", value))?;
+    let secs = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown unit in '{}' (use m, h, d or w)", value)),
+    };
+    Ok(Duration::from_secs(amount * secs))
+}
+
+/// Lists the arrayify files in `log_dir` last modified before `cutoff`,
+/// sorted by name. Anything not named like an arrayify file is left out.
+pub fn find_old_artifacts(log_dir: &str, cutoff: SystemTime) -> io::Result<Vec<PathBuf>> {
+    let pattern = Regex::new(ARTIFACT_PATTERN).unwrap();
+    let mut old = Vec::new();
+    for entry in fs::read_dir(log_dir)? {
+        let entry = entry?;
+        let is_artifact = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| pattern.is_match(name));
+        let metadata = entry.metadata()?;
+        if is_artifact && metadata.is_file() && metadata.modified()? < cutoff {
+            old.push(entry.path());
+        }
+    }
+    old.sort();
+    Ok(old)
+}
+
+/// Removes (or with `dry_run` just lists) arrayify files in `log_dir` older
+/// than `older_than`, returning the paths affected.
+pub fn clean_logs(log_dir: &str, older_than: Duration, dry_run: bool) -> io::Result<Vec<PathBuf>> {
+    let cutoff = SystemTime::now()
+        .checked_sub(older_than)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let old = find_old_artifacts(log_dir, cutoff)?;
+    if !dry_run {
+        for path in &old {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(old)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_clean_removes_only_old_arrayify_files() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let dir = log_dir.path();
+        let forty_days_ago = SystemTime::now() - Duration::from_secs(40 * 24 * 60 * 60);
+        for (name, old) in [
+            ("job_123_1.out", true),
+            ("arrayify-2026-01-01-10-00.log", true),
+            ("arrayify-2026-01-01-10-00.json", true),
+            ("notes.txt", true),
+            ("job_456_1.err", false),
+            ("arrayify-2026-06-01-10-00.log", false),
+        ] {
+            let file = File::create(dir.join(name)).unwrap();
+            if old {
+                file.set_modified(forty_days_ago).unwrap();
+            }
+        }
+
+        assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 24 * 60 * 60)));
+        assert_eq!(parse_age("2w"), Ok(Duration::from_secs(14 * 24 * 60 * 60)));
+        assert!(parse_age("30x").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3é").is_err());
+
+        let log_dir = dir.to_str().unwrap();
+        let preview = clean_logs(log_dir, parse_age("30d").unwrap(), true).unwrap();
+        assert_eq!(preview.len(), 3);
+        assert!(dir.join("job_123_1.out").exists());
+
+        let removed = clean_logs(log_dir, parse_age("30d").unwrap(), false).unwrap();
+        assert_eq!(removed, preview);
+        let mut left: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            vec![
+                "arrayify-2026-06-01-10-00.log",
+                "job_456_1.err",
+                "notes.txt"
+            ]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Files written for a submission that the scheduler hasn't accepted yet, so
+/// an interrupt can remove them rather than leave orphans in the log directory.
+pub struct Tracker {
+    paths: Mutex<Vec<PathBuf>>,
+}
+
+impl Tracker {
+    pub const fn new() -> Self {
+        Tracker {
+            paths: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn track(&self, path: impl Into<PathBuf>) {
+        self.paths.lock().unwrap().push(path.into());
+    }
+
+    /// Keeps one file, e.g. once the array it belongs to is submitted.
+    pub fn forget(&self, path: impl AsRef<Path>) {
+        self.paths
+            .lock()
+            .unwrap()
+            .retain(|tracked| tracked != path.as_ref());
+    }
+
+    /// Deletes every tracked file that still exists, returning how many were removed.
+    pub fn remove_tracked(&self) -> usize {
+        let mut paths = self.paths.lock().unwrap();
+        let removed = paths
+            .iter()
+            .filter(|path| fs::remove_file(path).is_ok())
+            .count();
+        paths.clear();
+        removed
+    }
+}
+
+pub static TRACKED: Tracker = Tracker::new();
+
+/// Removes unsubmitted files and exits on SIGINT/SIGTERM.
+pub fn install_handler() {
+    let installed = ctrlc::set_handler(|| {
+        let removed = TRACKED.remove_tracked();
+        eoutln!(
+            "\n🛑 Interrupted; removed {} unsubmitted file(s), nothing further was submitted",
+            removed
+        );
+        std::process::exit(130);
+    });
+    if let Err(e) = installed {
+        eoutln!("⚠️ Could not install the interrupt handler: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_removes_tracked_files() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_file = log_dir.path().join("arrayify-test.log");
+        let script = log_dir.path().join("arrayify-test.sh");
+        let submitted = log_dir.path().join("arrayify-old.log");
+        for path in [&log_file, &script, &submitted] {
+            fs::write(path, "echo").unwrap();
+        }
+
+        let tracker = Tracker::new();
+        tracker.track(&submitted);
+        tracker.forget(&submitted);
+        tracker.track(&log_file);
+        tracker.track(&script);
+        tracker.track(log_dir.path().join("never-written.json"));
+
+        assert_eq!(tracker.remove_tracked(), 2);
+        assert!(!log_file.exists());
+        assert!(!script.exists());
+        assert!(submitted.exists());
+        assert_eq!(tracker.remove_tracked(), 0);
+    }
+}
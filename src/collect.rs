@@ -0,0 +1,199 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Which of each task's output files `collect` gathers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Out,
+    Err,
+    Both,
+    /// The exit codes recorded by tasks submitted with `--exit-codes`
+    Code,
+}
+
+impl Stream {
+    pub fn from_name(name: &str) -> Option<Stream> {
+        match name {
+            "out" => Some(Stream::Out),
+            "err" => Some(Stream::Err),
+            "both" => Some(Stream::Both),
+            "code" => Some(Stream::Code),
+            _ => None,
+        }
+    }
+
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Stream::Out => &["out"],
+            Stream::Err => &["err"],
+            Stream::Both => &["out", "err"],
+            Stream::Code => &["code"],
+        }
+    }
+}
+
+/// Finds the `job_<ID>_<INDEX>.out`/`.err` files LSF wrote for `job_id`,
+/// ordered by array index and, within a task, stdout before stderr.
+pub fn task_files(log_dir: &str, job_id: &str, stream: Stream) -> io::Result<Vec<PathBuf>> {
+    let prefix = format!("job_{}_", job_id);
+    let extensions = stream.extensions();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(log_dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some((index, extension)) = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.split_once('.'))
+        else {
+            continue;
+        };
+        let (Ok(index), Some(order)) = (
+            index.parse::<usize>(),
+            extensions.iter().position(|ext| *ext == extension),
+        ) else {
+            continue;
+        };
+        files.push((index, order, path));
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, _, path)| path).collect())
+}
+
+/// Reads the exit code each task recorded in `job_<ID>_<INDEX>.code`, in
+/// index order. These come from the task itself, independent of LSF's
+/// accounting.
+pub fn read_exit_codes(log_dir: &str, job_id: &str) -> io::Result<Vec<(usize, i32)>> {
+    let prefix = format!("job_{}_", job_id);
+    let mut codes = Vec::new();
+    for path in task_files(log_dir, job_id, Stream::Code)? {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let index = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".code"))
+            .and_then(|index| index.parse().ok());
+        let content = fs::read_to_string(&path)?;
+        let (Some(index), Ok(code)) = (index, content.trim().parse()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} does not hold an exit code", path.display()),
+            ));
+        };
+        codes.push((index, code));
+    }
+    Ok(codes)
+}
+
+/// Concatenates an array's task output into `writer` in index order, each
+/// file headed by its name. Exit codes are written one `INDEX<TAB>CODE` line
+/// per task instead. Returns how many files were collected.
+pub fn collect_logs(
+    log_dir: &str,
+    job_id: &str,
+    stream: Stream,
+    writer: &mut impl Write,
+) -> io::Result<usize> {
+    if stream == Stream::Code {
+        let codes = read_exit_codes(log_dir, job_id)?;
+        for (index, code) in &codes {
+            writeln!(writer, "{}\t{}", index, code)?;
+        }
+        return Ok(codes.len());
+    }
+
+    let files = task_files(log_dir, job_id, stream)?;
+    for path in &files {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        writeln!(writer, "==> {} <==", name)?;
+        let content = fs::read(path)?;
+        writer.write_all(&content)?;
+        if !content.is_empty() && !content.ends_with(b"\n") {
+            writeln!(writer)?;
+        }
+    }
+    Ok(files.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_exit_codes() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let dir = log_dir.path();
+        for (index, code) in [(10, "0"), (2, "137"), (1, "0")] {
+            fs::write(
+                dir.join(format!("job_42_{}.code", index)),
+                format!("{}\n", code),
+            )
+            .unwrap();
+            fs::write(dir.join(format!("job_42_{}.out", index)), "output\n").unwrap();
+        }
+        let log_dir = dir.to_str().unwrap();
+
+        assert_eq!(
+            read_exit_codes(log_dir, "42").unwrap(),
+            vec![(1, 0), (2, 137), (10, 0)]
+        );
+        let mut collected = Vec::new();
+        assert_eq!(
+            collect_logs(log_dir, "42", Stream::Code, &mut collected).unwrap(),
+            3
+        );
+        assert_eq!(
+            String::from_utf8(collected).unwrap(),
+            "1\t0\n2\t137\n10\t0\n"
+        );
+
+        fs::write(dir.join("job_42_3.code"), "").unwrap();
+        assert!(read_exit_codes(log_dir, "42").is_err());
+    }
+
+    #[test]
+    fn test_collect_err_only_reads_err_files() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let dir = log_dir.path();
+        for index in [1, 2, 10] {
+            fs::write(
+                dir.join(format!("job_42_{}.out", index)),
+                format!("out {}\n", index),
+            )
+            .unwrap();
+            fs::write(
+                dir.join(format!("job_42_{}.err", index)),
+                format!("err {}", index),
+            )
+            .unwrap();
+        }
+        fs::write(dir.join("job_7_1.err"), "another array\n").unwrap();
+        let log_dir = dir.to_str().unwrap();
+
+        let mut collected = Vec::new();
+        let count = collect_logs(log_dir, "42", Stream::Err, &mut collected).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(
+            String::from_utf8(collected).unwrap(),
+            "==> job_42_1.err <==\nerr 1\n==> job_42_2.err <==\nerr 2\n==> job_42_10.err <==\nerr 10\n"
+        );
+
+        let both = task_files(log_dir, "42", Stream::Both).unwrap();
+        let names: Vec<_> = both
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "job_42_1.out",
+                "job_42_1.err",
+                "job_42_2.out",
+                "job_42_2.err",
+                "job_42_10.out",
+                "job_42_10.err"
+            ]
+        );
+    }
+}
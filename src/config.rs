@@ -0,0 +1,117 @@
+use crate::submission::SubmitOptions;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// User configuration, read from `$ARRAYIFY_CONFIG` or `~/.config/arrayify/config.toml`.
+///
+/// ```toml
+/// [profiles.gpu]
+/// queue = "gpu-normal"
+/// memory = 32
+/// threads = 8
+/// time = 720
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// A named bundle of resources; any field left out keeps the built-in default.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Profile {
+    pub queue: Option<String>,
+    /// Memory per job in GB
+    pub memory: Option<u32>,
+    pub threads: Option<u32>,
+    /// Run time limit in minutes
+    pub time: Option<u32>,
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("ARRAYIFY_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/arrayify/config.toml"))
+}
+
+/// Extra exit code reasons for `check`, from `$ARRAYIFY_EXIT_CODES` or
+/// `~/.config/arrayify/exit_codes.toml`.
+pub fn default_exit_codes_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("ARRAYIFY_EXIT_CODES") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".config/arrayify/exit_codes.toml"))
+}
+
+pub fn load_config(path: &Path) -> io::Result<Config> {
+    let content = fs::read_to_string(path)?;
+    toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl Config {
+    pub fn profile(&self, name: &str) -> io::Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No profile named '{}' in the config file", name),
+            )
+        })
+    }
+}
+
+impl Profile {
+    /// Fills in resources from the profile, except those given explicitly on the
+    /// command line: explicit flag > profile > built-in default.
+    pub fn apply(&self, opts: &mut SubmitOptions, is_explicit: impl Fn(&str) -> bool) {
+        if let (Some(queue), false) = (&self.queue, is_explicit("queue")) {
+            opts.queue = queue.clone();
+        }
+        if let (Some(memory), false) = (self.memory, is_explicit("memory")) {
+            opts.memory_gb = memory;
+        }
+        if let (Some(threads), false) = (self.threads, is_explicit("threads")) {
+            opts.threads = threads;
+        }
+        if let (Some(time), false) = (self.time, is_explicit("time")) {
+            opts.time_minutes = Some(time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_resolution_and_override() {
+        let config: Config = toml::from_str(
+            r#"
+            [profiles.gpu]
+            queue = "gpu"
+            memory = 32
+            threads = 8
+            time = 720
+            "#,
+        )
+        .unwrap();
+        let profile = config.profile("gpu").unwrap();
+
+        // Only --threads given explicitly
+        let mut opts = SubmitOptions {
+            threads: 2,
+            ..Default::default()
+        };
+        profile.apply(&mut opts, |flag| flag == "threads");
+
+        assert_eq!(opts.queue, "gpu");
+        assert_eq!(opts.memory_gb, 32);
+        assert_eq!(opts.threads, 2);
+        assert_eq!(opts.time_minutes, Some(720));
+        assert!(config.profile("missing").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::scheduler::{self, Scheduler};
+use crate::submission::{self, SubmitOptions};
+use std::ffi::OsStr;
+use std::io;
+
+/// Everything `arrayify doctor` reports, gathered up front so it can be
+/// formatted and tested without a scheduler.
+pub struct Diagnosis {
+    pub version: &'static str,
+    pub scheduler: io::Result<Scheduler>,
+    /// `LSB_*` variables, sorted by name
+    pub lsb_vars: Vec<(String, String)>,
+    /// The first rendered command, if an input was given
+    pub sample_command: Option<io::Result<Option<String>>>,
+}
+
+/// Collects the diagnosis from `path` (a PATH-style list), the given
+/// environment and, when `opts` is set, the input it describes.
+pub fn diagnose(
+    scheduler_name: &str,
+    path: &OsStr,
+    vars: impl Iterator<Item = (String, String)>,
+    opts: Option<&SubmitOptions>,
+) -> Diagnosis {
+    let mut lsb_vars: Vec<(String, String)> =
+        vars.filter(|(name, _)| name.starts_with("LSB_")).collect();
+    lsb_vars.sort();
+    Diagnosis {
+        version: env!("CARGO_PKG_VERSION"),
+        scheduler: scheduler::resolve_scheduler(scheduler_name, path),
+        lsb_vars,
+        sample_command: opts.map(submission::first_command),
+    }
+}
+
+pub fn format_diagnosis(diagnosis: &Diagnosis) -> String {
+    let mut out = format!("arrayify version: {}\n", diagnosis.version);
+    match &diagnosis.scheduler {
+        Ok(scheduler) => out.push_str(&format!("scheduler: {}\n", scheduler)),
+        Err(e) => out.push_str(&format!("scheduler: not detected ({})\n", e)),
+    }
+
+    if diagnosis.lsb_vars.is_empty() {
+        out.push_str("LSB_* environment: none set\n");
+    } else {
+        out.push_str("LSB_* environment:\n");
+        for (name, value) in &diagnosis.lsb_vars {
+            out.push_str(&format!("  {}={}\n", name, value));
+        }
+    }
+
+    match &diagnosis.sample_command {
+        None => {}
+        Some(Ok(Some(command))) => out.push_str(&format!("first command: {}\n", command)),
+        Some(Ok(None)) => out.push_str("first command: input produced no jobs\n"),
+        Some(Err(e)) => out.push_str(&format!("first command: failed to render ({})\n", e)),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_doctor_reports_version_and_scheduler() {
+        let bin = tempfile::tempdir().unwrap();
+        let bsub = bin.path().join("bsub");
+        fs::write(&bsub, "#!/bin/bash\n").unwrap();
+        fs::set_permissions(&bsub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID\ns1\ns2").unwrap();
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "echo {ID}".to_string(),
+            ..Default::default()
+        };
+        let vars = [("LSB_DEFAULTQUEUE", "normal"), ("HOME", "/home/me")]
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let report = format_diagnosis(&diagnose(
+            "auto",
+            bin.path().as_os_str(),
+            vars.into_iter(),
+            Some(&opts),
+        ));
+        assert!(report.contains(&format!("arrayify version: {}", env!("CARGO_PKG_VERSION"))));
+        assert!(report.contains("scheduler: lsf"));
+        assert!(report.contains("LSB_DEFAULTQUEUE=normal"));
+        assert!(!report.contains("HOME"));
+        assert!(report.contains("first command: echo s1"));
+    }
+}
@@ -0,0 +1,107 @@
+/// Workflow engines `export` can write the rendered jobs for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Snakemake,
+    Nextflow,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name {
+            "snakemake" => Some(ExportFormat::Snakemake),
+            "nextflow" => Some(ExportFormat::Nextflow),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the jobs as a workflow skeleton that runs each command once.
+pub fn export_jobs(jobs: &[String], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Snakemake => snakemake(jobs),
+        ExportFormat::Nextflow => nextflow(jobs),
+    }
+}
+
+/// One rule per command, each touching a marker file that `rule all` asks for.
+fn snakemake(jobs: &[String]) -> String {
+    let mut out = String::from("# Generated by arrayify export\n\n");
+    out.push_str("rule all:\n    input:\n");
+    for index in 1..=jobs.len() {
+        out.push_str(&format!("        \"arrayify_done/task_{}\",\n", index));
+    }
+    for (index, job) in jobs.iter().enumerate() {
+        let index = index + 1;
+        // Snakemake formats shell strings, so the command's own braces are doubled
+        let command = job.replace('{', "{{").replace('}', "}}");
+        out.push_str(&format!(
+            "\nrule task_{}:\n    output:\n        touch(\"arrayify_done/task_{}\")\n    shell:\n        {}\n",
+            index,
+            index,
+            python_string(&command)
+        ));
+    }
+    out
+}
+
+/// A single process fed the commands through a channel.
+fn nextflow(jobs: &[String]) -> String {
+    let mut out = String::from("// Generated by arrayify export\n\n");
+    out.push_str("process arrayify_task {\n    input:\n    val command\n\n");
+    out.push_str("    script:\n    \"\"\"\n    ${command}\n    \"\"\"\n}\n\n");
+    out.push_str("workflow {\n    channel.of(\n");
+    for job in jobs {
+        out.push_str(&format!("        {},\n", groovy_string(job)));
+    }
+    out.push_str("    ) | arrayify_task\n}\n");
+    out
+}
+
+fn python_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Single quotes, so Groovy doesn't interpolate the command's `$` references.
+fn groovy_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('\n', "\\n");
+    format!("'{}'", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_contains_each_command() {
+        let jobs = vec![
+            "bwa mem ref.fa s1_R1.fq s1_R2.fq > s1.sam".to_string(),
+            "bwa mem ref.fa s2_R1.fq s2_R2.fq > s2.sam".to_string(),
+        ];
+
+        let snakefile = export_jobs(&jobs, ExportFormat::Snakemake);
+        let nextflow = export_jobs(&jobs, ExportFormat::Nextflow);
+        for job in &jobs {
+            assert!(snakefile.contains(job.as_str()));
+            assert!(nextflow.contains(job.as_str()));
+        }
+        assert!(snakefile.contains("rule task_2:"));
+        assert!(snakefile.contains("\"arrayify_done/task_2\","));
+        assert!(nextflow.contains(") | arrayify_task"));
+
+        let awk = vec!["awk '{print $1}' \"in.txt\"".to_string()];
+        assert!(
+            export_jobs(&awk, ExportFormat::Snakemake)
+                .contains(r#""awk '{{print $1}}' \"in.txt\"""#)
+        );
+        assert!(
+            export_jobs(&awk, ExportFormat::Nextflow).contains(r#"'awk \'{print $1}\' "in.txt"'"#)
+        );
+    }
+}
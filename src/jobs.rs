@@ -1,41 +1,759 @@
 use csv::ReaderBuilder;
-use std::collections::HashMap;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self};
-use std::io::{self};
+use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 
-pub fn read_jobs_from_csv(csv_file: &str, command_template: &str) -> io::Result<Vec<String>> {
-    let mut rdr = ReaderBuilder::new()
+/// Settings controlling how CSV rows are read and rendered.
+#[derive(Debug, Clone, Default)]
+pub struct CsvOptions {
+    /// Fill missing trailing columns with empty values instead of erroring
+    pub pad_short_rows: bool,
+    /// Encoding label (e.g. `latin1`) to decode the file from instead of UTF-8
+    pub encoding: Option<String>,
+    /// Added to `{ROW}`, so rows keep counting across concatenated files
+    pub row_offset: usize,
+    /// Columns that must exist and be non-empty on every row
+    pub required_columns: Vec<String>,
+    /// Column whose values are themselves templates, rendered against the
+    /// row before being spliced into the command
+    pub template_column: Option<String>,
+    /// Read every row and report all the bad ones, instead of stopping at the first
+    pub collect_errors: bool,
+}
+
+/// Settings controlling how `--dir` and `--glob` files are paired and rendered.
+#[derive(Debug, Clone)]
+pub struct PairOptions {
+    /// Substitute canonical absolute paths instead of the paths as found
+    pub absolute_paths: bool,
+    /// Joins R1 and R2 in `{READS}`
+    pub reads_separator: String,
+    /// Group a sample's `_L001`, `_L002`, ... lane files into one task
+    pub merge_lanes: bool,
+    /// Joins the lane files of a read in `{R1}` and `{R2}` when merging lanes
+    pub lane_separator: String,
+}
+
+impl Default for PairOptions {
+    fn default() -> Self {
+        PairOptions {
+            absolute_paths: false,
+            reads_separator: ",".to_string(),
+            merge_lanes: false,
+            lane_separator: " ".to_string(),
+        }
+    }
+}
+
+/// Decodes a whole file from the named encoding. A leading byte order mark
+/// takes precedence and is removed.
+fn decode_file(path: &str, label: &str) -> io::Result<String> {
+    let encoding = encoding_rs::Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown encoding: {}", label),
+        )
+    })?;
+    let bytes = fs::read(path)?;
+    let (content, _, _) = encoding.decode(&bytes);
+    Ok(content.into_owned())
+}
+
+fn open_csv(
+    csv_file: &str,
+    csv_options: &CsvOptions,
+) -> io::Result<csv::Reader<Box<dyn io::Read>>> {
+    // UTF-8 is streamed straight from disk (the csv reader drops a leading BOM);
+    // other encodings are decoded up front
+    let input: Box<dyn io::Read> = match &csv_options.encoding {
+        Some(label) => Box::new(io::Cursor::new(decode_file(csv_file, label)?.into_bytes())),
+        None => Box::new(fs::File::open(csv_file)?),
+    };
+    Ok(ReaderBuilder::new()
         .has_headers(true)
-        .from_path(csv_file)
+        .flexible(true)
+        .from_reader(input))
+}
+
+pub fn read_csv_headers(csv_file: &str, csv_options: &CsvOptions) -> io::Result<Vec<String>> {
+    let mut rdr = open_csv(csv_file, csv_options)?;
+    let headers = rdr
+        .headers()
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(headers.iter().map(String::from).collect())
+}
+
+/// Reads several CSVs into one job list, in the order given. Every file must
+/// have the same set of columns, though not necessarily in the same order.
+pub fn read_jobs_from_csvs(
+    csv_files: &[String],
+    command_template: &str,
+    csv_options: &CsvOptions,
+) -> io::Result<Vec<String>> {
+    let mut expected: Option<(&str, Vec<String>)> = None;
+    for csv_file in csv_files {
+        let mut headers = read_csv_headers(csv_file, csv_options)?;
+        headers.sort();
+        match &expected {
+            None => expected = Some((csv_file, headers)),
+            Some((first_file, first_headers)) if *first_headers != headers => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} has columns {} but {} has columns {}",
+                        csv_file,
+                        headers.join(","),
+                        first_file,
+                        first_headers.join(",")
+                    ),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
 
+    let mut jobs = Vec::new();
+    let mut errors = Vec::new();
+    for csv_file in csv_files {
+        let file_options = CsvOptions {
+            row_offset: csv_options.row_offset + jobs.len(),
+            ..csv_options.clone()
+        };
+        match read_jobs_from_csv(csv_file, command_template, &file_options) {
+            Ok(file_jobs) => jobs.extend(file_jobs),
+            Err(e) if csv_options.collect_errors => errors.push(e.to_string()),
+            Err(e) => return Err(e),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            errors.join("\n"),
+        ));
+    }
+    Ok(jobs)
+}
+
+pub fn read_jobs_from_csv(
+    csv_file: &str,
+    command_template: &str,
+    csv_options: &CsvOptions,
+) -> io::Result<Vec<String>> {
+    let mut rdr = open_csv(csv_file, csv_options)?;
     let headers = rdr
         .headers()
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
         .clone();
+    let required = required_column_indices(&headers, &csv_options.required_columns, csv_file)?;
+    // Sanitized aliases go in first so a real column of the same name wins
+    let mut columns: HashMap<String, usize> = HashMap::new();
+    for (i, header) in headers.iter().enumerate() {
+        columns.entry(sanitize_header(header)).or_insert(i);
+    }
+    for (i, header) in headers.iter().enumerate() {
+        columns.insert(header.to_string(), i);
+    }
+    let template_index = match &csv_options.template_column {
+        Some(name) => Some(*columns.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no template column {}", csv_file, name),
+            )
+        })?),
+        None => None,
+    };
     let mut jobs = Vec::new();
+    let placeholder = Regex::new(PLACEHOLDER_PATTERN).unwrap();
+    let arithmetic = Regex::new(ARITHMETIC_PATTERN).unwrap();
+    let has_arithmetic = arithmetic.is_match(command_template);
 
-    for result in rdr.records() {
-        let record = result.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let render_row = |row: usize, record: &csv::StringRecord| -> io::Result<String> {
         let mut job_command = command_template.to_string();
+        if has_arithmetic {
+            let lookup = |name: &str| {
+                headers
+                    .iter()
+                    .position(|header| header == name || sanitize_header(header) == name)
+                    .map(|i| record.get(i).unwrap_or_default().to_string())
+            };
+            job_command = evaluate_arithmetic(&job_command, &arithmetic, lookup, row + 1)?;
+        }
 
-        for (i, header) in headers.iter().enumerate() {
-            let placeholder = format!("{{{}}}", header);
-            if let Some(value) = record.get(i) {
-                job_command = job_command.replace(&placeholder, value);
+        if record.len() > headers.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Row {} has {} fields but the header has {}",
+                    row + 1,
+                    record.len(),
+                    headers.len()
+                ),
+            ));
+        }
+        if record.len() < headers.len() {
+            let missing: Vec<&str> = headers.iter().skip(record.len()).collect();
+            if !csv_options.pad_short_rows {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Row {} is missing columns: {} (use --pad-short-rows to fill them with empty values)",
+                        row + 1,
+                        missing.join(", ")
+                    ),
+                ));
             }
+            eoutln!(
+                "⚠️ Row {} is missing columns {}, using empty values",
+                row + 1,
+                missing.join(", ")
+            );
         }
-        jobs.push(job_command);
+
+        let empty: Vec<&str> = required
+            .iter()
+            .filter(|&&i| record.get(i).unwrap_or_default().trim().is_empty())
+            .map(|&i| &headers[i])
+            .collect();
+        if !empty.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Row {} has empty required columns: {}",
+                    row + 1,
+                    empty.join(", ")
+                ),
+            ));
+        }
+
+        // {ROW} is the 1-based data row; the header row is not counted.
+        // A real column called ROW takes precedence.
+        let value = |name: &str| match columns.get(name) {
+            Some(&i) => Some(record.get(i).unwrap_or_default().to_string()),
+            None if name == "ROW" => Some((csv_options.row_offset + row + 1).to_string()),
+            None => None,
+        };
+        // The template column is rendered one level deep: values substituted
+        // into it are not expanded again
+        let job_command = substitute_placeholders(&job_command, &placeholder, |name| {
+            match (columns.get(name), template_index) {
+                (Some(&i), Some(template)) if i == template => Some(substitute_placeholders(
+                    record.get(i).unwrap_or_default(),
+                    &placeholder,
+                    value,
+                )),
+                _ => value(name),
+            }
+        });
+        Ok(job_command)
+    };
+
+    // With collect_errors, every bad row is reported rather than just the first
+    let mut errors = Vec::new();
+    for (row, result) in rdr.records().enumerate() {
+        let rendered = result
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            .and_then(|record| render_row(row, &record));
+        match rendered {
+            Ok(job) => jobs.push(job),
+            Err(e) if csv_options.collect_errors => errors.push(e.to_string()),
+            Err(e) => return Err(e),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} has {} invalid rows:\n  {}",
+                csv_file,
+                errors.len(),
+                errors.join("\n  ")
+            ),
+        ));
     }
 
     Ok(jobs)
 }
 
+/// Maps `--require-columns` names (raw or sanitized headers) to column
+/// indices, erroring if any are absent from the header.
+fn required_column_indices(
+    headers: &csv::StringRecord,
+    required: &[String],
+    csv_file: &str,
+) -> io::Result<Vec<usize>> {
+    let mut indices = Vec::new();
+    let mut missing = Vec::new();
+    for name in required {
+        match headers
+            .iter()
+            .position(|header| header == name || sanitize_header(header) == *name)
+        {
+            Some(i) => indices.push(i),
+            None => missing.push(name.as_str()),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is missing required columns: {}",
+                csv_file,
+                missing.join(", ")
+            ),
+        ));
+    }
+    Ok(indices)
+}
+
+/// Reads one job per line of a plain list (or stdin when `list_file` is `-`),
+/// substituting the line into `{ITEM}`. Blank lines and lines starting with
+/// `comment_char` are skipped.
+pub fn read_jobs_from_list(
+    list_file: &str,
+    command_template: &str,
+    comment_char: char,
+) -> io::Result<Vec<String>> {
+    if list_file == "-" {
+        render_list_jobs(io::stdin().lock(), command_template, comment_char)
+    } else {
+        let file = fs::File::open(list_file)?;
+        render_list_jobs(io::BufReader::new(file), command_template, comment_char)
+    }
+}
+
+fn render_list_jobs(
+    reader: impl BufRead,
+    command_template: &str,
+    comment_char: char,
+) -> io::Result<Vec<String>> {
+    let placeholder = Regex::new(PLACEHOLDER_PATTERN).unwrap();
+    let mut jobs = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let item = line.trim();
+        if item.is_empty() || item.starts_with(comment_char) {
+            continue;
+        }
+        jobs.push(substitute_placeholders(
+            command_template,
+            &placeholder,
+            |name| (name == "ITEM").then(|| item.to_string()),
+        ));
+    }
+    Ok(jobs)
+}
+
+/// Renders a template against `KEY=VALUE` pairs as if they were one CSV row,
+/// for `render`. Arithmetic and conditionals work as they do for real input.
+pub fn render_sample(template: &str, pairs: &[String]) -> io::Result<String> {
+    let mut values = BTreeMap::new();
+    for pair in pairs {
+        let Some((key, value)) = pair.split_once('=') else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--set {} is not of the form KEY=VALUE", pair),
+            ));
+        };
+        values.insert(key.trim().to_string(), value.to_string());
+    }
+    let lookup = |name: &str| values.get(name).cloned();
+    let arithmetic = Regex::new(ARITHMETIC_PATTERN).unwrap();
+    let command = evaluate_arithmetic(template, &arithmetic, lookup, 1)?;
+    let placeholder = Regex::new(PLACEHOLDER_PATTERN).unwrap();
+    Ok(substitute_placeholders(&command, &placeholder, lookup))
+}
+
+/// Reads jobs from a JSON array. String elements are taken as ready-made
+/// commands; the fields of object elements are substituted into the template
+/// like CSV columns, with `{ROW}` as the 1-based element number.
+pub fn read_jobs_from_json(json_file: &str, command_template: &str) -> io::Result<Vec<String>> {
+    let elements = read_json_array(json_file)?;
+    let placeholder = Regex::new(PLACEHOLDER_PATTERN).unwrap();
+    let mut jobs = Vec::with_capacity(elements.len());
+    for (i, element) in elements.iter().enumerate() {
+        let job = match element {
+            serde_json::Value::String(command) => command.clone(),
+            serde_json::Value::Object(_) if command_template.is_empty() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Element {} of {} is an object, which needs --command to render",
+                        i + 1,
+                        json_file
+                    ),
+                ));
+            }
+            serde_json::Value::Object(fields) => {
+                substitute_placeholders(command_template, &placeholder, |name| {
+                    match fields.get(name) {
+                        Some(value) => Some(json_field_text(value)),
+                        None => (name == "ROW").then(|| (i + 1).to_string()),
+                    }
+                })
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Element {} of {} is neither a string nor an object",
+                        i + 1,
+                        json_file
+                    ),
+                ));
+            }
+        };
+        jobs.push(job);
+    }
+    Ok(jobs)
+}
+
+/// Field names of the objects in a JSON array, for `--placeholder-alias`.
+pub fn read_json_fields(json_file: &str) -> io::Result<Vec<String>> {
+    let mut names: Vec<String> = Vec::new();
+    for element in read_json_array(json_file)? {
+        if let serde_json::Value::Object(fields) = element {
+            for name in fields.keys() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+    }
+    names.push("ROW".to_string());
+    Ok(names)
+}
+
+fn read_json_array(json_file: &str) -> io::Result<Vec<serde_json::Value>> {
+    let contents = fs::read_to_string(json_file)?;
+    match serde_json::from_str(&contents) {
+        Ok(serde_json::Value::Array(elements)) => Ok(elements),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} does not hold a JSON array", json_file),
+        )),
+        Err(e) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Could not parse {}: {}", json_file, e),
+        )),
+    }
+}
+
+/// Text substituted for a JSON field: strings without their quotes, `null`
+/// as empty, anything else as JSON.
+fn json_field_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Stand-ins for a template's literal braces while it is rendered with
+/// custom delimiters.
+const LITERAL_OPEN: char = '\u{2}';
+const LITERAL_CLOSE: char = '\u{3}';
+
+/// Rewrites a template using `open`/`close` placeholder markers (e.g. `%%`
+/// or `<<` and `>>`) into the usual brace syntax, setting its own braces
+/// aside so they aren't taken for placeholders. Identical markers alternate
+/// between opening and closing. Undo with `restore_braces` after rendering.
+pub fn translate_delimiters(template: &str, open: &str, close: &str) -> String {
+    let template = template
+        .replace('{', &LITERAL_OPEN.to_string())
+        .replace('}', &LITERAL_CLOSE.to_string());
+    if open == close {
+        return template
+            .split(open)
+            .enumerate()
+            .map(|(i, part)| match i {
+                0 => part.to_string(),
+                i if i % 2 == 1 => format!("{{{}", part),
+                _ => format!("}}{}", part),
+            })
+            .collect();
+    }
+    template.replace(open, "{").replace(close, "}")
+}
+
+/// Puts back the literal braces set aside by `translate_delimiters`.
+pub fn restore_braces(rendered: &str) -> String {
+    rendered
+        .replace(LITERAL_OPEN, "{")
+        .replace(LITERAL_CLOSE, "}")
+}
+
+/// Matches a `{NAME}` placeholder.
+const PLACEHOLDER_PATTERN: &str = r"\{([^{}]+)\}";
+
+/// Reads `--vars` constants from a flat TOML table. Strings are used as they
+/// are; numbers and booleans as written.
+pub fn read_template_vars(vars_file: &str) -> io::Result<BTreeMap<String, String>> {
+    let content = fs::read_to_string(vars_file)?;
+    let table: toml::Table = toml::from_str(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", vars_file, e)))?;
+    table
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                toml::Value::String(text) => text,
+                toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                    value.to_string()
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}: {} must be a string, number or boolean",
+                            vars_file, name
+                        ),
+                    ));
+                }
+            };
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Fills `{NAME}` placeholders (written with the `open` and `close`
+/// delimiters) from `vars` in the template itself, before any row is
+/// rendered. Names in `provided` are left for the input, so columns win over
+/// vars of the same name. Like row values, var values are never rescanned.
+pub fn apply_template_vars(
+    template: &str,
+    vars: &BTreeMap<String, String>,
+    provided: &[String],
+    (open, close): (&str, &str),
+) -> String {
+    let pattern = Regex::new(&format!(
+        r"{}(\w+){}",
+        regex::escape(open),
+        regex::escape(close)
+    ))
+    .unwrap();
+    pattern
+        .replace_all(template, |caps: &regex::Captures| {
+            let name = &caps[1];
+            match vars.get(name) {
+                Some(value) if !provided.iter().any(|column| column == name) => value.clone(),
+                _ => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Matches an `{env:NAME}` placeholder, optionally with a `:-default`.
+const ENV_PLACEHOLDER_PATTERN: &str = r"\{env:([A-Za-z_][A-Za-z0-9_]*)(:-[^{}]*)?\}";
+
+/// Checks that every `{env:NAME}` placeholder without a default names a set
+/// environment variable, looked up with `env`. Returns whether there are any.
+pub fn check_env_placeholders(
+    template: &str,
+    env: impl Fn(&str) -> Option<String>,
+) -> io::Result<bool> {
+    let pattern = Regex::new(ENV_PLACEHOLDER_PATTERN).unwrap();
+    for caps in pattern.captures_iter(template) {
+        let name = &caps[1];
+        if caps.get(2).is_none() && env(name).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Environment variable {} used by {{env:{}}} is not set (give a default with {{env:{}:-VALUE}})",
+                    name, name, name
+                ),
+            ));
+        }
+    }
+    Ok(pattern.is_match(template))
+}
+
+/// Replaces `{env:NAME}` placeholders in a rendered command with a reference
+/// to the variable, so the secret itself is never written to the command log
+/// and is only expanded by the task's shell.
+pub fn mask_env_placeholders(command: &str) -> String {
+    let pattern = Regex::new(ENV_PLACEHOLDER_PATTERN).unwrap();
+    pattern
+        .replace_all(command, |caps: &regex::Captures| {
+            format!(
+                "${{{}{}}}",
+                &caps[1],
+                caps.get(2).map_or("", |default| default.as_str())
+            )
+        })
+        .into_owned()
+}
+
+/// Keeps or drops each `{?NAME: text}` block depending on whether `NAME` has a
+/// non-empty value; unknown names count as empty. The kept text may itself
+/// contain placeholders. Only presence is tested, with no else or nesting.
+fn render_conditionals(template: &str, lookup: &impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{?") {
+        let after = &rest[start + 2..];
+        // Find the closing brace, skipping over placeholders in the text
+        let mut depth = 0;
+        let end = after.char_indices().find_map(|(i, c)| match c {
+            '{' => {
+                depth += 1;
+                None
+            }
+            '}' if depth == 0 => Some(i),
+            '}' => {
+                depth -= 1;
+                None
+            }
+            _ => None,
+        });
+        let Some((name, text)) = end.and_then(|end| after[..end].split_once(':')) else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let present = lookup(name.trim()).is_some_and(|value| !value.trim().is_empty());
+        if present {
+            result.push_str(text);
+        }
+        rest = &after[name.len() + 1 + text.len() + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Replaces every placeholder `lookup` knows in a single pass over the
+/// template. Values are inserted verbatim and never expanded again, so a value
+/// that itself contains `{x}` comes through unchanged whatever the column
+/// order. Unknown placeholders, such as bash's `${var}`, are left alone.
+/// `{?NAME: text}` conditionals are resolved first.
+pub fn substitute_placeholders(
+    template: &str,
+    pattern: &Regex,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> String {
+    let template = render_conditionals(template, &lookup);
+    pattern
+        .replace_all(&template, |caps: &regex::Captures| {
+            lookup(&caps[1]).unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+/// Matches `{a op b}` where each operand is a column name or an integer.
+const ARITHMETIC_PATTERN: &str = r"\{\s*(\w+)\s*([-+*/])\s*(\w+)\s*\}";
+
+/// Evaluates arithmetic placeholders such as `{threads/2}` or `{mem*1000}`
+/// using the row's values. Only `+ - * /` on integers are supported, with
+/// division rounding towards zero. Anything naming an unknown column is left
+/// as it is.
+fn evaluate_arithmetic(
+    command: &str,
+    pattern: &Regex,
+    lookup: impl Fn(&str) -> Option<String>,
+    row: usize,
+) -> io::Result<String> {
+    // None when the token is neither a number nor a column, e.g. bash's ${x-1}
+    let operand = |token: &str| -> Option<io::Result<i64>> {
+        if let Ok(number) = token.parse::<i64>() {
+            return Some(Ok(number));
+        }
+        let value = lookup(token)?;
+        Some(value.trim().parse::<i64>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Row {}: column '{}' is '{}', not a whole number",
+                    row, token, value
+                ),
+            )
+        }))
+    };
+
+    let mut result = String::with_capacity(command.len());
+    let mut last = 0;
+    for caps in pattern.captures_iter(command) {
+        let whole = caps.get(0).unwrap();
+        let (Some(a), Some(b)) = (operand(&caps[1]), operand(&caps[3])) else {
+            continue;
+        };
+        let (a, b) = (a?, b?);
+        let value = match &caps[2] {
+            "+" => a.checked_add(b),
+            "-" => a.checked_sub(b),
+            "*" => a.checked_mul(b),
+            _ => a.checked_div(b),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Row {}: cannot evaluate {}", row, whole.as_str()),
+            )
+        })?;
+        result.push_str(&command[last..whole.start()]);
+        result.push_str(&value.to_string());
+        last = whole.end();
+    }
+    result.push_str(&command[last..]);
+    Ok(result)
+}
+
+/// Tidy placeholder alias for a CSV header: lowercased, with each run of
+/// non-alphanumeric characters collapsed to a single underscore and no
+/// leading/trailing underscores, so `Sample Name (v2)` becomes `sample_name_v2`.
+pub fn sanitize_header(header: &str) -> String {
+    let mut alias = String::with_capacity(header.len());
+    for c in header.chars() {
+        if c.is_alphanumeric() {
+            alias.extend(c.to_lowercase());
+        } else if !alias.ends_with('_') {
+            alias.push('_');
+        }
+    }
+    alias.trim_matches('_').to_string()
+}
+
+/// Paired-read templates must use the reads, otherwise every task ignores its input.
+fn validate_paired_template(command_template: &str) -> io::Result<()> {
+    // {READS} stands in for both
+    if command_template.contains("{READS}") {
+        return Ok(());
+    }
+    let missing: Vec<&str> = ["{R1}", "{R2}"]
+        .into_iter()
+        .filter(|placeholder| !command_template.contains(placeholder))
+        .collect();
+    if !missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Command template must reference {} when pairing reads",
+                missing.join(" and ")
+            ),
+        ));
+    }
+    Ok(())
+}
+
 pub fn read_jobs_from_dir(
     dir_path: &str,
     command_template: &str,
+    pair: &PairOptions,
 ) -> io::Result<Vec<std::string::String>> {
+    Ok(without_sizes(read_sized_jobs_from_dir(
+        dir_path,
+        command_template,
+        pair,
+    )?))
+}
+
+/// Like `read_jobs_from_dir`, but pairs each command with the combined size
+/// of its R1 and R2 files in bytes.
+pub fn read_sized_jobs_from_dir(
+    dir_path: &str,
+    command_template: &str,
+    pair: &PairOptions,
+) -> io::Result<Vec<(String, u64)>> {
+    validate_paired_template(command_template)?;
+
     let dir = Path::new(dir_path);
     if !dir.is_dir() {
         return Err(io::Error::new(
@@ -44,57 +762,954 @@ pub fn read_jobs_from_dir(
         ));
     }
 
-    // Collect all files in the directory
-    let mut file_map: HashMap<String, (Option<PathBuf>, Option<PathBuf>)> = HashMap::new();
-
+    // Collect all files in the directory, minus any the ignore file lists
+    let ignored = read_ignore_patterns(dir)?;
+    let mut paths = Vec::new();
     for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if name == IGNORE_FILE || ignored.iter().any(|pattern| pattern.matches(name)) {
+            continue;
+        }
         if path.is_file() {
-            if let Some(file_name) = path.file_name().and_then(|f| f.to_str()) {
-                if file_name.contains("_1") {
-                    let id = file_name
-                        .split("_1")
-                        .next()
-                        .unwrap_or(file_name)
-                        .to_string();
-                    file_map.entry(id).or_insert((None, None)).0 = Some(path.clone());
-                } else if file_name.contains("_2") {
-                    let id = file_name
-                        .split("_2")
-                        .next()
-                        .unwrap_or(file_name)
-                        .to_string();
-                    file_map.entry(id).or_insert((None, None)).1 = Some(path.clone());
-                }
+            paths.push(resolve_path(path, pair.absolute_paths)?);
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Directory {} is empty", dir_path),
+        ));
+    }
+
+    render_paired_jobs(paths, command_template, pair)
+}
+
+/// File in a `--dir` directory listing names to leave out of pairing.
+const IGNORE_FILE: &str = ".arrayifyignore";
+
+/// Reads the gitignore-style glob patterns (e.g. `*.md`, `README*`) from the
+/// directory's ignore file, if it has one. Patterns match file names; blank
+/// lines and `#` comments are skipped.
+fn read_ignore_patterns(dir: &Path) -> io::Result<Vec<glob::Pattern>> {
+    let content = match fs::read_to_string(dir.join(IGNORE_FILE)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            glob::Pattern::new(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Bad pattern '{}' in {}: {}", line, IGNORE_FILE, e),
+                )
+            })
+        })
+        .collect()
+}
+
+pub fn read_jobs_from_glob(
+    pattern: &str,
+    command_template: &str,
+    pair: &PairOptions,
+) -> io::Result<Vec<String>> {
+    Ok(without_sizes(read_sized_jobs_from_glob(
+        pattern,
+        command_template,
+        pair,
+    )?))
+}
+
+/// Like `read_jobs_from_glob`, but pairs each command with the combined size
+/// of its R1 and R2 files in bytes.
+pub fn read_sized_jobs_from_glob(
+    pattern: &str,
+    command_template: &str,
+    pair: &PairOptions,
+) -> io::Result<Vec<(String, u64)>> {
+    validate_paired_template(command_template)?;
+
+    let entries =
+        glob::glob(pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // Collect every file the pattern matches, wherever it lives
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(io::Error::other)?;
+        if path.is_file() {
+            paths.push(resolve_path(path, pair.absolute_paths)?);
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No files matched the pattern: {}", pattern),
+        ));
+    }
+
+    render_paired_jobs(paths, command_template, pair)
+}
+
+/// Tasks may run from a different working directory than the submit host,
+/// so relative paths can be made absolute before they are substituted.
+fn resolve_path(path: PathBuf, absolute_paths: bool) -> io::Result<PathBuf> {
+    if absolute_paths {
+        fs::canonicalize(path)
+    } else {
+        Ok(path)
+    }
+}
+
+fn without_sizes(jobs: Vec<(String, u64)>) -> Vec<String> {
+    jobs.into_iter().map(|(command, _)| command).collect()
+}
+
+/// Matches a lane-split read file such as `S1_L001_R1_001.fastq.gz` or
+/// `S1_L002_2.fq`, capturing the sample and the read number.
+const LANE_PATTERN: &str = r"^(.+?)_L\d{3}_R?([12])(?:[._]|$)";
+
+/// Pairs files into R1/R2 by the prefix before `_1`/`_2` in their file name
+/// and renders one command per ID, in ID order, alongside its input size.
+/// With `merge_lanes`, a sample's lane files are grouped under the name
+/// before the lane token and joined in `{R1}`/`{R2}`.
+/// Errors if no file fits the pattern or if any pair is incomplete.
+fn render_paired_jobs(
+    paths: Vec<PathBuf>,
+    command_template: &str,
+    pair: &PairOptions,
+) -> io::Result<Vec<(String, u64)>> {
+    let num_files = paths.len();
+    let lane = Regex::new(LANE_PATTERN).unwrap();
+    let mut file_map: BTreeMap<String, (Vec<PathBuf>, Vec<PathBuf>)> = BTreeMap::new();
+
+    for path in paths {
+        let Some(file_name) = path.file_name().and_then(|f| f.to_str()) else {
+            continue;
+        };
+        let lane_read = lane
+            .captures(file_name)
+            .filter(|_| pair.merge_lanes)
+            .map(|caps| (caps[1].to_string(), &caps[2] == "1"));
+        let (id, is_r1) = match lane_read {
+            Some(lane_read) => lane_read,
+            None if file_name.contains("_1") => {
+                let id = file_name.split("_1").next().unwrap_or(file_name);
+                (id.to_string(), true)
             }
+            None if file_name.contains("_2") => {
+                let id = file_name.split("_2").next().unwrap_or(file_name);
+                (id.to_string(), false)
+            }
+            None => continue,
+        };
+        let entry = file_map.entry(id).or_default();
+        let reads = if is_r1 { &mut entry.0 } else { &mut entry.1 };
+        if !pair.merge_lanes {
+            reads.clear();
         }
+        reads.push(path);
     }
 
-    // Validate and collect paired files
+    if file_map.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} files found but none match the pairing pattern (expected <ID>_1* and <ID>_2*)",
+                num_files
+            ),
+        ));
+    }
+
+    // A template that only uses {READS} can run single-end samples too
+    let single_end = !command_template.contains("{R2}");
+
+    // Validate and collect paired files, lanes in order
+    let placeholder = Regex::new(PLACEHOLDER_PATTERN).unwrap();
+    let join = |paths: &[PathBuf]| {
+        let paths: Vec<&str> = paths
+            .iter()
+            .map(|path| path.to_str().unwrap_or_default())
+            .collect();
+        paths.join(&pair.lane_separator)
+    };
     let mut jobs = Vec::new();
-    for (id, (r1, r2)) in file_map {
-        if let (Some(r1_path), Some(r2_path)) = (r1, r2) {
-            // Replace placeholders in the command template
-            let job_command = command_template
-                .replace("{ID}", &id)
-                .replace("{R1}", r1_path.to_str().unwrap_or_default())
-                .replace("{R2}", r2_path.to_str().unwrap_or_default());
-            jobs.push(job_command);
-        } else {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Missing R1 or R2 for ID: {}", id),
-            ));
+    let mut incomplete = Vec::new();
+    for (id, (mut r1_paths, mut r2_paths)) in file_map {
+        match (r1_paths.is_empty(), r2_paths.is_empty()) {
+            (false, false) => {}
+            (false, true) if single_end => {}
+            (false, true) => {
+                incomplete.push(format!("{} (missing R2)", id));
+                continue;
+            }
+            _ => {
+                incomplete.push(format!("{} (missing R1)", id));
+                continue;
+            }
+        }
+        r1_paths.sort();
+        r2_paths.sort();
+        let r1 = join(&r1_paths);
+        let r2 = (!r2_paths.is_empty()).then(|| join(&r2_paths));
+        let reads = match &r2 {
+            Some(r2) => format!("{}{}{}", r1, pair.reads_separator, r2),
+            None => r1.clone(),
+        };
+        // Replace placeholders in the command template
+        let job_command =
+            substitute_placeholders(command_template, &placeholder, |name| match name {
+                "ID" => Some(id.clone()),
+                "R1" => Some(r1.clone()),
+                "R2" => r2.clone(),
+                "READS" => Some(reads.clone()),
+                _ => None,
+            });
+        let mut input_bytes = 0;
+        for path in r1_paths.iter().chain(&r2_paths) {
+            input_bytes += fs::metadata(path)?.len();
         }
+        jobs.push((job_command, input_bytes));
     }
 
-    if jobs.is_empty() {
+    if !incomplete.is_empty() {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
-            "No valid file pairs found in the directory",
+            format!(
+                "Found {} complete pairs but {} are incomplete: {}",
+                jobs.len(),
+                incomplete.len(),
+                incomplete.join(", ")
+            ),
         ));
     }
 
     Ok(jobs)
 }
+
+/// Quotes a value for bash by wrapping it in single quotes.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// A filter accepting each command the first time it is seen, for dropping
+/// repeated commands with `retain` while keeping the first occurrence of each.
+pub fn first_occurrences() -> impl FnMut(&str) -> bool {
+    let mut seen = HashSet::new();
+    move |job| seen.insert(job.to_string())
+}
+
+/// Lines of unchanged context kept around each change in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// A unified diff of two command lists, one command per line, or an empty
+/// string if they match. Uses a plain longest-common-subsequence line diff.
+pub fn diff_commands(old: &[String], new: &[String], old_name: &str, new_name: &str) -> String {
+    // lcs[i][j]: length of the common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    // Each line tagged ' ', '-' or '+', with its position in old and new
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', &old[i], i, j));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', &old[i], i, j));
+            i += 1;
+        } else {
+            lines.push(('+', &new[j], i, j));
+            j += 1;
+        }
+    }
+
+    let changed: Vec<usize> = (0..lines.len()).filter(|&k| lines[k].0 != ' ').collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+    let mut k = 0;
+    while k < changed.len() {
+        // Grow the hunk while the next change is within reach of its context
+        let start = changed[k].saturating_sub(DIFF_CONTEXT);
+        let mut last = changed[k];
+        while k + 1 < changed.len() && changed[k + 1] <= last + 2 * DIFF_CONTEXT {
+            k += 1;
+            last = changed[k];
+        }
+        let end = (last + DIFF_CONTEXT + 1).min(lines.len());
+        let hunk = &lines[start..end];
+        let old_count = hunk.iter().filter(|line| line.0 != '+').count();
+        let new_count = hunk.iter().filter(|line| line.0 != '-').count();
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            hunk[0].2 + usize::from(old_count > 0),
+            old_count,
+            hunk[0].3 + usize::from(new_count > 0),
+            new_count
+        ));
+        for (tag, line, _, _) in hunk {
+            diff.push_str(&format!("{}{}\n", tag, line));
+        }
+        k += 1;
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_row_placeholder() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID\na\nb\nc").unwrap();
+
+        let jobs = read_jobs_from_csv(
+            csv_file.path().to_str().unwrap(),
+            "echo {ROW}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(jobs, vec!["echo 1", "echo 2", "echo 3"]);
+    }
+
+    #[test]
+    fn test_list_skips_comments_and_blanks() {
+        let mut list_file = NamedTempFile::new().unwrap();
+        writeln!(list_file, "# samples\na.bam\n\n  \nb.bam\n# c.bam\nc2.bam").unwrap();
+        let list_file = list_file.path().to_str().unwrap();
+
+        let jobs = read_jobs_from_list(list_file, "samtools index {ITEM}", '#').unwrap();
+        assert_eq!(
+            jobs,
+            vec![
+                "samtools index a.bam",
+                "samtools index b.bam",
+                "samtools index c2.bam"
+            ]
+        );
+
+        let jobs = read_jobs_from_list(list_file, "echo {ITEM}", ';').unwrap();
+        assert_eq!(jobs.len(), 5);
+    }
+
+    #[test]
+    fn test_sanitized_header_alias() {
+        assert_eq!(sanitize_header("Sample Name (v2)"), "sample_name_v2");
+
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "Sample Name (v2),R1\ns1,a.fq").unwrap();
+
+        let jobs = read_jobs_from_csv(
+            csv_file.path().to_str().unwrap(),
+            "echo {Sample Name (v2)} {sample_name_v2} {r1} {R1}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(jobs, vec!["echo s1 s1 a.fq a.fq"]);
+    }
+
+    #[test]
+    fn test_read_jobs_from_glob_across_folders() {
+        let root = tempfile::tempdir().unwrap();
+        for run in ["run1", "run2"] {
+            let dir = root.path().join(run);
+            fs::create_dir(&dir).unwrap();
+            fs::write(dir.join(format!("{}_1.fastq.gz", run)), "").unwrap();
+            fs::write(dir.join(format!("{}_2.fastq.gz", run)), "").unwrap();
+        }
+        fs::write(root.path().join("notes.txt"), "").unwrap();
+
+        let pattern = format!("{}/**/*.fastq.gz", root.path().display());
+        let jobs =
+            read_jobs_from_glob(&pattern, "echo {ID} {R1} {R2}", &PairOptions::default()).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert!(jobs[0].starts_with("echo run1 ") && jobs[1].starts_with("echo run2 "));
+    }
+
+    #[test]
+    fn test_arrayifyignore_skips_decoys() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("s1_1.fq"), "").unwrap();
+        fs::write(dir.path().join("s1_2.fq"), "").unwrap();
+        // Would otherwise pair as an incomplete sample "index"
+        fs::write(dir.path().join("index_1.fai"), "").unwrap();
+        fs::write(dir.path().join(".arrayifyignore"), "# indexes\n*.fai\n").unwrap();
+
+        let jobs = read_jobs_from_dir(
+            dir.path().to_str().unwrap(),
+            "echo {ID} {R1} {R2}",
+            &PairOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert!(jobs[0].starts_with("echo s1 "));
+    }
+
+    #[test]
+    fn test_reads_joins_pairs_and_single_end() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a_1.fq", "a_2.fq", "b_1.fq"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let dir = dir.path().to_str().unwrap();
+
+        let jobs = read_jobs_from_dir(dir, "fastp -i {READS}", &PairOptions::default()).unwrap();
+        assert_eq!(
+            jobs,
+            vec![
+                format!("fastp -i {dir}/a_1.fq,{dir}/a_2.fq"),
+                format!("fastp -i {dir}/b_1.fq"),
+            ]
+        );
+        let spaced = PairOptions {
+            reads_separator: " ".to_string(),
+            ..Default::default()
+        };
+        let jobs = read_jobs_from_dir(dir, "cat {READS}", &spaced).unwrap();
+        assert_eq!(jobs[0], format!("cat {dir}/a_1.fq {dir}/a_2.fq"));
+
+        // Templates naming R2 still need every pair complete
+        assert!(read_jobs_from_dir(dir, "bwa {READS} {R2}", &PairOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_merge_lanes_per_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "S1_L002_R1_001.fastq.gz",
+            "S1_L001_R1_001.fastq.gz",
+            "S1_L001_R2_001.fastq.gz",
+            "S1_L002_R2_001.fastq.gz",
+            "S2_L001_R1_001.fastq.gz",
+            "S2_L001_R2_001.fastq.gz",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let dir = dir.path().to_str().unwrap();
+        let template = "cat {R1} > {ID}_R1.fq.gz && cat {R2} > {ID}_R2.fq.gz";
+
+        let merge = PairOptions {
+            merge_lanes: true,
+            ..Default::default()
+        };
+        let jobs = read_jobs_from_dir(dir, template, &merge).unwrap();
+        assert_eq!(
+            jobs[0],
+            format!(
+                "cat {0}/S1_L001_R1_001.fastq.gz {0}/S1_L002_R1_001.fastq.gz > S1_R1.fq.gz && \
+                 cat {0}/S1_L001_R2_001.fastq.gz {0}/S1_L002_R2_001.fastq.gz > S1_R2.fq.gz",
+                dir
+            )
+        );
+        assert_eq!(jobs.len(), 2);
+
+        let commas = PairOptions {
+            lane_separator: ",".to_string(),
+            ..merge
+        };
+        let jobs = read_jobs_from_dir(dir, "star --readFilesIn {R1} {R2}", &commas).unwrap();
+        assert!(jobs[0].contains("S1_L001_R1_001.fastq.gz,"));
+
+        // Without merging, the lane files don't pair up by sample
+        assert!(read_jobs_from_dir(dir, template, &PairOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_dir_template_missing_r1() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("s1_1.fq"), "").unwrap();
+        fs::write(dir.path().join("s1_2.fq"), "").unwrap();
+        let dir = dir.path().to_str().unwrap();
+
+        let err = read_jobs_from_dir(
+            dir,
+            "bwa mem ref.fa {R2} > {ID}.sam",
+            &PairOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("{R1}"));
+        assert!(
+            read_jobs_from_dir(dir, "bwa mem ref.fa {R1} {R2}", &PairOptions::default()).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_substitution_is_single_pass() {
+        // A value holding a brace token must come through literally, whether
+        // the column it names comes before or after it
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "A,B,C\n{{C}},{{A}},c1").unwrap();
+        let csv_file = csv_file.path().to_str().unwrap();
+
+        let jobs = read_jobs_from_csv(csv_file, "echo {A} {B} {C} ${HOME}", &CsvOptions::default())
+            .unwrap();
+        assert_eq!(jobs, vec!["echo {C} {A} c1 ${HOME}"]);
+    }
+
+    #[test]
+    fn test_value_holding_placeholder_is_not_substituted() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,R1,R2\n{{R2}},a1.fq,a2.fq").unwrap();
+        let csv_file = csv_file.path().to_str().unwrap();
+        let jobs =
+            read_jobs_from_csv(csv_file, "echo {ID} {R1} {R2}", &CsvOptions::default()).unwrap();
+        assert_eq!(jobs, vec!["echo {R2} a1.fq a2.fq"]);
+
+        // Nor is a --vars value that names another var
+        let vars = BTreeMap::from([
+            ("a".to_string(), "{b}".to_string()),
+            ("b".to_string(), "b1".to_string()),
+        ]);
+        assert_eq!(
+            apply_template_vars("{a} {b} {R2}", &vars, &[], ("{", "}")),
+            "{b} b1 {R2}"
+        );
+    }
+
+    #[test]
+    fn test_conditional_renders_only_when_present() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,R1,R2\na,a1.fq,a2.fq\nb,b1.fq,").unwrap();
+        let csv_file = csv_file.path().to_str().unwrap();
+
+        let jobs = read_jobs_from_csv(
+            csv_file,
+            "align {ID} {R1}{?R2: --paired {R2}}{?MISSING: never}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(jobs, vec!["align a a1.fq --paired a2.fq", "align b b1.fq"]);
+    }
+
+    #[test]
+    fn test_template_column_renders_one_level() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(
+            csv_file,
+            "ID,other,ARGS\ns1,{{ID}},--in {{other}}.bam --name {{ID}}\ns2,x,--fast"
+        )
+        .unwrap();
+        let csv_file = csv_file.path().to_str().unwrap();
+
+        let options = CsvOptions {
+            template_column: Some("ARGS".to_string()),
+            ..Default::default()
+        };
+        let jobs = read_jobs_from_csv(csv_file, "tool {ARGS} -o {ID}.out", &options).unwrap();
+        // {other} is spliced in as "{ID}" and left there, not expanded a second time
+        assert_eq!(
+            jobs,
+            vec![
+                "tool --in {ID}.bam --name s1 -o s1.out",
+                "tool --fast -o s2.out"
+            ]
+        );
+
+        // Without the option the column is an ordinary value
+        let jobs = read_jobs_from_csv(csv_file, "tool {ARGS}", &CsvOptions::default()).unwrap();
+        assert_eq!(jobs[0], "tool --in {other}.bam --name {ID}");
+
+        let missing = CsvOptions {
+            template_column: Some("NOPE".to_string()),
+            ..Default::default()
+        };
+        assert!(read_jobs_from_csv(csv_file, "tool", &missing).is_err());
+    }
+
+    #[test]
+    fn test_quoted_fields_keep_commas_and_newlines() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        write!(
+            csv_file,
+            "ID,NOTE,TAGS\ns1,\"a, b\",x\ns2,\"first line\nsecond line\",\"say \"\"hi\"\"\"\n"
+        )
+        .unwrap();
+        let jobs = read_jobs_from_csv(
+            csv_file.path().to_str().unwrap(),
+            "echo {ID} {NOTE} {TAGS}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            jobs,
+            vec![
+                "echo s1 a, b x",
+                "echo s2 first line\nsecond line say \"hi\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_required_columns() {
+        let required = CsvOptions {
+            required_columns: vec!["ID".to_string(), "R2".to_string()],
+            ..Default::default()
+        };
+
+        let mut no_r2 = NamedTempFile::new().unwrap();
+        writeln!(no_r2, "ID,R1\na,a1").unwrap();
+        let err =
+            read_jobs_from_csv(no_r2.path().to_str().unwrap(), "echo {ID}", &required).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("missing required columns: R2"));
+
+        let mut empty_r2 = NamedTempFile::new().unwrap();
+        writeln!(empty_r2, "ID,R1,R2\na,a1,a2\nb,b1, ").unwrap();
+        let err = read_jobs_from_csv(empty_r2.path().to_str().unwrap(), "echo {ID}", &required)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Row 2 has empty required columns: R2")
+        );
+    }
+
+    #[test]
+    fn test_collect_errors_reports_every_bad_row() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,R1,R2\na,a1,a2\nb,b1\nc,c1,c2\nd,d1,d2,extra").unwrap();
+        let csv_file = csv_file.path().to_str().unwrap();
+
+        let err = read_jobs_from_csv(csv_file, "echo {ID}", &CsvOptions::default()).unwrap_err();
+        assert!(err.to_string().starts_with("Row 2 is missing columns"));
+
+        let collecting = CsvOptions {
+            collect_errors: true,
+            ..Default::default()
+        };
+        let err = read_jobs_from_csv(csv_file, "echo {ID}", &collecting).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let report = err.to_string();
+        assert!(report.contains("has 2 invalid rows:"));
+        assert!(report.contains("\n  Row 2 is missing columns: R2"));
+        assert!(report.contains("\n  Row 4 has 4 fields but the header has 3"));
+    }
+
+    #[test]
+    fn test_render_sample_values() {
+        let pairs: Vec<String> = ["ID=s1", "R1=a.fq", "threads=8", "OPTS=--x=1"]
+            .iter()
+            .map(|pair| pair.to_string())
+            .collect();
+        assert_eq!(
+            render_sample(
+                "bwa mem -t {threads/2} {R1} {R2} {?OPTS:{OPTS}} > {ID}.sam ${HOME}",
+                &pairs
+            )
+            .unwrap(),
+            "bwa mem -t 4 a.fq {R2} --x=1 > s1.sam ${HOME}"
+        );
+        let err = render_sample("echo {ID}", &["ID".to_string()]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_short_row_errors_unless_padded() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,R1,R2\na,a1,a2\nb,b1").unwrap();
+        let csv_file = csv_file.path().to_str().unwrap();
+
+        let err = read_jobs_from_csv(csv_file, "echo {ID} {R1} {R2}", &CsvOptions::default())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("Row 2 is missing columns: R2"));
+
+        let padded = CsvOptions {
+            pad_short_rows: true,
+            ..Default::default()
+        };
+        let jobs = read_jobs_from_csv(csv_file, "echo {ID} {R1} {R2}", &padded).unwrap();
+        assert_eq!(jobs, vec!["echo a a1 a2", "echo b b1 "]);
+    }
+
+    #[test]
+    fn test_bom_and_latin1_headers() {
+        let mut bom_csv = NamedTempFile::new().unwrap();
+        bom_csv.write_all(b"\xef\xbb\xbfID,R1\ns1,a.fq\n").unwrap();
+        let jobs = read_jobs_from_csv(
+            bom_csv.path().to_str().unwrap(),
+            "echo {ID} {R1}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(jobs, vec!["echo s1 a.fq"]);
+
+        // "Caf\xe9" is "Café" in Latin-1 and invalid as UTF-8
+        let latin1 = CsvOptions {
+            encoding: Some("latin1".to_string()),
+            ..Default::default()
+        };
+        let mut latin1_csv = NamedTempFile::new().unwrap();
+        latin1_csv.write_all(b"ID,Name\ns1,Caf\xe9\n").unwrap();
+        let jobs = read_jobs_from_csv(
+            latin1_csv.path().to_str().unwrap(),
+            "echo {ID} {Name}",
+            &latin1,
+        )
+        .unwrap();
+        assert_eq!(jobs, vec!["echo s1 Café"]);
+    }
+
+    #[test]
+    fn test_absolute_paths_in_dir_mode() {
+        // A relative --dir, as given from the submit host's working directory
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("s1_1.fq"), "").unwrap();
+        fs::write(dir.path().join("s1_2.fq"), "").unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        let up = "../".repeat(cwd.components().count() - 1);
+        let relative = format!("{}{}", up, dir.path().strip_prefix("/").unwrap().display());
+
+        let jobs =
+            read_jobs_from_dir(&relative, "echo {R1} {R2}", &PairOptions::default()).unwrap();
+        assert_eq!(
+            jobs,
+            vec![format!("echo {0}/s1_1.fq {0}/s1_2.fq", relative)]
+        );
+
+        let jobs = read_jobs_from_dir(
+            &relative,
+            "echo {R1} {R2}",
+            &PairOptions {
+                absolute_paths: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let expected = fs::canonicalize(dir.path()).unwrap();
+        assert!(expected.is_absolute());
+        assert_eq!(
+            jobs,
+            vec![format!("echo {0}/s1_1.fq {0}/s1_2.fq", expected.display())]
+        );
+    }
+
+    #[test]
+    fn test_concatenated_csvs() {
+        let mut run1 = NamedTempFile::new().unwrap();
+        writeln!(run1, "ID,R1\ns1,a.fq\ns2,b.fq").unwrap();
+        let mut run2 = NamedTempFile::new().unwrap();
+        writeln!(run2, "R1,ID\nc.fq,s3").unwrap();
+        let mut other = NamedTempFile::new().unwrap();
+        writeln!(other, "ID,READS\ns4,d.fq").unwrap();
+        let path = |file: &NamedTempFile| file.path().to_str().unwrap().to_string();
+
+        let jobs = read_jobs_from_csvs(
+            &[path(&run1), path(&run2)],
+            "echo {ROW} {ID} {R1}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            jobs,
+            vec!["echo 1 s1 a.fq", "echo 2 s2 b.fq", "echo 3 s3 c.fq"]
+        );
+
+        let err = read_jobs_from_csvs(
+            &[path(&run1), path(&other)],
+            "echo {ID}",
+            &CsvOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("has columns ID,READS"));
+    }
+
+    #[test]
+    fn test_dir_pairing_diagnostics() {
+        let template = "echo {R1} {R2}";
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().to_str().unwrap();
+
+        let err = read_jobs_from_dir(dir_path, template, &PairOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("is empty"));
+
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let err = read_jobs_from_dir(dir_path, template, &PairOptions::default()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("1 files found but none match the pairing pattern")
+        );
+
+        for name in ["s1_1.fq", "s1_2.fq", "s2_1.fq", "s3_2.fq"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let err = read_jobs_from_dir(dir_path, template, &PairOptions::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Found 1 complete pairs but 2 are incomplete: s2 (missing R2), s3 (missing R1)"
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_placeholders() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,n\ns1,3\ns2,10").unwrap();
+        let csv_path = csv_file.path().to_str().unwrap();
+
+        let jobs = read_jobs_from_csv(
+            csv_path,
+            "run {ID} -t {n*2} -h {n / 2} -m {n-n}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            jobs,
+            vec!["run s1 -t 6 -h 1 -m 0", "run s2 -t 20 -h 5 -m 0"]
+        );
+
+        let err = read_jobs_from_csv(csv_path, "run {ID*2}", &CsvOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("not a whole number"));
+        assert!(read_jobs_from_csv(csv_path, "run {n/0}", &CsvOptions::default()).is_err());
+        let jobs = read_jobs_from_csv(csv_path, "echo ${x-1}", &CsvOptions::default()).unwrap();
+        assert_eq!(jobs[0], "echo ${x-1}");
+    }
+
+    #[test]
+    fn test_diff_shows_changed_commands() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,THREADS").unwrap();
+        for i in 1..=10 {
+            writeln!(csv_file, "s{},{}", i, if i == 9 { 8 } else { 4 }).unwrap();
+        }
+        let csv_file = csv_file.path().to_str().unwrap();
+        let previous =
+            read_jobs_from_csv(csv_file, "run {ID} --threads 4", &CsvOptions::default()).unwrap();
+        let current = read_jobs_from_csv(
+            csv_file,
+            "run {ID} --threads {THREADS}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+
+        let diff = diff_commands(&previous, &current, "old.log", "new");
+        assert!(diff.starts_with("--- old.log\n+++ new\n"));
+        assert!(diff.contains("@@ -6,5 +6,5 @@\n"));
+        assert!(diff.contains("\n-run s9 --threads 4\n+run s9 --threads 8\n"));
+        assert!(diff.contains(" run s6 --threads 4\n"));
+        assert!(!diff.contains("run s5 "));
+
+        assert!(diff_commands(&previous, &previous, "a", "b").is_empty());
+    }
+
+    #[test]
+    fn test_json_input() {
+        let mut strings = NamedTempFile::new().unwrap();
+        writeln!(strings, r#"["echo a", "echo b"]"#).unwrap();
+        let jobs = read_jobs_from_json(strings.path().to_str().unwrap(), "").unwrap();
+        assert_eq!(jobs, vec!["echo a", "echo b"]);
+
+        let mut objects = NamedTempFile::new().unwrap();
+        writeln!(
+            objects,
+            r#"[{{"ID": "s1", "threads": 4, "extra": null}}, {{"ID": "s2", "threads": 8}}]"#
+        )
+        .unwrap();
+        let objects = objects.path().to_str().unwrap();
+        let jobs = read_jobs_from_json(objects, "run {ID} -t {threads} {ROW}{extra}").unwrap();
+        assert_eq!(jobs, vec!["run s1 -t 4 1", "run s2 -t 8 2{extra}"]);
+        assert_eq!(
+            read_json_fields(objects).unwrap(),
+            vec!["ID", "extra", "threads", "ROW"]
+        );
+        assert!(read_jobs_from_json(objects, "").is_err());
+
+        let mut scalar = NamedTempFile::new().unwrap();
+        writeln!(scalar, "[1, 2]").unwrap();
+        assert!(read_jobs_from_json(scalar.path().to_str().unwrap(), "echo").is_err());
+    }
+
+    #[test]
+    fn test_env_placeholders_are_masked() {
+        let template = "curl -H 'X: '\"{env:FOO}\" {env:BAR:-fallback} {ID}";
+        let env = |name: &str| (name == "FOO").then(|| "s3cret".to_string());
+        assert!(check_env_placeholders(template, env).unwrap());
+        assert!(!check_env_placeholders("echo {ID}", env).unwrap());
+        assert!(check_env_placeholders("{env:BAR}", env).is_err());
+
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID\ns1").unwrap();
+        let jobs = read_jobs_from_csv(
+            csv_file.path().to_str().unwrap(),
+            template,
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        let logged = mask_env_placeholders(&jobs[0]);
+        assert_eq!(logged, "curl -H 'X: '\"${FOO}\" ${BAR:-fallback} s1");
+        assert!(!logged.contains("s3cret"));
+
+        // The task's shell resolves the secret from its environment
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(logged.replacen("curl -H", "echo", 1))
+            .env("FOO", "s3cret")
+            .env_remove("BAR")
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "X: s3cret fallback s1\n"
+        );
+    }
+
+    #[test]
+    fn test_template_vars_fill_every_row() {
+        let mut vars_file = NamedTempFile::new().unwrap();
+        writeln!(
+            vars_file,
+            "ref = \"/refs/GRCh38.fa\"\nthreads = 8\nID = \"ignored\""
+        )
+        .unwrap();
+        let vars = read_template_vars(vars_file.path().to_str().unwrap()).unwrap();
+        assert_eq!(vars["threads"], "8");
+
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID\ns1\ns2").unwrap();
+        let csv_file = csv_file.path().to_str().unwrap();
+        let headers = read_csv_headers(csv_file, &CsvOptions::default()).unwrap();
+        let template = apply_template_vars(
+            "bwa mem -t {threads} {ref} {ID}.fq # {ROW}",
+            &vars,
+            &headers,
+            ("{", "}"),
+        );
+        let jobs = read_jobs_from_csv(csv_file, &template, &CsvOptions::default()).unwrap();
+        assert_eq!(
+            jobs,
+            vec![
+                "bwa mem -t 8 /refs/GRCh38.fa s1.fq # 1",
+                "bwa mem -t 8 /refs/GRCh38.fa s2.fq # 2"
+            ]
+        );
+
+        let mut nested = NamedTempFile::new().unwrap();
+        writeln!(nested, "[paths]\nref = \"x\"").unwrap();
+        assert!(read_template_vars(nested.path().to_str().unwrap()).is_err());
+    }
+}
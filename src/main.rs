@@ -1,10 +1,26 @@
+#[macro_use]
+mod output;
+
 mod args;
+mod check;
+mod clean;
+mod cleanup;
+mod collect;
+mod config;
+mod doctor;
+mod export;
 mod jobs;
+mod manifest;
+mod run;
+mod scheduler;
 mod submission;
+mod tui;
 
-use clap::Subcommand;
-use std::process::Command;
-use submission::InputFormat;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Subcommand};
+use manifest::RerunOverrides;
+use std::io::IsTerminal;
+use submission::{InputFormat, SubmitOptions};
 
 #[derive(Subcommand)]
 enum SubCommands {
@@ -15,150 +31,660 @@ enum SubCommands {
     },
 }
 
-fn check_jobs(job_id: &str) {
-    let output = Command::new("bjobs")
-        .arg("-noheader")
-        .arg("-o")
-        .arg("job_name stat exit_code")
-        .arg(job_id)
-        .output()
-        .expect("Failed to check job status");
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut failed_jobs = Vec::new();
-    let mut all_done = true;
-    let mut running_count = 0;
-    let mut pending_count = 0;
-    let mut done_count = 0;
-
-    for line in stdout.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 3 {
-            let array_name = parts[0];
-            let status = parts[1];
-            let exit_code = parts[2];
-
-            match status {
-                "EXIT" => {
-                    all_done = false;
-                    let reason = match exit_code {
-                        "2" => "Killed 💀",
-                        "130" => "Memory error 🧠💾",
-                        "137" => "Killed (OOM) 💀🛑💾",
-                        "143" => "Timeout ⏳",
-                        _ => "Unknown error ❓🚨",
-                    };
-                    failed_jobs.push((
-                        array_name.to_string(),
-                        exit_code.to_string(),
-                        reason.to_string(),
-                    ));
-                }
-                "RUN" => {
-                    all_done = false;
-                    running_count += 1;
-                }
-                "PEND" => {
-                    all_done = false;
-                    pending_count += 1;
-                }
-                "DONE" => {
-                    done_count += 1;
+fn main() {
+    let matches = args::parse_args();
+    output::set_no_emoji(matches.get_flag("no_emoji") || output::no_emoji_from_env());
+
+    // Only commands that actually talk to the scheduler need one to be found
+    if let Some((name, sub_matches)) = matches.subcommand() {
+        let needs_scheduler = match name {
+            "history" | "doctor" | "clean" | "collect" | "export" | "render" => false,
+            "sub" | "run-until-done" => {
+                !sub_matches.get_flag("dry_run")
+                    && !sub_matches.get_flag("profile_readers")
+                    && sub_matches.get_one::<String>("emit_script").is_none()
+                    && !sub_matches.get_flag("local")
+                    // The scheduler is on the remote host, not on this PATH
+                    && sub_matches.get_one::<String>("submit_host").is_none()
+            }
+            _ => true,
+        };
+        if needs_scheduler {
+            let name = sub_matches.get_one::<String>("scheduler").unwrap();
+            let path = std::env::var_os("PATH").unwrap_or_default();
+            match scheduler::resolve_scheduler(name, &path) {
+                Ok(scheduler::Scheduler::Lsf) => {}
+                Ok(other) => {
+                    eprintln!(
+                        "Error: arrayify only supports LSF so far, but {} was selected",
+                        other
+                    );
+                    std::process::exit(1);
                 }
-                _ => {
-                    all_done = false;
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
                 }
             }
         }
     }
 
-    if all_done && failed_jobs.is_empty() {
-        println!("✅ All jobs in array {} completed successfully!", job_id);
-    } else {
-        if running_count > 0 {
-            println!("🚀 {} jobs are currently running!", running_count);
-        }
-        if pending_count > 0 {
-            println!("⏳ {} jobs are still pending!", pending_count);
-        }
-        if done_count > 0 {
-            println!("✅ {} jobs have completed successfully!", done_count);
-        }
-        if !failed_jobs.is_empty() {
-            println!("❌ Some jobs in array {} had issues:", job_id);
-            for (array_name, code, reason) in failed_jobs {
-                println!("  - {} Exit Code {}: {}", array_name, code, reason);
-            }
+    // Site-specific exit code reasons shown by check and run-until-done
+    if let Some(path) = config::default_exit_codes_path() {
+        match check::ExitReasons::load(&path) {
+            Ok(reasons) => check::set_exit_reasons(reasons),
+            Err(e) => eoutln!("⚠️ Ignoring exit code reasons: {}", e),
         }
     }
-}
-
-fn main() {
-    let matches = args::parse_args();
 
     match matches.subcommand() {
         Some(("sub", sub_matches)) => {
-            let csv_file = sub_matches.get_one::<String>("csv");
-            let dir_path = sub_matches.get_one::<String>("dir");
-
-            // Ensure only one of csv_file or dir_path is provided
-            if csv_file.is_some() && dir_path.is_some() {
-                eprintln!("Error: Cannot provide both --csv and --dir at the same time");
+            let options = submit_options(sub_matches);
+            cleanup::install_handler();
+            submission::submit_jobs(&options).expect("Job submission failed");
+        }
+        Some(("run-until-done", run_matches)) => {
+            let options = submit_options(run_matches);
+            let policy = run::RetryPolicy {
+                max_attempts: *run_matches.get_one::<u32>("max_attempts").unwrap(),
+                memory_factor: *run_matches.get_one::<u32>("memory_factor").unwrap(),
+                interval: std::time::Duration::from_secs(
+                    *run_matches.get_one::<u64>("interval").unwrap(),
+                ),
+                ..Default::default()
+            };
+            cleanup::install_handler();
+            if let Err(e) = run::run_until_done(
+                &options,
+                &policy,
+                submission::submit_jobs,
+                check::query_bjobs,
+                check::query_bacct,
+            ) {
+                eprintln!("Error: {}", e);
                 std::process::exit(1);
             }
-
-            let command_template = sub_matches.get_one::<String>("command").unwrap();
-            let job_prefix = sub_matches.get_one::<String>("job_prefix").unwrap();
-            let log_dir = sub_matches.get_one::<String>("log").unwrap();
-            let memory_gb: u32 = sub_matches
-                .get_one::<String>("memory")
-                .unwrap()
-                .parse()
-                .expect("Memory must be a valid number in GB");
-            let threads: u32 = sub_matches
-                .get_one::<String>("threads")
+        }
+        Some(("check", check_matches)) => {
+            let job_ids: Vec<String> = check_matches
+                .get_many::<String>("job_id")
                 .unwrap()
-                .parse()
-                .expect("Threads must be a valid number");
-            let batch_size = sub_matches
-                .get_one::<String>("batch_size")
-                .map(|value| {
-                    if value == "auto" {
-                        None
-                    } else {
-                        value.parse::<usize>().ok()
+                .map(|id_or_name| check::resolve_job_id(id_or_name, check::query_job_name))
+                .collect::<Result<_, _>>()
+                .unwrap_or_else(|e| {
+                    eoutln!("Error: {}", e);
+                    std::process::exit(1);
+                });
+            if job_ids.len() > 1 {
+                if check_matches.get_flag("watch") || check_matches.get_flag("tui") {
+                    eprintln!("Error: --watch and --tui take a single job ID");
+                    std::process::exit(1);
+                }
+                match check::summarise_arrays(&job_ids, check::query_bjobs) {
+                    Ok(summary) => out!("{}", summary),
+                    Err(e) => {
+                        eprintln!("Error: Failed to check job status: {}", e);
+                        std::process::exit(1);
                     }
+                }
+                return;
+            }
+
+            let job_id = &job_ids[0];
+            let log_dir = check_matches.get_one::<String>("log").unwrap();
+            if let Ok(manifest::Manifest {
+                note: Some(note), ..
+            }) = manifest::find_manifest(log_dir, job_id)
+            {
+                outln!("📝 {}", note);
+            }
+            let hooks = check::Hooks {
+                on_success: check_matches.get_one::<String>("on_success").cloned(),
+                on_failure: check_matches.get_one::<String>("on_failure").cloned(),
+            };
+            let interval = *check_matches.get_one::<u64>("interval").unwrap();
+            let tui = check_matches.get_flag("tui");
+            if tui && !std::io::stdout().is_terminal() {
+                eoutln!("⚠️ stdout is not a terminal; showing --watch output instead of --tui");
+            }
+            let status = if tui && std::io::stdout().is_terminal() {
+                let status = tui::run_dashboard(
+                    job_id,
+                    std::time::Duration::from_secs(interval),
+                    check::query_bjobs,
+                )
+                .unwrap_or_else(|e| {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                });
+                check::print_status(job_id, &status);
+                let fired = if status.is_finished() {
+                    hooks.fire(job_id, &status)
+                } else {
+                    Ok(())
+                };
+                if let Err(e) = fired {
+                    eoutln!("⚠️ Could not run hook: {}", e);
+                }
+                status
+            } else if tui || check_matches.get_flag("watch") {
+                check::watch_jobs(
+                    job_id,
+                    std::time::Duration::from_secs(interval),
+                    &hooks,
+                    check::query_bjobs,
+                    check::query_bacct,
+                )
+                .unwrap_or_else(|e| {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
                 })
-                .unwrap_or(None);
-            let queue = sub_matches.get_one::<String>("queue").unwrap();
-
-            // Determine the input format and set input_path
-            let (format, input_path) = if let Some(csv) = csv_file {
-                (InputFormat::Csv, csv)
-            } else if let Some(dir) = dir_path {
-                (InputFormat::Directory, dir)
             } else {
-                eprintln!("Error: Either --csv or --dir must be provided");
+                check::check_jobs(job_id, &hooks)
+            };
+
+            if check_matches.get_flag("why") && status.pending > 0 {
+                match check::explain_pending(job_id, check::query_pending_reasons) {
+                    Ok(explanation) => out!("{}", explanation),
+                    Err(e) => eoutln!("⚠️ Could not query pending reasons: {}", e),
+                }
+            }
+
+            if let Some(dump_path) = check_matches.get_one::<String>("dump_failed") {
+                let dumped = manifest::find_manifest(log_dir, job_id).and_then(|manifest| {
+                    check::dump_failed(
+                        &status,
+                        &manifest.log_file,
+                        manifest.options.chunk,
+                        dump_path,
+                    )
+                });
+                match dumped {
+                    Ok(count) => outln!("📝 {} failed commands written to {}", count, dump_path),
+                    Err(e) => {
+                        eprintln!("Error: Could not write failed commands: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+        Some(("usage", usage_matches)) => {
+            let job_id = usage_matches.get_one::<String>("job_id").unwrap();
+            let log_dir = usage_matches.get_one::<String>("log").unwrap();
+            let output = check::query_usage(job_id).unwrap_or_else(|e| {
+                eprintln!("Error: Failed to query usage: {}", e);
+                std::process::exit(1);
+            });
+            let requested_gb = manifest::find_manifest(log_dir, job_id)
+                .ok()
+                .map(|manifest| manifest.memory_gb());
+            out!(
+                "{}",
+                check::format_usage_report(&check::parse_usage(&output), requested_gb)
+            );
+        }
+        Some(("export", export_matches)) => {
+            let options = input_options(export_matches);
+            let format = export::ExportFormat::from_name(
+                export_matches.get_one::<String>("format").unwrap(),
+            )
+            .unwrap();
+            match submission::rendered_commands(&options) {
+                Ok(jobs) => print!("{}", export::export_jobs(&jobs, format)),
+                Err(e) => {
+                    eprintln!("Error: Could not render the jobs: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("render", render_matches)) => {
+            let template = render_matches.get_one::<String>("command").unwrap();
+            let pairs: Vec<String> = render_matches
+                .get_many::<String>("set")
+                .unwrap_or_default()
+                .cloned()
+                .collect();
+            match jobs::render_sample(template, &pairs) {
+                Ok(command) => println!("{}", command),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("release", release_matches)) => {
+            let job_id = release_matches.get_one::<String>("job_id").unwrap();
+            if let Err(e) = submission::release_jobs(job_id) {
+                eprintln!("Error: {}", e);
                 std::process::exit(1);
+            }
+        }
+        Some(("rerun", rerun_matches)) => {
+            let job_id = rerun_matches.get_one::<String>("job_id").unwrap();
+            let log_dir = rerun_matches.get_one::<String>("log").unwrap();
+
+            let manifest = manifest::find_manifest(log_dir, job_id).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            });
+            let options = manifest.rerun_options(RerunOverrides {
+                command_template: rerun_matches.get_one::<String>("command").cloned(),
+                memory_gb: rerun_matches.get_one::<u32>("memory").copied(),
+                threads: rerun_matches.get_one::<u32>("threads").copied(),
+                queue: rerun_matches.get_one::<String>("queue").cloned(),
+                exclude_hosts: rerun_matches
+                    .get_many::<String>("exclude_host")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect(),
+            });
+
+            // The queue may have been reconfigured since the original submission
+            match submission::queue_limit_warnings(&options, submission::query_queue) {
+                Ok(warnings) => {
+                    for warning in warnings {
+                        eoutln!("{}", warning);
+                    }
+                }
+                Err(e) => eoutln!("⚠️ Could not check the queue's current limits: {}", e),
+            }
+
+            cleanup::install_handler();
+            submission::submit_jobs(&options).expect("Job submission failed");
+        }
+        Some(("collect", collect_matches)) => {
+            let job_id = collect_matches.get_one::<String>("job_id").unwrap();
+            let log_dir = collect_matches.get_one::<String>("log").unwrap();
+            let stream =
+                collect::Stream::from_name(collect_matches.get_one::<String>("stream").unwrap())
+                    .unwrap();
+
+            let collected = match collect_matches.get_one::<String>("output") {
+                Some(output) => std::fs::File::create(output)
+                    .and_then(|mut file| collect::collect_logs(log_dir, job_id, stream, &mut file)),
+                None => collect::collect_logs(log_dir, job_id, stream, &mut std::io::stdout()),
             };
+            match collected {
+                Ok(0) => eprintln!("No task output for job {} found in {}", job_id, log_dir),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Error: Could not collect task output: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("clean", clean_matches)) => {
+            let log_dir = clean_matches.get_one::<String>("log").unwrap();
+            let older_than = *clean_matches
+                .get_one::<std::time::Duration>("older_than")
+                .unwrap();
+            let dry_run = clean_matches.get_flag("dry_run");
 
-            submission::submit_jobs(
-                input_path,
-                command_template,
-                job_prefix,
-                log_dir,
-                memory_gb,
-                threads,
-                queue,
-                batch_size,
+            let files = clean::clean_logs(log_dir, older_than, dry_run).unwrap_or_else(|e| {
+                eprintln!("Error: Could not clean {}: {}", log_dir, e);
+                std::process::exit(1);
+            });
+            if dry_run {
+                for path in &files {
+                    println!("   {}", path.display());
+                }
+                outln!("🧪 Dry run: {} files would be removed", files.len());
+            } else {
+                outln!("🧹 Removed {} files from {}", files.len(), log_dir);
+            }
+        }
+        Some(("doctor", doctor_matches)) => {
+            let format = if doctor_matches.contains_id("dir") {
+                Some((InputFormat::Directory, "dir"))
+            } else if doctor_matches.contains_id("glob") {
+                Some((InputFormat::Glob, "glob"))
+            } else if doctor_matches.contains_id("list") {
+                Some((InputFormat::List, "list"))
+            } else if doctor_matches.contains_id("csv") {
+                Some((InputFormat::Csv, "csv"))
+            } else {
+                None
+            };
+            let options = format.map(|(format, id)| SubmitOptions {
+                input_path: doctor_matches.get_one::<String>(id).unwrap().clone(),
+                command_template: doctor_matches.get_one::<String>("command").unwrap().clone(),
                 format,
-            )
-            .expect("Job submission failed");
+                ..Default::default()
+            });
+            let scheduler_name = doctor_matches.get_one::<String>("scheduler").unwrap();
+            let path = std::env::var_os("PATH").unwrap_or_default();
+            let diagnosis =
+                doctor::diagnose(scheduler_name, &path, std::env::vars(), options.as_ref());
+            out!("{}", doctor::format_diagnosis(&diagnosis));
         }
-        Some(("check", check_matches)) => {
-            let job_id = check_matches.get_one::<String>("job_id").unwrap();
-            check_jobs(job_id);
+        Some(("history", history_matches)) => {
+            let log_dir = history_matches.get_one::<String>("log").unwrap();
+            let limit = *history_matches.get_one::<usize>("limit").unwrap();
+
+            let history = manifest::load_history(log_dir, limit).unwrap_or_else(|e| {
+                eprintln!("Error: Could not read history from {}: {}", log_dir, e);
+                std::process::exit(1);
+            });
+            if history.is_empty() {
+                println!("No submissions found in {}", log_dir);
+            } else {
+                print!("{}", manifest::format_history(&history));
+            }
         }
         _ => {}
     }
 }
+
+/// Builds the submission options from `sub`'s arguments, applying any
+/// `--profile` for values not given on the command line.
+fn submit_options(sub_matches: &ArgMatches) -> SubmitOptions {
+    let job_prefix = sub_matches.get_one::<String>("job_prefix").unwrap();
+    let log_dir = sub_matches.get_one::<String>("log").unwrap();
+    let memory_gb: u32 = sub_matches
+        .get_one::<String>("memory")
+        .unwrap()
+        .parse()
+        .expect("Memory must be a valid number in GB");
+    let threads: u32 = sub_matches
+        .get_one::<String>("threads")
+        .unwrap()
+        .parse()
+        .expect("Threads must be a valid number");
+    let batch_size = sub_matches
+        .get_one::<String>("batch_size")
+        .map(|value| {
+            if value == "auto" {
+                None
+            } else {
+                value.parse::<usize>().ok()
+            }
+        })
+        .unwrap_or(None);
+    let min_batch = sub_matches.get_one::<usize>("min_batch").copied();
+    let batch_of_queue = sub_matches.get_one::<u32>("batch_of_queue").copied();
+    let submit_host = sub_matches.get_one::<String>("submit_host").cloned();
+    let index_range = sub_matches
+        .get_one::<(usize, usize)>("index_range")
+        .copied();
+    let queue = sub_matches.get_one::<String>("queue").unwrap();
+    let time_minutes = sub_matches.get_one::<u32>("time").copied();
+    let min_time_minutes = *sub_matches.get_one::<u32>("min_time").unwrap();
+    let chunk = *sub_matches.get_one::<usize>("chunk").unwrap();
+    let prolog = sub_matches.get_one::<String>("prolog").cloned();
+    let epilog = sub_matches.get_one::<String>("epilog").cloned();
+    let strict_bash = !sub_matches.get_flag("no_strict_bash");
+    let priority = sub_matches.get_one::<u32>("priority").copied();
+    let max_array_size = sub_matches
+        .get_one::<u32>("max_array_size")
+        .map(|&n| n as usize);
+    let diff_against = sub_matches.get_one::<String>("diff_against").cloned();
+    let no_log = sub_matches.get_flag("no_log");
+    let task_retries = *sub_matches.get_one::<u32>("task_retries").unwrap();
+    let retry_sleep_secs = *sub_matches.get_one::<u64>("retry_sleep").unwrap();
+    let summary_file = sub_matches.get_one::<String>("summary_file").cloned();
+    let group = sub_matches.get_one::<String>("group").cloned();
+    let log_header = sub_matches.get_flag("log_header");
+    let parallel_submit = sub_matches.get_flag("parallel_submit");
+    let after_array = sub_matches.get_one::<u64>("after_array").copied();
+    let seed = sub_matches.get_one::<u32>("seed").copied();
+    let reservation = sub_matches.get_one::<String>("reservation").cloned();
+    let interpreter = sub_matches.get_one::<String>("interpreter").cloned();
+    let max_total_mem_gb = sub_matches.get_one::<u32>("max_total_mem").copied();
+    let max_total_slots = sub_matches.get_one::<u32>("max_total_slots").copied();
+    let exit_codes = sub_matches.get_flag("exit_codes");
+    let warn_collisions = sub_matches.get_flag("warn_collisions");
+    let local = sub_matches.get_flag("local");
+    let local_jobs = sub_matches
+        .get_one::<u64>("local_jobs")
+        .map(|jobs| *jobs as usize);
+    let output_columns: Vec<String> = sub_matches
+        .get_many::<String>("output_columns")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let max_pending = sub_matches.get_one::<usize>("max_pending").copied();
+    let dry_run = sub_matches.get_flag("dry_run");
+    let stagger_secs = sub_matches.get_one::<u64>("stagger").copied();
+    let emit_script = sub_matches.get_one::<String>("emit_script").cloned();
+    let exclude_hosts: Vec<String> = sub_matches
+        .get_many::<String>("exclude_host")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    let mem_per_gb_input = sub_matches.get_one::<u32>("mem_per_gb_input").copied();
+    let shellcheck = sub_matches.get_flag("shellcheck");
+    let force = sub_matches.get_flag("force");
+    let profile_readers = sub_matches.get_flag("profile_readers");
+    let hold = sub_matches.get_flag("hold");
+    let verify = sub_matches.get_flag("verify");
+    let tee = sub_matches.get_one::<String>("tee").cloned();
+    let no_duplicate = sub_matches.get_flag("no_duplicate");
+    let duplicate_window_hours = *sub_matches.get_one::<u32>("duplicate_window").unwrap();
+    let keep_script = sub_matches.get_one::<String>("keep_script").cloned();
+    let smoke_test = sub_matches.get_flag("smoke_test");
+    let smoke_timeout_secs = sub_matches.get_one::<u64>("smoke_timeout").copied();
+    let note = sub_matches.get_one::<String>("note").cloned();
+    let rusage = sub_matches.get_one::<String>("rusage").cloned();
+    let resource_select = sub_matches.get_one::<String>("resource_select").cloned();
+    let memory_column = sub_matches.get_one::<String>("memory_column").cloned();
+    let threads_column = sub_matches.get_one::<String>("threads_column").cloned();
+    let check_paths = sub_matches.get_flag("check_paths");
+    let path_columns: Vec<String> = sub_matches
+        .get_many::<String>("path_columns")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+
+    let mut options = SubmitOptions {
+        job_prefix: job_prefix.to_string(),
+        log_dir: log_dir.to_string(),
+        memory_gb,
+        threads,
+        time_minutes,
+        queue: queue.to_string(),
+        batch_size,
+        prolog,
+        epilog,
+        strict_bash,
+        max_pending,
+        dry_run,
+        stagger_secs,
+        emit_script,
+        exclude_hosts,
+        mem_per_gb_input,
+        shellcheck,
+        force,
+        profile_readers,
+        hold,
+        verify,
+        no_duplicate,
+        duplicate_window_hours,
+        keep_script,
+        smoke_test,
+        smoke_timeout_secs,
+        note,
+        check_paths,
+        path_columns,
+        rusage,
+        threads_column,
+        memory_column,
+        min_time_minutes,
+        chunk,
+        min_batch,
+        index_range,
+        tee,
+        resource_select,
+        batch_of_queue,
+        submit_host,
+        priority,
+        max_array_size,
+        diff_against,
+        no_log,
+        task_retries,
+        retry_sleep_secs,
+        summary_file,
+        group,
+        log_header,
+        parallel_submit,
+        after_array,
+        seed,
+        reservation,
+        interpreter,
+        max_total_mem_gb,
+        max_total_slots,
+        exit_codes,
+        warn_collisions,
+        output_columns,
+        local,
+        local_jobs,
+        ..input_options(sub_matches)
+    };
+
+    if let Some(profile_name) = sub_matches.get_one::<String>("profile") {
+        let config_path = sub_matches
+            .get_one::<String>("config")
+            .map(std::path::PathBuf::from)
+            .or_else(config::default_config_path)
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "Error: --profile needs a config file (set --config or $ARRAYIFY_CONFIG)"
+                );
+                std::process::exit(1);
+            });
+        let config = config::load_config(&config_path).unwrap_or_else(|e| {
+            eprintln!(
+                "Error: Could not read config {}: {}",
+                config_path.display(),
+                e
+            );
+            std::process::exit(1);
+        });
+        let profile = config.profile(profile_name).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+        profile.apply(&mut options, |flag| {
+            sub_matches.value_source(flag) == Some(ValueSource::CommandLine)
+        });
+    }
+
+    options
+}
+
+/// Options choosing the input and rendering its commands, shared by `sub` and
+/// `export`; everything else keeps its default.
+fn input_options(sub_matches: &ArgMatches) -> SubmitOptions {
+    let csv_file = sub_matches.get_one::<String>("csv");
+    let extra_inputs: Vec<String> = sub_matches
+        .get_many::<String>("csv")
+        .unwrap_or_default()
+        .skip(1)
+        .cloned()
+        .collect();
+    let dir_path = sub_matches.get_one::<String>("dir");
+    let glob_pattern = sub_matches.get_one::<String>("glob");
+    let list_file = sub_matches.get_one::<String>("list");
+
+    // Ensure only one of csv_file or dir_path is provided
+    if csv_file.is_some() && dir_path.is_some() {
+        eprintln!("Error: Cannot provide both --csv and --dir at the same time");
+        std::process::exit(1);
+    }
+
+    let jobs_file = sub_matches.get_one::<String>("jobs_file");
+    let json_input = sub_matches.get_one::<String>("json_input");
+    let command_template = sub_matches
+        .get_one::<String>("command")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let template_column = sub_matches.get_one::<String>("template_column").cloned();
+    let delimiters = sub_matches.get_one::<String>("delim").map(|delim| {
+        let markers: Vec<&str> = delim.split_whitespace().collect();
+        match markers.as_slice() {
+            [marker] => (marker.to_string(), marker.to_string()),
+            [open, close] => (open.to_string(), close.to_string()),
+            _ => {
+                eprintln!(
+                    "Error: --delim expects one marker or an opening and closing marker, got '{}'",
+                    delim
+                );
+                std::process::exit(1);
+            }
+        }
+    });
+    let placeholder_aliases: Vec<(String, String)> = sub_matches
+        .get_many::<String>("placeholder_alias")
+        .unwrap_or_default()
+        .map(|pair| match pair.split_once('=') {
+            Some((name, alias)) => (name.to_string(), alias.to_string()),
+            None => {
+                eprintln!(
+                    "Error: --placeholder-alias expects NAME=ALIAS, got '{}'",
+                    pair
+                );
+                std::process::exit(1);
+            }
+        })
+        .collect();
+    // Determine the input format and set input_path
+    let (format, input_path) = if let Some(csv) = csv_file {
+        (InputFormat::Csv, csv)
+    } else if let Some(dir) = dir_path {
+        (InputFormat::Directory, dir)
+    } else if let Some(pattern) = glob_pattern {
+        (InputFormat::Glob, pattern)
+    } else if let Some(list) = list_file {
+        (InputFormat::List, list)
+    } else if let Some(jobs_file) = jobs_file {
+        (InputFormat::JobsFile, jobs_file)
+    } else if let Some(json_input) = json_input {
+        (InputFormat::Json, json_input)
+    } else {
+        eprintln!(
+            "Error: One of --csv, --dir, --glob, --list, --jobs-file or --json-input must be provided"
+        );
+        std::process::exit(1);
+    };
+    let dedup = sub_matches.get_flag("dedup");
+    let skip_empty = !sub_matches.get_flag("no_skip_empty");
+    let template_vars = sub_matches.get_one::<String>("vars").cloned();
+    let pre_submit_validate = sub_matches.get_flag("pre_submit_validate");
+    let comment_char = *sub_matches.get_one::<char>("comment_char").unwrap();
+    let pad_short_rows = sub_matches.get_flag("pad_short_rows");
+    let encoding = sub_matches.get_one::<String>("encoding").cloned();
+    let task_log = sub_matches.get_one::<String>("task_log").cloned();
+    let absolute_paths = sub_matches.get_flag("absolute_paths");
+    let reads_separator = sub_matches
+        .get_one::<String>("reads_separator")
+        .unwrap()
+        .clone();
+    let merge_lanes = sub_matches.get_one::<String>("merge_lanes").cloned();
+    let required_columns: Vec<String> = sub_matches
+        .get_many::<String>("require_columns")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+
+    SubmitOptions {
+        input_path: input_path.to_string(),
+        extra_inputs,
+        command_template: command_template.to_string(),
+        format,
+        dedup,
+        skip_empty,
+        comment_char,
+        pad_short_rows,
+        encoding,
+        required_columns,
+        template_column,
+        delimiters,
+        placeholder_aliases,
+        template_vars,
+        task_log,
+        absolute_paths,
+        reads_separator,
+        merge_lanes,
+        pre_submit_validate,
+        ..SubmitOptions::default()
+    }
+}
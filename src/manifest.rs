@@ -0,0 +1,369 @@
+use crate::submission::SubmitOptions;
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Record of a submission, written next to the command log so it can be rerun later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub job_id: String,
+    pub submitted_at: String,
+    pub num_jobs: usize,
+    pub log_file: String,
+    pub options: SubmitOptions,
+    /// Fingerprint of the rendered commands and resources, used to spot repeat submissions
+    #[serde(default)]
+    pub submission_hash: String,
+    /// Free-text description given with --note
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Memory in GB this array requested, which differs from `options` when the
+    /// submission was split by memory tier or resource columns
+    #[serde(default)]
+    pub requested_memory_gb: Option<u32>,
+    /// Threads this array requested, likewise
+    #[serde(default)]
+    pub requested_threads: Option<u32>,
+}
+
+/// Parameters given on the `rerun` command line that replace the stored ones.
+#[derive(Debug, Clone, Default)]
+pub struct RerunOverrides {
+    pub command_template: Option<String>,
+    pub memory_gb: Option<u32>,
+    pub threads: Option<u32>,
+    pub queue: Option<String>,
+    pub exclude_hosts: Vec<String>,
+}
+
+impl Manifest {
+    /// Memory in GB this array requested. Manifests written before it was
+    /// recorded fall back to the submission's.
+    pub fn memory_gb(&self) -> u32 {
+        self.requested_memory_gb.unwrap_or(self.options.memory_gb)
+    }
+
+    /// Threads this array requested, falling back to the submission's likewise.
+    pub fn threads(&self) -> u32 {
+        self.requested_threads.unwrap_or(self.options.threads)
+    }
+
+    /// Options for a fresh submission from the same input, inheriting anything not overridden.
+    pub fn rerun_options(&self, overrides: RerunOverrides) -> SubmitOptions {
+        let mut options = self.options.clone();
+        if let Some(command_template) = overrides.command_template {
+            options.command_template = command_template;
+        }
+        if let Some(memory_gb) = overrides.memory_gb {
+            options.memory_gb = memory_gb;
+        }
+        if let Some(threads) = overrides.threads {
+            options.threads = threads;
+        }
+        if let Some(queue) = overrides.queue {
+            options.queue = queue;
+        }
+        for host in overrides.exclude_hosts {
+            if !options.exclude_hosts.contains(&host) {
+                options.exclude_hosts.push(host);
+            }
+        }
+        options.dry_run = false;
+        options
+    }
+}
+
+pub fn write_manifest(manifest_path: &str, manifest: &Manifest) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(manifest)?;
+    fs::write(manifest_path, json)
+}
+
+pub fn read_manifest(manifest_path: &Path) -> io::Result<Manifest> {
+    let content = fs::read_to_string(manifest_path)?;
+    serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Lists the `arrayify-*.json` manifests in a log directory.
+pub fn list_manifests(log_dir: &str) -> io::Result<Vec<PathBuf>> {
+    let mut manifests = Vec::new();
+    for entry in fs::read_dir(log_dir)? {
+        let path = entry?.path();
+        let is_manifest = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .is_some_and(|name| name.starts_with("arrayify-") && name.ends_with(".json"));
+        if is_manifest {
+            manifests.push(path);
+        }
+    }
+    manifests.sort();
+    Ok(manifests)
+}
+
+/// Finds the manifest recorded for `job_id` in a log directory.
+pub fn find_manifest(log_dir: &str, job_id: &str) -> io::Result<Manifest> {
+    for path in list_manifests(log_dir)? {
+        // Skip anything that isn't a readable manifest rather than failing the search
+        let Ok(manifest) = read_manifest(&path) else {
+            continue;
+        };
+        if manifest.job_id == job_id {
+            return Ok(manifest);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No manifest for job {} found in {}", job_id, log_dir),
+    ))
+}
+
+/// Loads every manifest in a log directory, most recent submission first.
+pub fn load_history(log_dir: &str, limit: usize) -> io::Result<Vec<Manifest>> {
+    let mut manifests: Vec<Manifest> = list_manifests(log_dir)?
+        .iter()
+        .filter_map(|path| read_manifest(path).ok())
+        .collect();
+    manifests.sort_by(|a, b| b.submitted_at.cmp(&a.submitted_at));
+    manifests.truncate(limit);
+    Ok(manifests)
+}
+
+pub fn format_history(manifests: &[Manifest]) -> String {
+    let mut table = format!(
+        "{:<18} {:<10} {:>6} {:<12} {:<30} {}\n",
+        "SUBMITTED", "JOB_ID", "JOBS", "QUEUE", "INPUT", "NOTE"
+    );
+    for manifest in manifests {
+        let line = format!(
+            "{:<18} {:<10} {:>6} {:<12} {:<30} {}",
+            manifest.submitted_at,
+            manifest.job_id,
+            manifest.num_jobs,
+            manifest.options.queue,
+            manifest.options.input_path,
+            manifest.note.as_deref().unwrap_or("")
+        );
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Format of `Manifest::submitted_at`, which is also the log file timestamp.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d-%H-%M";
+
+/// Finds a submission in the log directory with the same fingerprint made
+/// within `window_hours` of `now`.
+pub fn find_recent_duplicate(
+    log_dir: &str,
+    submission_hash: &str,
+    window_hours: u32,
+    now: NaiveDateTime,
+) -> io::Result<Option<Manifest>> {
+    if !Path::new(log_dir).is_dir() {
+        return Ok(None);
+    }
+    let window = Duration::hours(window_hours as i64);
+    for path in list_manifests(log_dir)? {
+        let Ok(manifest) = read_manifest(&path) else {
+            continue;
+        };
+        let Ok(submitted_at) = NaiveDateTime::parse_from_str(
+            &format!("{}-00", manifest.submitted_at),
+            &format!("{}-%S", TIMESTAMP_FORMAT),
+        ) else {
+            continue;
+        };
+        if manifest.submission_hash == submission_hash && now - submitted_at <= window {
+            return Ok(Some(manifest));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::submission::InputFormat;
+
+    #[test]
+    fn test_rerun_reuses_input_and_overrides_memory() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_dir = log_dir.path().to_str().unwrap();
+        let manifest = Manifest {
+            job_id: "12345".to_string(),
+            submitted_at: "2026-01-01-10-00".to_string(),
+            num_jobs: 2,
+            log_file: format!("{}/arrayify-2026-01-01-10-00.log", log_dir),
+            options: SubmitOptions {
+                input_path: "samples.csv".to_string(),
+                command_template: "echo {ID}".to_string(),
+                format: InputFormat::Csv,
+                memory_gb: 4,
+                threads: 2,
+                ..Default::default()
+            },
+            submission_hash: String::new(),
+            note: None,
+            requested_memory_gb: None,
+            requested_threads: None,
+        };
+        write_manifest(
+            &format!("{}/arrayify-2026-01-01-10-00.json", log_dir),
+            &manifest,
+        )
+        .unwrap();
+
+        let found = find_manifest(log_dir, "12345").unwrap();
+        let options = found.rerun_options(RerunOverrides {
+            memory_gb: Some(16),
+            ..Default::default()
+        });
+
+        assert_eq!(options.input_path, "samples.csv");
+        assert_eq!(options.command_template, "echo {ID}");
+        assert_eq!(options.threads, 2);
+        assert_eq!(options.memory_gb, 16);
+        assert!(find_manifest(log_dir, "99999").is_err());
+    }
+
+    #[test]
+    fn test_history_lists_recent_first() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_dir = log_dir.path().to_str().unwrap();
+        for (job_id, submitted_at, input) in [
+            ("100", "2026-01-01-10-00", "old.csv"),
+            ("200", "2026-02-01-10-00", "new.csv"),
+        ] {
+            let manifest = Manifest {
+                job_id: job_id.to_string(),
+                submitted_at: submitted_at.to_string(),
+                num_jobs: 3,
+                log_file: format!("{}/arrayify-{}.log", log_dir, submitted_at),
+                options: SubmitOptions {
+                    input_path: input.to_string(),
+                    ..Default::default()
+                },
+                submission_hash: String::new(),
+                note: None,
+                requested_memory_gb: None,
+                requested_threads: None,
+            };
+            write_manifest(
+                &format!("{}/arrayify-{}.json", log_dir, submitted_at),
+                &manifest,
+            )
+            .unwrap();
+        }
+        fs::write(format!("{}/arrayify-2026-03-01-10-00.log", log_dir), "echo").unwrap();
+
+        let history = load_history(log_dir, 10).unwrap();
+        let job_ids: Vec<&str> = history.iter().map(|m| m.job_id.as_str()).collect();
+        assert_eq!(job_ids, vec!["200", "100"]);
+
+        let table = format_history(&history);
+        assert!(table.find("new.csv").unwrap() < table.find("old.csv").unwrap());
+        assert_eq!(load_history(log_dir, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_find_recent_duplicate() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_dir = log_dir.path().to_str().unwrap();
+        let manifest = Manifest {
+            job_id: "300".to_string(),
+            submitted_at: "2026-03-01-10-00".to_string(),
+            num_jobs: 1,
+            log_file: format!("{}/arrayify-2026-03-01-10-00.log", log_dir),
+            options: SubmitOptions::default(),
+            submission_hash: "abc123".to_string(),
+            note: None,
+            requested_memory_gb: None,
+            requested_threads: None,
+        };
+        write_manifest(
+            &format!("{}/arrayify-2026-03-01-10-00.json", log_dir),
+            &manifest,
+        )
+        .unwrap();
+
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        let found = find_recent_duplicate(log_dir, "abc123", 24, at("2026-03-01 18:00")).unwrap();
+        assert_eq!(found.unwrap().job_id, "300");
+
+        assert!(
+            find_recent_duplicate(log_dir, "abc123", 24, at("2026-03-03 10:00"))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            find_recent_duplicate(log_dir, "other", 24, at("2026-03-01 18:00"))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_note_round_trips_through_manifest() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_dir = log_dir.path().to_str().unwrap();
+        let manifest = Manifest {
+            job_id: "400".to_string(),
+            submitted_at: "2026-04-01-10-00".to_string(),
+            num_jobs: 1,
+            log_file: format!("{}/arrayify-2026-04-01-10-00.log", log_dir),
+            options: SubmitOptions {
+                note: Some("ignored".to_string()),
+                ..Default::default()
+            },
+            submission_hash: String::new(),
+            note: Some("re-run after reference update".to_string()),
+            requested_memory_gb: None,
+            requested_threads: None,
+        };
+        write_manifest(
+            &format!("{}/arrayify-2026-04-01-10-00.json", log_dir),
+            &manifest,
+        )
+        .unwrap();
+
+        let found = find_manifest(log_dir, "400").unwrap();
+        assert_eq!(found.note.as_deref(), Some("re-run after reference update"));
+        assert!(found.options.note.is_none());
+        assert!(format_history(&[found]).contains("re-run after reference update"));
+    }
+
+    #[test]
+    fn test_manifest_records_array_memory() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_dir = log_dir.path().to_str().unwrap();
+        let mut manifest = Manifest {
+            job_id: "500".to_string(),
+            submitted_at: "2026-04-01-10-00".to_string(),
+            num_jobs: 1,
+            log_file: format!("{}/arrayify-2026-04-01-10-00-mem12G.log", log_dir),
+            options: SubmitOptions {
+                memory_gb: 4,
+                ..Default::default()
+            },
+            submission_hash: String::new(),
+            note: None,
+            requested_memory_gb: None,
+            requested_threads: None,
+        };
+        // Manifests from before the array's own memory was recorded
+        assert_eq!(manifest.memory_gb(), 4);
+
+        manifest.requested_memory_gb = Some(12);
+        write_manifest(
+            &format!("{}/arrayify-2026-04-01-10-00-mem12G.json", log_dir),
+            &manifest,
+        )
+        .unwrap();
+        let found = find_manifest(log_dir, "500").unwrap();
+        assert_eq!(found.memory_gb(), 12);
+        assert_eq!(found.options.memory_gb, 4);
+    }
+}
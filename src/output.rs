@@ -0,0 +1,150 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static NO_EMOJI: AtomicBool = AtomicBool::new(false);
+
+/// Switches all further output to plain text, for `--no-emoji` or `NO_EMOJI`.
+pub fn set_no_emoji(no_emoji: bool) {
+    NO_EMOJI.store(no_emoji, Ordering::Relaxed);
+}
+
+/// Whether `NO_EMOJI` is set to something other than an empty string.
+pub fn no_emoji_from_env() -> bool {
+    std::env::var_os("NO_EMOJI").is_some_and(|value| !value.is_empty())
+}
+
+/// ASCII tags for the emoji that start a line of output.
+const EMOJI_TAGS: &[(&str, &str)] = &[
+    ("✅", "[OK]"),
+    ("❌", "[FAIL]"),
+    ("⚠️", "[WARN]"),
+    ("⏳", "[PEND]"),
+    ("🚀", "[RUN]"),
+    ("🛑", "[STOP]"),
+    ("💡", "[HINT]"),
+    ("🧪", "[DRY-RUN]"),
+    ("🔖", "[JOB]"),
+    ("📌", "[JOBS]"),
+    ("⚖️", "[BATCH]"),
+    ("📝", "[LOG]"),
+    ("📂", "[DIR]"),
+    ("📡", "[INFO]"),
+    ("📜", "[SCRIPT]"),
+    ("📦", "[CHUNK]"),
+    ("🔍", "[DIFF]"),
+    ("🔁", "[RETRY]"),
+    ("🐚", "[SHELL]"),
+    ("💨", "[SMOKE]"),
+    ("🧹", "[CLEAN]"),
+    ("👀", "[WATCH]"),
+    ("📊", "[USAGE]"),
+    ("🧠", "[MEM]"),
+    ("🤷", "[NONE]"),
+    ("📚", "[ACCT]"),
+    ("⏱️", "[TIME]"),
+    ("▶️", "[RELEASE]"),
+];
+
+/// Whether `c` is part of an emoji, including the variation selector and
+/// joiner that combine them.
+pub fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F300..=0x1FAFF | 0x2600..=0x27BF | 0x2B50 | 0x231B | 0x23F1 | 0x23F3 | 0x25B6 | 0xFE0F
+            | 0x200D
+    )
+}
+
+/// Rewrites `text` without emoji. An emoji starting a line becomes its ASCII
+/// tag, e.g. `[OK]`; any others, such as those decorating exit reasons, are
+/// dropped along with the space that separated them.
+pub fn plain(text: &str) -> String {
+    let lines: Vec<String> = text.split('\n').map(plain_line).collect();
+    lines.join("\n")
+}
+
+fn plain_line(line: &str) -> String {
+    let body = line.trim_start();
+    let indent = &line[..line.len() - body.len()];
+    let (tag, rest) = EMOJI_TAGS
+        .iter()
+        .find_map(|(emoji, tag)| Some((*tag, body.strip_prefix(emoji)?)))
+        .unwrap_or(("", body));
+
+    let mut out = format!("{}{}", indent, tag);
+    let mut stripped = false;
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !is_emoji(c) {
+            out.push(c);
+            continue;
+        }
+        while chars.next_if(|c| is_emoji(*c)).is_some() {}
+        if out.ends_with(' ') {
+            chars.next_if_eq(&' ');
+        }
+        stripped = true;
+    }
+    if stripped {
+        out.truncate(out.trim_end_matches(' ').len());
+    }
+    out
+}
+
+/// `text` as it should be shown: unchanged, or without emoji under `--no-emoji`.
+pub fn render(text: &str) -> Cow<'_, str> {
+    if NO_EMOJI.load(Ordering::Relaxed) {
+        Cow::Owned(plain(text))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// `println!` that honours `--no-emoji`.
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::output::render(&format!($($arg)*)))
+    };
+}
+
+/// `eprintln!` that honours `--no-emoji`.
+macro_rules! eoutln {
+    ($($arg:tt)*) => {
+        eprintln!("{}", $crate::output::render(&format!($($arg)*)))
+    };
+}
+
+/// `print!` that honours `--no-emoji`.
+macro_rules! out {
+    ($($arg:tt)*) => {
+        print!("{}", $crate::output::render(&format!($($arg)*)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_replaces_emoji() {
+        assert_eq!(plain("✅ 3 jobs done"), "[OK] 3 jobs done");
+        assert_eq!(plain("⚠️ Skipped 2 commands"), "[WARN] Skipped 2 commands");
+        assert_eq!(
+            plain("  - Exit Code 137: Killed (OOM) 💀🛑💾 x3 (indices 1,2,3)"),
+            "  - Exit Code 137: Killed (OOM) x3 (indices 1,2,3)"
+        );
+        assert_eq!(
+            plain("\n🛑 Interrupted\n   arrayify check 1"),
+            "\n[STOP] Interrupted\n   arrayify check 1"
+        );
+        assert_eq!(plain("Memory error 🧠💾"), "Memory error");
+        assert_eq!(
+            plain("⏱️ Run time (min): median 1.0"),
+            "[TIME] Run time (min): median 1.0"
+        );
+        assert_eq!(
+            plain("▶️ Released held jobs in array 42"),
+            "[RELEASE] Released held jobs in array 42"
+        );
+    }
+}
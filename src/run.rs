@@ -0,0 +1,472 @@
+use crate::check::{self, ArrayStatus};
+use crate::manifest::{self, Manifest};
+use crate::submission::{self, InputFormat, SubmitOptions};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// How `run_until_done` waits for arrays and retries their failures.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Submissions in total, counting the first
+    pub max_attempts: u32,
+    /// Memory multiplier for a retry when any task ran out of memory
+    pub memory_factor: u32,
+    pub interval: Duration,
+    /// Consecutive failed or empty scheduler queries tolerated while waiting
+    pub max_hiccups: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            memory_factor: 2,
+            interval: Duration::from_secs(60),
+            max_hiccups: 5,
+        }
+    }
+}
+
+/// Polls an array until every task has finished, reading its final outcome
+/// from `bacct` if LSF purges it from `bjobs` first. A failing query, or one
+/// that briefly reports no tasks at all, is retried rather than fatal.
+fn wait_for_array(
+    job_id: &str,
+    policy: &RetryPolicy,
+    query: &impl Fn(&str) -> io::Result<String>,
+    bacct: &impl Fn(&str) -> io::Result<String>,
+) -> io::Result<ArrayStatus> {
+    let mut hiccups = 0;
+    loop {
+        match check::query_status(job_id, query, bacct).map(|(status, _)| status) {
+            Ok(status) if status.is_finished() => return Ok(status),
+            Ok(status) if status.total() > 0 => hiccups = 0,
+            Ok(_) => hiccups += 1,
+            Err(e) => {
+                hiccups += 1;
+                eoutln!("⚠️ Could not query array {}: {}", job_id, e);
+            }
+        }
+        if hiccups > policy.max_hiccups {
+            return Err(io::Error::other(format!(
+                "Gave up waiting for array {} after {} failed queries",
+                job_id, hiccups
+            )));
+        }
+        thread::sleep(policy.interval);
+    }
+}
+
+/// Memory in GB and threads an array was submitted with.
+type Resources = (u32, u32);
+
+/// Failed commands of arrays that requested the same resources, and whether
+/// any of them ran out of memory.
+#[derive(Default)]
+struct Failures {
+    commands: Vec<String>,
+    out_of_memory: bool,
+}
+
+/// Options for resubmitting failed commands, written to `retry_file`, as a
+/// jobs file with the given memory and threads.
+fn retry_options(
+    opts: &SubmitOptions,
+    retry_file: &str,
+    (memory_gb, threads): Resources,
+) -> SubmitOptions {
+    SubmitOptions {
+        input_path: retry_file.to_string(),
+        extra_inputs: Vec::new(),
+        format: InputFormat::JobsFile,
+        memory_gb,
+        threads,
+        memory_column: None,
+        threads_column: None,
+        mem_per_gb_input: None,
+        index_range: None,
+        dedup: false,
+        no_duplicate: false,
+        smoke_test: false,
+        ..opts.clone()
+    }
+}
+
+/// Why `opts` can't be waited on, for options that submit nothing here or
+/// that submit somewhere `query` can't see.
+fn unsupported_option(opts: &SubmitOptions) -> Option<&'static str> {
+    if opts.dry_run || opts.emit_script.is_some() || opts.profile_readers {
+        Some(
+            "--dry-run, --emit-script and --profile-readers submit nothing for run-until-done to wait for",
+        )
+    } else if opts.submit_host.is_some() {
+        Some(
+            "--submit-host submits on another host, but run-until-done checks the array with bjobs on this one",
+        )
+    } else if opts.local {
+        Some("--local runs the tasks itself; use arrayify sub --local")
+    } else {
+        None
+    }
+}
+
+/// Submits `opts`, waits for every array to finish and resubmits the failed
+/// commands until all succeed or `max_attempts` submissions have been made.
+/// Failed commands are retried with the memory and threads of the array they
+/// ran in, one retry array per set of resources; where a task ran out of
+/// memory, with `memory_factor` times the memory. Errors if commands are
+/// still failing at the end.
+pub fn run_until_done(
+    opts: &SubmitOptions,
+    policy: &RetryPolicy,
+    mut submit: impl FnMut(&SubmitOptions) -> io::Result<Vec<Manifest>>,
+    query: impl Fn(&str) -> io::Result<String>,
+    bacct: impl Fn(&str) -> io::Result<String>,
+) -> io::Result<()> {
+    if opts.no_log {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--no-log leaves no command log to retry failed commands from",
+        ));
+    }
+    if let Some(reason) = unsupported_option(opts) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, reason));
+    }
+    let timestamp = submission::generated_at(opts)
+        .format(manifest::TIMESTAMP_FORMAT)
+        .to_string();
+    let mut current = vec![opts.clone()];
+    for attempt in 1..=policy.max_attempts {
+        let mut manifests = Vec::new();
+        for retry_opts in &current {
+            manifests.extend(submit(retry_opts)?);
+        }
+        if manifests.is_empty() {
+            return Err(io::Error::other("Nothing was submitted"));
+        }
+
+        let mut failures: BTreeMap<Resources, Failures> = BTreeMap::new();
+        for manifest in &manifests {
+            if manifest.job_id == "unknown" {
+                return Err(io::Error::other(format!(
+                    "The scheduler did not return a job ID for {}",
+                    manifest.log_file
+                )));
+            }
+            let status = wait_for_array(&manifest.job_id, policy, &query, &bacct)?;
+            let log_content = fs::read_to_string(&manifest.log_file)?;
+            let failed = check::failed_commands(&status, &log_content, manifest.options.chunk);
+            if failed.is_empty() {
+                continue;
+            }
+            let group = failures
+                .entry((manifest.memory_gb(), manifest.threads()))
+                .or_default();
+            group.commands.extend(failed);
+            group.out_of_memory |= status
+                .failed
+                .iter()
+                .any(|job| check::is_memory_failure(&job.exit_code));
+        }
+
+        if failures.is_empty() {
+            outln!("✅ All tasks succeeded after {} attempt(s)", attempt);
+            return Ok(());
+        }
+        if attempt == policy.max_attempts {
+            break;
+        }
+
+        let several = failures.len() > 1;
+        current = Vec::new();
+        for ((memory_gb, threads), group) in failures {
+            let suffix = if several {
+                format!("-mem{}G-n{}", memory_gb, threads)
+            } else {
+                String::new()
+            };
+            let retry_file = format!(
+                "{}/arrayify-{}-retry{}{}.log",
+                opts.log_dir, timestamp, attempt, suffix
+            );
+            fs::write(&retry_file, format!("{}\n", group.commands.join("\n")))?;
+            let memory_gb = if group.out_of_memory {
+                memory_gb * policy.memory_factor
+            } else {
+                memory_gb
+            };
+            outln!(
+                "🔁 {} commands failed; resubmitting with {} GB (attempt {} of {})",
+                group.commands.len(),
+                memory_gb,
+                attempt + 1,
+                policy.max_attempts
+            );
+            current.push(retry_options(opts, &retry_file, (memory_gb, threads)));
+        }
+    }
+
+    Err(io::Error::other(format!(
+        "Commands were still failing after {} attempts",
+        policy.max_attempts
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::process::Command;
+
+    /// Runs each submitted command locally with bash and reports the results
+    /// the way `bjobs` would, standing in for the scheduler.
+    struct LocalBackend {
+        results: RefCell<HashMap<String, String>>,
+        resources: RefCell<Vec<Resources>>,
+    }
+
+    impl LocalBackend {
+        fn submit(&self, opts: &SubmitOptions) -> io::Result<Vec<Manifest>> {
+            let mut results = self.results.borrow_mut();
+            let job_id = (1000 + results.len()).to_string();
+            self.resources
+                .borrow_mut()
+                .push((opts.memory_gb, opts.threads));
+
+            let mut report = String::new();
+            for (i, command) in fs::read_to_string(&opts.input_path)?.lines().enumerate() {
+                let status = Command::new("bash").arg("-c").arg(command).status()?;
+                let (stat, code) = match status.code() {
+                    Some(0) => ("DONE", "-".to_string()),
+                    Some(code) => ("EXIT", code.to_string()),
+                    None => ("EXIT", "143".to_string()),
+                };
+                report.push_str(&format!("job[{}] {} {} local\n", i + 1, stat, code));
+            }
+            results.insert(job_id.clone(), report);
+
+            Ok(vec![Manifest {
+                job_id,
+                submitted_at: String::new(),
+                num_jobs: 0,
+                log_file: opts.input_path.clone(),
+                options: opts.clone(),
+                submission_hash: String::new(),
+                note: None,
+                requested_memory_gb: None,
+                requested_threads: None,
+            }])
+        }
+
+        fn query(&self, job_id: &str) -> io::Result<String> {
+            Ok(self.results.borrow()[job_id].clone())
+        }
+    }
+
+    #[test]
+    fn test_run_until_done_retries_flaky_task() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let dir = work_dir.path();
+        // Fails with an out-of-memory exit code on the first try only
+        let flaky = format!(
+            "if [ -e {0}/tried ]; then echo ok; else touch {0}/tried; exit 137; fi",
+            dir.display()
+        );
+        let jobs_file = dir.join("commands.txt");
+        fs::write(&jobs_file, format!("true\n{}\ntrue\n", flaky)).unwrap();
+
+        let opts = SubmitOptions {
+            input_path: jobs_file.to_str().unwrap().to_string(),
+            format: InputFormat::JobsFile,
+            log_dir: dir.to_str().unwrap().to_string(),
+            memory_gb: 4,
+            ..Default::default()
+        };
+        let policy = RetryPolicy {
+            interval: Duration::ZERO,
+            ..Default::default()
+        };
+        let backend = LocalBackend {
+            results: RefCell::new(HashMap::new()),
+            resources: RefCell::new(Vec::new()),
+        };
+
+        run_until_done(
+            &opts,
+            &policy,
+            |o| backend.submit(o),
+            |id| backend.query(id),
+            |_: &str| Ok(String::new()),
+        )
+        .unwrap();
+        // Only the flaky command was retried, with double the memory
+        assert_eq!(*backend.resources.borrow(), vec![(4, 1), (8, 1)]);
+        assert_eq!(backend.results.borrow()["1001"], "job[1] DONE - local\n");
+
+        // A command that always fails exhausts the attempts
+        fs::write(&jobs_file, "exit 1\n").unwrap();
+        let err = run_until_done(
+            &opts,
+            &policy,
+            |o| backend.submit(o),
+            |id| backend.query(id),
+            |_: &str| Ok(String::new()),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("still failing after 3 attempts"));
+    }
+
+    #[test]
+    fn test_wait_tolerates_scheduler_hiccups() {
+        let policy = RetryPolicy {
+            interval: Duration::ZERO,
+            max_hiccups: 2,
+            ..Default::default()
+        };
+        let calls = RefCell::new(0);
+        let no_bacct = |_: &str| Ok(String::new());
+        let status = wait_for_array(
+            "7",
+            &policy,
+            &|_: &str| {
+                *calls.borrow_mut() += 1;
+                match *calls.borrow() {
+                    1 => Err(io::Error::other("bjobs: LSF is down")),
+                    2 => Ok(String::new()),
+                    _ => Ok("job[1] DONE - node1\n".to_string()),
+                }
+            },
+            &no_bacct,
+        )
+        .unwrap();
+        assert_eq!(status.done, 1);
+
+        let err =
+            wait_for_array("7", &policy, &|_: &str| Ok(String::new()), &no_bacct).unwrap_err();
+        assert!(err.to_string().contains("after 3 failed queries"));
+    }
+
+    #[test]
+    fn test_run_until_done_rejects_options_it_cannot_wait_on() {
+        let unsupported = [
+            SubmitOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+            SubmitOptions {
+                emit_script: Some("array.sh".to_string()),
+                ..Default::default()
+            },
+            SubmitOptions {
+                submit_host: Some("me@login".to_string()),
+                ..Default::default()
+            },
+            SubmitOptions {
+                local: true,
+                ..Default::default()
+            },
+        ];
+        for opts in unsupported {
+            let err = run_until_done(
+                &opts,
+                &RetryPolicy::default(),
+                |_: &SubmitOptions| panic!("nothing should be submitted"),
+                |_: &str| panic!("nothing should be queried"),
+                |_: &str| panic!("nothing should be queried"),
+            )
+            .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_wait_ends_when_array_is_purged() {
+        let policy = RetryPolicy {
+            interval: Duration::ZERO,
+            max_hiccups: 2,
+            ..Default::default()
+        };
+        let bacct = |_: &str| {
+            Ok(
+                "\nJob <7[1]>, Job Name <job[1]>, User <sam>, Status <DONE>, Queue <normal>\n\
+                \nJob <7[2]>, Job Name <job[2]>, User <sam>, Status <EXIT>, Queue <normal>\n\
+                Completed <exit>; exit code <137>.\n"
+                    .to_string(),
+            )
+        };
+        let status = wait_for_array("7", &policy, &|_: &str| Ok(String::new()), &bacct).unwrap();
+        assert_eq!(status.done, 1);
+        assert_eq!(status.failed[0].array_name, "job[2]");
+        assert_eq!(status.failed[0].exit_code, "137");
+    }
+
+    #[test]
+    fn test_retries_keep_each_arrays_resources() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let dir = work_dir.path();
+        // Each fails on the first try only, one of them out of memory
+        let flaky = |name: &str, code: u32| {
+            format!(
+                "if [ -e {0}/{1} ]; then echo ok; else touch {0}/{1}; exit {2}; fi",
+                dir.display(),
+                name,
+                code
+            )
+        };
+        let big = dir.join("big.txt");
+        fs::write(&big, format!("true\n{}\n", flaky("big", 137))).unwrap();
+        let small = dir.join("small.txt");
+        fs::write(&small, format!("{}\ntrue\n", flaky("small", 1))).unwrap();
+
+        let opts = SubmitOptions {
+            log_dir: dir.to_str().unwrap().to_string(),
+            memory_gb: 4,
+            ..Default::default()
+        };
+        let policy = RetryPolicy {
+            interval: Duration::ZERO,
+            ..Default::default()
+        };
+        let backend = LocalBackend {
+            results: RefCell::new(HashMap::new()),
+            resources: RefCell::new(Vec::new()),
+        };
+        // The first submission splits into two arrays with resources of their own
+        let submit = |o: &SubmitOptions| {
+            if matches!(o.format, InputFormat::JobsFile) {
+                return backend.submit(o);
+            }
+            let mut manifests = Vec::new();
+            for (file, (memory_gb, threads)) in [(&big, (16, 4)), (&small, (2, 1))] {
+                let array = SubmitOptions {
+                    input_path: file.to_str().unwrap().to_string(),
+                    ..o.clone()
+                };
+                for mut manifest in backend.submit(&array)? {
+                    manifest.requested_memory_gb = Some(memory_gb);
+                    manifest.requested_threads = Some(threads);
+                    manifests.push(manifest);
+                }
+            }
+            Ok(manifests)
+        };
+
+        run_until_done(
+            &opts,
+            &policy,
+            submit,
+            |id| backend.query(id),
+            |_: &str| Ok(String::new()),
+        )
+        .unwrap();
+        // Retried per array: double the memory where a task ran out of it
+        assert_eq!(
+            *backend.resources.borrow(),
+            vec![(4, 1), (4, 1), (2, 1), (32, 4)]
+        );
+    }
+}
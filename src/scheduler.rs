@@ -0,0 +1,152 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+/// Batch schedulers arrayify knows how to recognise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduler {
+    Lsf,
+    Slurm,
+    Sge,
+}
+
+impl Scheduler {
+    const ALL: [Scheduler; 3] = [Scheduler::Lsf, Scheduler::Slurm, Scheduler::Sge];
+
+    /// The submission command whose presence on PATH identifies the scheduler.
+    fn submit_command(self) -> &'static str {
+        match self {
+            Scheduler::Lsf => "bsub",
+            Scheduler::Slurm => "sbatch",
+            Scheduler::Sge => "qsub",
+        }
+    }
+
+    /// Parses a `--scheduler` value; `auto` is handled by the caller.
+    pub fn from_name(name: &str) -> Option<Scheduler> {
+        match name {
+            "lsf" => Some(Scheduler::Lsf),
+            "slurm" => Some(Scheduler::Slurm),
+            "sge" => Some(Scheduler::Sge),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Scheduler::Lsf => "lsf",
+            Scheduler::Slurm => "slurm",
+            Scheduler::Sge => "sge",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+fn on_path(path: &OsStr, command: &str) -> bool {
+    std::env::split_paths(path).any(|dir| is_executable(&dir.join(command)))
+}
+
+/// Picks the scheduler whose submission command is on `path` (a PATH-style
+/// list). Errors if none or several are found, as the choice is then ambiguous.
+pub fn detect_scheduler(path: &OsStr) -> io::Result<Scheduler> {
+    let found: Vec<Scheduler> = Scheduler::ALL
+        .into_iter()
+        .filter(|scheduler| on_path(path, scheduler.submit_command()))
+        .collect();
+
+    match found.as_slice() {
+        [scheduler] => Ok(*scheduler),
+        [] => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No scheduler found on PATH (looked for bsub, sbatch and qsub); set --scheduler",
+        )),
+        _ => {
+            let names: Vec<String> = found.iter().map(Scheduler::to_string).collect();
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Several schedulers found on PATH ({}); choose one with --scheduler",
+                    names.join(", ")
+                ),
+            ))
+        }
+    }
+}
+
+/// Resolves `--scheduler`, detecting it from PATH for `auto`. A scheduler
+/// named explicitly must still have its submission command on PATH, so a
+/// host without it is caught before any work is done.
+pub fn resolve_scheduler(name: &str, path: &OsStr) -> io::Result<Scheduler> {
+    if name == "auto" {
+        return detect_scheduler(path);
+    }
+    let scheduler = Scheduler::from_name(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown scheduler: {}", name),
+        )
+    })?;
+    if !on_path(path, scheduler.submit_command()) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "{} was selected but {} is not on PATH; load it (e.g. 'module load {}') or run \
+                 arrayify on a host where jobs can be submitted",
+                scheduler,
+                scheduler.submit_command(),
+                scheduler
+            ),
+        ));
+    }
+    Ok(scheduler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_detect_scheduler_from_path() {
+        let slurm_bin = tempfile::tempdir().unwrap();
+        let sbatch = slurm_bin.path().join("sbatch");
+        fs::write(&sbatch, "#!/bin/bash\n").unwrap();
+        fs::set_permissions(&sbatch, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = slurm_bin.path().as_os_str();
+        assert_eq!(detect_scheduler(path).unwrap(), Scheduler::Slurm);
+
+        let empty_bin = tempfile::tempdir().unwrap();
+        let err = detect_scheduler(empty_bin.path().as_os_str()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let lsf_bin = tempfile::tempdir().unwrap();
+        let bsub = lsf_bin.path().join("bsub");
+        fs::write(&bsub, "#!/bin/bash\n").unwrap();
+        fs::set_permissions(&bsub, fs::Permissions::from_mode(0o755)).unwrap();
+        let both = std::env::join_paths([slurm_bin.path(), lsf_bin.path()]).unwrap();
+        let err = detect_scheduler(&both).unwrap_err();
+        assert!(err.to_string().contains("lsf, slurm"));
+
+        assert_eq!(resolve_scheduler("lsf", &both).unwrap(), Scheduler::Lsf);
+    }
+
+    #[test]
+    fn test_explicit_scheduler_must_be_on_path() {
+        let err = resolve_scheduler("lsf", OsStr::new("")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("bsub is not on PATH"));
+
+        let err = resolve_scheduler("auto", OsStr::new("")).unwrap_err();
+        assert!(err.to_string().contains("No scheduler found on PATH"));
+    }
+}
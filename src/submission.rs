@@ -1,92 +1,955 @@
-use crate::jobs;
-use chrono::Local;
+use crate::check;
+use crate::cleanup;
+use crate::jobs::{self, CsvOptions, PairOptions};
+use crate::manifest::{self, Manifest};
+use chrono::{DateTime, Local, NaiveDateTime};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::{self, File};
-use std::io::{self, Write};
-use std::process::Command;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub enum InputFormat {
+    #[default]
     Csv,
     Directory,
+    Glob,
+    List,
+    JobsFile,
+    Json,
     // Add new formats here in the future
 }
 
-pub fn write_job_log(log_file_path: &str, jobs: &[String]) -> io::Result<()> {
+/// Everything `submit_jobs` needs to render and submit an array.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubmitOptions {
+    pub input_path: String,
+    /// Further CSVs whose rows are appended to those of `input_path`
+    pub extra_inputs: Vec<String>,
+    pub command_template: String,
+    pub job_prefix: String,
+    pub log_dir: String,
+    pub memory_gb: u32,
+    pub threads: u32,
+    pub time_minutes: Option<u32>,
+    pub queue: String,
+    pub batch_size: Option<usize>,
+    pub format: InputFormat,
+    pub dedup: bool,
+    pub prolog: Option<String>,
+    pub epilog: Option<String>,
+    pub strict_bash: bool,
+    pub max_pending: Option<usize>,
+    pub dry_run: bool,
+    pub stagger_secs: Option<u64>,
+    pub emit_script: Option<String>,
+    pub exclude_hosts: Vec<String>,
+    pub mem_per_gb_input: Option<u32>,
+    pub comment_char: char,
+    pub shellcheck: bool,
+    pub force: bool,
+    pub pad_short_rows: bool,
+    pub profile_readers: bool,
+    pub encoding: Option<String>,
+    pub hold: bool,
+    pub verify: bool,
+    pub task_log: Option<String>,
+    pub no_duplicate: bool,
+    pub duplicate_window_hours: u32,
+    /// Keep the task script piped to bsub; an empty path means next to the command log
+    pub keep_script: Option<String>,
+    pub smoke_test: bool,
+    /// With --smoke-test, accept a first command still running after this many seconds
+    pub smoke_timeout_secs: Option<u64>,
+    /// Free-text note recorded in the manifest; not inherited by reruns
+    #[serde(skip)]
+    pub note: Option<String>,
+    pub absolute_paths: bool,
+    pub check_paths: bool,
+    /// Columns holding file paths for --check-paths; empty means guess from the commands
+    pub path_columns: Vec<String>,
+    /// Replaces the default `-R` resource string; `{mem}` is the memory in MB
+    pub rusage: Option<String>,
+    /// CSV column giving each row's thread count, overriding `threads`
+    pub threads_column: Option<String>,
+    /// CSV column giving each row's memory in GB, overriding `memory_gb`
+    pub memory_column: Option<String>,
+    /// Warn when `time_minutes` is below this many minutes
+    pub min_time_minutes: u32,
+    /// Consecutive commands run by each array task
+    pub chunk: usize,
+    /// `(NAME, ALIAS)` pairs: `{ALIAS}` and `{NAME}` stand for the same value,
+    /// whichever of the two the input provides
+    pub placeholder_aliases: Vec<(String, String)>,
+    /// Floor for the automatic batch size
+    pub min_batch: Option<usize>,
+    /// CSV columns that must exist and be non-empty on every row
+    pub required_columns: Vec<String>,
+    /// With --jobs-file, submit only these 1-based lines (inclusive) as array indices
+    pub index_range: Option<(usize, usize)>,
+    /// Shared file every task also appends its stdout to, prefixed with its index
+    pub tee: Option<String>,
+    /// Extra `select[...]` term ANDed with the memory selection, e.g. `avx512`
+    pub resource_select: Option<String>,
+    /// Automatic batch size as this percentage of the queue's slot limit
+    pub batch_of_queue: Option<u32>,
+    /// `user@host` to run bsub on over SSH instead of locally
+    pub submit_host: Option<String>,
+    /// CSV column holding a per-row template fragment, see `CsvOptions`
+    pub template_column: Option<String>,
+    /// `(OPEN, CLOSE)` markers used for placeholders instead of `{` and `}`
+    pub delimiters: Option<(String, String)>,
+    /// Drop commands that render to nothing but whitespace
+    pub skip_empty: bool,
+    /// User-assigned job priority, passed to bsub as `-sp`
+    pub priority: Option<u32>,
+    /// Largest array the scheduler accepts; read from LSF when unset
+    pub max_array_size: Option<usize>,
+    /// Previous command log to diff the dry run's commands against
+    pub diff_against: Option<String>,
+    /// Embed the commands in the task script instead of writing a command log
+    pub no_log: bool,
+    /// Times each task reruns its command after a failure
+    pub task_retries: u32,
+    /// Seconds to wait between a task's attempts
+    pub retry_sleep_secs: u64,
+    /// File to write a JSON summary of the submitted array to
+    pub summary_file: Option<String>,
+    /// LSF job group for fair share and throttling, passed to bsub as `-g`
+    pub group: Option<String>,
+    /// Start the command log with comment lines describing the submission
+    pub log_header: bool,
+    /// Make the bsub calls of a submission split into several arrays at once
+    pub parallel_submit: bool,
+    /// Upstream array whose task N must finish successfully before task N starts
+    pub after_array: Option<u64>,
+    /// TOML file of constants available as placeholders in every row
+    pub template_vars: Option<String>,
+    /// Fixed clock for generated names and headers, for reproducible output
+    pub seed: Option<u32>,
+    /// Joins R1 and R2 in the `{READS}` placeholder of directory input
+    pub reads_separator: String,
+    /// Advance reservation whose hosts the tasks run on
+    pub reservation: Option<String>,
+    /// Program each command is run with instead of bash, e.g. `python3`
+    pub interpreter: Option<String>,
+    /// Most memory in GB the array's running tasks may hold between them
+    pub max_total_mem_gb: Option<u32>,
+    /// Most slots the array's running tasks may hold between them
+    pub max_total_slots: Option<u32>,
+    /// Joins a sample's lane files in `{R1}`/`{R2}` of directory input, if
+    /// lanes are merged
+    pub merge_lanes: Option<String>,
+    /// Have each task record its exit code in `job_<ID>_<INDEX>.code`
+    pub exit_codes: bool,
+    pub warn_collisions: bool,
+    /// Columns holding output paths for --warn-collisions; empty means guess from the commands
+    pub output_columns: Vec<String>,
+    /// Report every malformed CSV row before failing, not just the first
+    pub pre_submit_validate: bool,
+    /// Run the tasks on this machine instead of submitting them
+    pub local: bool,
+    /// With --local, most tasks running at once; the number of CPUs if unset
+    pub local_jobs: Option<usize>,
+}
+
+impl Default for SubmitOptions {
+    fn default() -> Self {
+        SubmitOptions {
+            input_path: String::new(),
+            extra_inputs: Vec::new(),
+            command_template: String::new(),
+            job_prefix: "arrayify".to_string(),
+            log_dir: "logs".to_string(),
+            memory_gb: 1,
+            threads: 1,
+            time_minutes: None,
+            queue: "normal".to_string(),
+            batch_size: None,
+            format: InputFormat::Csv,
+            dedup: false,
+            prolog: None,
+            epilog: None,
+            strict_bash: true,
+            max_pending: None,
+            dry_run: false,
+            stagger_secs: None,
+            emit_script: None,
+            exclude_hosts: Vec::new(),
+            mem_per_gb_input: None,
+            comment_char: '#',
+            shellcheck: false,
+            force: false,
+            pad_short_rows: false,
+            profile_readers: false,
+            encoding: None,
+            hold: false,
+            verify: false,
+            task_log: None,
+            no_duplicate: false,
+            duplicate_window_hours: 24,
+            keep_script: None,
+            smoke_test: false,
+            smoke_timeout_secs: None,
+            note: None,
+            absolute_paths: false,
+            check_paths: false,
+            path_columns: Vec::new(),
+            rusage: None,
+            threads_column: None,
+            memory_column: None,
+            min_time_minutes: 10,
+            chunk: 1,
+            placeholder_aliases: Vec::new(),
+            min_batch: None,
+            required_columns: Vec::new(),
+            index_range: None,
+            tee: None,
+            resource_select: None,
+            batch_of_queue: None,
+            submit_host: None,
+            template_column: None,
+            delimiters: None,
+            skip_empty: true,
+            priority: None,
+            max_array_size: None,
+            diff_against: None,
+            no_log: false,
+            task_retries: 0,
+            retry_sleep_secs: 0,
+            summary_file: None,
+            group: None,
+            log_header: false,
+            parallel_submit: false,
+            after_array: None,
+            template_vars: None,
+            seed: None,
+            reads_separator: ",".to_string(),
+            reservation: None,
+            interpreter: None,
+            max_total_mem_gb: None,
+            max_total_slots: None,
+            merge_lanes: None,
+            exit_codes: false,
+            warn_collisions: false,
+            output_columns: Vec::new(),
+            pre_submit_validate: false,
+            local: false,
+            local_jobs: None,
+        }
+    }
+}
+
+/// Starts each comment line `--log-header` puts at the top of a command log.
+pub const LOG_HEADER_PREFIX: &str = "# arrayify ";
+
+/// Number of lines in a log header, which tasks skip to find their commands.
+const LOG_HEADER_LINES: usize = 4;
+
+/// Lines of header before the first command of the log the tasks read. A
+/// jobs file is read as given, so never has one.
+fn header_lines(opts: &SubmitOptions) -> usize {
+    match opts.format {
+        InputFormat::JobsFile => 0,
+        _ if opts.log_header => LOG_HEADER_LINES,
+        _ => 0,
+    }
+}
+
+/// The `--log-header` lines for a submission. The job ID isn't known until
+/// bsub answers, so it starts out as `pending`.
+fn log_header(opts: &SubmitOptions, job_id: &str, timestamp: &str) -> String {
+    let fields = [
+        ("job_id", job_id),
+        ("submitted_at", timestamp),
+        ("queue", &opts.queue),
+        ("template", &opts.command_template),
+    ];
+    fields
+        .iter()
+        .map(|(name, value)| {
+            // Keep the header a fixed number of lines whatever the template holds
+            format!(
+                "{}{}={}\n",
+                LOG_HEADER_PREFIX,
+                name,
+                value.replace('\n', "\\n")
+            )
+        })
+        .collect()
+}
+
+/// Records the job ID in a log header once the array has been submitted. The
+/// log is replaced in one rename, so a task reading it sees one version or the
+/// other, with the same number of lines either way.
+fn fill_in_log_header(log_file_path: &str, job_id: &str) -> io::Result<()> {
+    let content = fs::read_to_string(log_file_path)?;
+    let pending = format!("{}job_id=pending\n", LOG_HEADER_PREFIX);
+    let Some(rest) = content.strip_prefix(&pending) else {
+        return Ok(());
+    };
+    let updated_path = format!("{}.tmp", log_file_path);
+    fs::write(
+        &updated_path,
+        format!("{}job_id={}\n{}", LOG_HEADER_PREFIX, job_id, rest),
+    )?;
+    fs::rename(&updated_path, log_file_path)
+}
+
+/// The commands of a command log, without any `--log-header` lines.
+pub fn strip_log_header(log_content: &str) -> String {
+    log_content
+        .lines()
+        .skip_while(|line| line.starts_with(LOG_HEADER_PREFIX))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Writes the commands one per line, after the optional `header`.
+pub fn write_job_log(log_file_path: &str, header: Option<&str>, jobs: &[String]) -> io::Result<()> {
     let mut log_file = File::create(log_file_path)?;
+    if let Some(header) = header {
+        log_file.write_all(header.as_bytes())?;
+    }
     for job_command in jobs.iter() {
         writeln!(log_file, "{}", job_command)?;
     }
     Ok(())
 }
 
-pub fn calculate_batch_size(num_jobs: usize, batch_size: Option<usize>) -> usize {
+/// Concurrency for the array: the explicit `batch_size`, or 20% of the jobs
+/// rounded up and raised to `min_batch`, never more than there are jobs.
+pub fn calculate_batch_size(
+    num_jobs: usize,
+    batch_size: Option<usize>,
+    min_batch: Option<usize>,
+) -> usize {
     batch_size.unwrap_or_else(|| {
         let calculated = ((num_jobs as f64) * 0.2).ceil() as usize;
-        calculated.min(num_jobs)
+        calculated.max(min_batch.unwrap_or(0)).min(num_jobs)
     })
 }
 
+/// Reads the slot limit from `bqueues -o max` output; `-` means unlimited.
+fn parse_queue_slots(output: &str) -> Option<usize> {
+    output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?
+        .parse()
+        .ok()
+        .filter(|&slots| slots > 0)
+}
+
+fn query_queue_slots(queue: &str) -> io::Result<String> {
+    let output = Command::new("bqueues")
+        .args(["-noheader", "-o", "max", queue])
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Concurrency for `num_tasks` tasks. With `--batch-of-queue` and no explicit
+/// batch size it is that percentage of the queue's slots, clamped to the
+/// array; if the queue can't be queried the usual rule applies. Either way it
+/// is then capped by `--max-total-mem` and `--max-total-slots`.
+fn resolve_batch_size(
+    opts: &SubmitOptions,
+    num_tasks: usize,
+    query: impl Fn(&str) -> io::Result<String>,
+) -> usize {
+    let of_queue = match (opts.batch_size, opts.batch_of_queue) {
+        (None, Some(percent)) => match query(&opts.queue)
+            .ok()
+            .and_then(|output| parse_queue_slots(&output))
+        {
+            Some(slots) => {
+                let batch = (slots * percent as usize).div_ceil(100);
+                Some(batch.clamp(1, num_tasks.max(1)))
+            }
+            None => {
+                eoutln!(
+                    "⚠️ Could not read the slot limit of queue {}; sizing the batch from the array instead",
+                    opts.queue
+                );
+                None
+            }
+        },
+        _ => None,
+    };
+    let batch = of_queue
+        .unwrap_or_else(|| calculate_batch_size(num_tasks, opts.batch_size, opts.min_batch));
+
+    let capped = cap_batch_size(batch, opts);
+    if capped < batch {
+        outln!(
+            "⚖️ Running at most {} tasks at once to stay within the total memory and slot limits",
+            capped
+        );
+    }
+    capped
+}
+
+/// Lowers `batch` so that that many tasks at once stay within
+/// `--max-total-mem` and `--max-total-slots`. At least one task always runs,
+/// even if it alone needs more than the limit.
+pub fn cap_batch_size(batch: usize, opts: &SubmitOptions) -> usize {
+    let fits = |total: Option<u32>, per_task: u32| {
+        total.map_or(usize::MAX, |total| (total / per_task.max(1)) as usize)
+    };
+    let limit =
+        fits(opts.max_total_mem_gb, opts.memory_gb).min(fits(opts.max_total_slots, opts.threads));
+    batch.min(limit).max(1)
+}
+
+/// Counts the pending array elements in `bjobs -o stat` output.
+fn count_pending_jobs(bjobs_output: &str) -> usize {
+    bjobs_output
+        .lines()
+        .filter(|line| line.trim() == "PEND")
+        .count()
+}
+
+fn query_pending_jobs() -> io::Result<usize> {
+    let output = Command::new("bjobs")
+        .arg("-noheader")
+        .arg("-o")
+        .arg("stat")
+        .output()?;
+    Ok(count_pending_jobs(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Refuses a submission of `num_tasks` array tasks that would push the user's
+/// pending jobs above `max_pending`.
+fn ensure_pending_capacity(pending: usize, num_tasks: usize, max_pending: usize) -> io::Result<()> {
+    if pending + num_tasks > max_pending {
+        return Err(io::Error::other(format!(
+            "Submitting {} tasks would exceed --max-pending {} ({} already pending)",
+            num_tasks, max_pending, pending
+        )));
+    }
+    Ok(())
+}
+
+/// Describes the resolved concurrency, e.g. "batch=200 (20% of 1000)".
+fn format_batch_summary(batch_size: usize, num_jobs: usize) -> String {
+    let percent = if num_jobs == 0 {
+        0
+    } else {
+        ((batch_size as f64 / num_jobs as f64) * 100.0).round() as usize
+    };
+    format!("batch={} ({}% of {})", batch_size, percent, num_jobs)
+}
+
+/// Counts lines by streaming the file, so memory use doesn't grow with its size.
+/// A final line without a trailing newline still counts.
 fn count_lines_in_file(file_path: &str) -> io::Result<usize> {
-    let content = std::fs::read_to_string(file_path)?;
-    Ok(content.lines().count())
+    let mut reader = BufReader::new(File::open(file_path)?);
+    let mut count = 0;
+    let mut last_byte = b'\n';
+    loop {
+        let buffer = reader.fill_buf()?;
+        let Some(&last) = buffer.last() else {
+            break;
+        };
+        count += buffer.iter().filter(|&&byte| byte == b'\n').count();
+        last_byte = last;
+        let consumed = buffer.len();
+        reader.consume(consumed);
+    }
+    if last_byte != b'\n' {
+        count += 1;
+    }
+    Ok(count)
 }
 
-fn print_run_stats(num_jobs: usize, log_dir: &str, log_file_path: &str, job_id: &str) {
-    let message = format!(
+/// What was submitted, as printed after submission and written by `--summary-file`.
+#[derive(Debug, Serialize)]
+struct RunSummary {
+    job_id: String,
+    submitted_at: String,
+    num_jobs: usize,
+    batch_size: usize,
+    log_file: String,
+    log_dir: String,
+}
+
+fn format_run_stats(summary: &RunSummary) -> String {
+    format!(
         r#"🚀 Job submission complete! ✅
 🔖 Job ID is: {}
 📌 {} jobs submitted.
+⚖️ Concurrency: {}
 📝 Job commands logged in: {}
 📂 Logs can be found in: {}
 📡 Track with -
    arrayify check {}"#,
-        job_id, num_jobs, log_file_path, log_dir, job_id
+        summary.job_id,
+        summary.num_jobs,
+        format_batch_summary(summary.batch_size, summary.num_jobs),
+        summary.log_file,
+        summary.log_dir,
+        summary.job_id
+    )
+}
+
+fn print_run_stats(summary: &RunSummary) {
+    outln!("{}", format_run_stats(summary));
+}
+
+fn write_run_summary(summary_path: &str, summary: &RunSummary) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(summary)?;
+    fs::write(summary_path, json)
+}
+
+/// Prints the run statistics and, with `--summary-file`, writes them to disk.
+/// `suffix` tells the files of arrays split from one submission apart.
+fn report_run(opts: &SubmitOptions, summary: &RunSummary, suffix: &str) -> io::Result<()> {
+    print_run_stats(summary);
+    if let Some(summary_file) = &opts.summary_file {
+        write_run_summary(&with_suffix(summary_file, suffix), summary)?;
+    }
+    Ok(())
+}
+
+/// LSF's own default for `MAX_JOB_ARRAY_SIZE`, assumed when it can't be read.
+const DEFAULT_MAX_ARRAY_SIZE: usize = 1000;
+
+fn query_array_limit() -> io::Result<String> {
+    let output = Command::new("badmin").args(["showconf", "mbd"]).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads `MAX_JOB_ARRAY_SIZE = N` from `badmin showconf mbd` output.
+fn parse_array_limit(output: &str) -> Option<usize> {
+    output.lines().find_map(|line| {
+        let (name, value) = line.split_once('=')?;
+        if name.trim() != "MAX_JOB_ARRAY_SIZE" {
+            return None;
+        }
+        value.trim().parse().ok().filter(|&limit| limit > 0)
+    })
+}
+
+/// The largest array the scheduler accepts: `--max-array-size` if given,
+/// otherwise LSF's configured limit, falling back to LSF's default.
+fn resolve_array_limit(opts: &SubmitOptions, query: impl Fn() -> io::Result<String>) -> usize {
+    opts.max_array_size.unwrap_or_else(|| {
+        query()
+            .ok()
+            .and_then(|output| parse_array_limit(&output))
+            .unwrap_or(DEFAULT_MAX_ARRAY_SIZE)
+    })
+}
+
+/// Options that keep `num_jobs` commands within the array size limit by
+/// running several per task, or `None` if they already fit.
+fn fit_array_limit(opts: &SubmitOptions, num_jobs: usize, limit: usize) -> Option<SubmitOptions> {
+    if array_size(opts, num_jobs) <= limit {
+        return None;
+    }
+    let chunk = num_jobs.div_ceil(limit);
+    outln!(
+        "📦 {} commands exceed the array size limit of {}; running {} per task",
+        num_jobs,
+        limit,
+        chunk
     );
+    Some(SubmitOptions {
+        chunk,
+        ..opts.clone()
+    })
+}
 
-    println!("{}", message);
+/// Number of array tasks needed for `num_jobs` commands, `chunk` per task.
+fn array_size(opts: &SubmitOptions, num_jobs: usize) -> usize {
+    num_jobs.div_ceil(opts.chunk.max(1))
 }
 
-fn submit_jobs_to_scheduler(
-    job_file_path: &str,
-    log_dir: &str,
-    job_prefix: &str,
-    memory_mb: u32,
-    threads: u32,
-    queue: &str,
-    batch_size: usize,
-) -> io::Result<String> {
-    // Count the number of lines in the file to determine the job array size
-    let num_jobs = count_lines_in_file(job_file_path)?;
-    let job_array = format!("{}_job_array[1-{}]%{}", job_prefix, num_jobs, batch_size);
-    let output_log = format!("{}/job_%J_%I.out", log_dir);
-    let error_log = format!("{}/job_%J_%I.err", log_dir);
-
-    // Generate the bsub command
-    let bsub_cmd = format!(
-        "bsub -J {} -q {} -n {} -M {} -R \"select[mem>{}] rusage[mem={}]\" -o {} -e {}",
-        job_array, queue, threads, memory_mb, memory_mb, memory_mb, output_log, error_log
+/// Number of array tasks over all the arrays of a submission.
+fn total_array_size(sub_arrays: &[(SubmitOptions, Vec<String>)]) -> usize {
+    sub_arrays
+        .iter()
+        .map(|(array_opts, jobs)| array_size(array_opts, jobs.len()))
+        .sum()
+}
+
+/// Parses `--index-range START-END` into 1-based inclusive bounds.
+pub fn parse_index_range(value: &str) -> Result<(usize, usize), String> {
+    let parsed = value
+        .split_once('-')
+        .and_then(|(start, end)| Some((start.trim().parse().ok()?, end.trim().parse().ok()?)));
+    match parsed {
+        Some((start, end)) if start >= 1 && start <= end => Ok((start, end)),
+        _ => Err(format!(
+            "expected START-END with 1 <= START <= END, got '{}'",
+            value
+        )),
+    }
+}
+
+fn build_bsub_command(opts: &SubmitOptions, num_jobs: usize, batch_size: usize) -> String {
+    let memory_mb = opts.memory_gb * 1000;
+    // Task indices are line numbers, so a range needs no offset in the script
+    let (first, last) = opts.index_range.unwrap_or((1, array_size(opts, num_jobs)));
+    let job_array = format!(
+        "{}_job_array[{}-{}]%{}",
+        opts.job_prefix, first, last, batch_size
+    );
+    let output_log = jobs::shell_quote(&format!("{}/job_%J_%I.out", opts.log_dir));
+    let error_log = jobs::shell_quote(&format!("{}/job_%J_%I.err", opts.log_dir));
+
+    let mut bsub_cmd = format!(
+        "bsub -J {} -q {} -n {} -M {}",
+        job_array, opts.queue, opts.threads, memory_mb
     );
+    if let Some(time_minutes) = opts.time_minutes {
+        bsub_cmd.push_str(&format!(" -W {}", time_minutes));
+    }
+    if opts.hold {
+        bsub_cmd.push_str(" -H");
+    }
+    if let Some(priority) = opts.priority {
+        bsub_cmd.push_str(&format!(" -sp {}", priority));
+    }
+    if let Some(group) = &opts.group {
+        bsub_cmd.push_str(&format!(" -g {}", group));
+    }
+    if let Some(reservation) = &opts.reservation {
+        bsub_cmd.push_str(&format!(" -U {}", reservation));
+    }
+    // Between arrays of the same size, [*] makes each element wait on its counterpart
+    if let Some(upstream) = opts.after_array {
+        bsub_cmd.push_str(&format!(" -w \"done({}[*])\"", upstream));
+    }
+    bsub_cmd.push_str(&format!(
+        " -R \"{}\" -o {} -e {}",
+        build_resource_string(opts, memory_mb),
+        output_log,
+        error_log
+    ));
+    bsub_cmd
+}
+
+fn build_release_command(job_id: &str) -> Command {
+    let mut command = Command::new("bresume");
+    command.arg(job_id);
+    command
+}
+
+/// Releases an array submitted with `--hold` so its tasks can start.
+pub fn release_jobs(job_id: &str) -> io::Result<()> {
+    let output = build_release_command(job_id).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "bresume failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    outln!("▶️ Released held jobs in array {}", job_id);
+    Ok(())
+}
+
+/// Builds the `-R` resource requirement, e.g. `select[mem>4000 && hname!=node7] rusage[mem=4000]`.
+fn build_resource_string(opts: &SubmitOptions, memory_mb: u32) -> String {
+    if let Some(rusage) = &opts.rusage {
+        return rusage.replace("{mem}", &memory_mb.to_string());
+    }
+    let mut select = vec![format!("mem>{}", memory_mb)];
+    for host in &opts.exclude_hosts {
+        select.push(format!("hname!={}", host));
+    }
+    if let Some(expr) = &opts.resource_select {
+        // Accept the term with or without its select[...] wrapper
+        let expr = expr.trim();
+        let expr = expr
+            .strip_prefix("select[")
+            .and_then(|inner| inner.strip_suffix(']'))
+            .unwrap_or(expr)
+            .trim();
+        if expr.contains("||") {
+            select.push(format!("({})", expr));
+        } else {
+            select.push(expr.to_string());
+        }
+    }
+    format!("select[{}] rusage[mem={}]", select.join(" && "), memory_mb)
+}
+
+/// Longest start delay any task is given by `--stagger`, in seconds.
+const MAX_STAGGER_SECS: u64 = 600;
+
+/// Where a task script finds its commands.
+enum CommandSource<'a> {
+    /// One command per line of a file, picked out with `sed`
+    File(&'a str),
+    /// Held in the script itself as a bash array, so nothing is left on disk
+    Embedded(&'a [String]),
+}
+
+/// Generates the script that uses `sed` to extract the job command from the file,
+/// wrapped in the optional prolog and epilog.
+fn build_job_script(job_file_path: &str, opts: &SubmitOptions) -> io::Result<String> {
+    build_task_script(CommandSource::File(job_file_path), opts)
+}
+
+/// The line that runs `$COMMAND`: evaluated by bash, or with `--interpreter`
+/// handed to that program as a script file, so e.g. `python3` runs a Python
+/// one-liner while the task keeps its stdin.
+fn command_runner(interpreter: Option<&str>) -> String {
+    match interpreter {
+        Some(interpreter) => format!("{} <(printf '%s\\n' \"$COMMAND\")", interpreter),
+        None => "eval \"$COMMAND\"".to_string(),
+    }
+}
+
+fn build_task_script(source: CommandSource, opts: &SubmitOptions) -> io::Result<String> {
+    let mut script = String::from("#!/bin/bash\n\n");
+
+    // Make failures anywhere in the command (including inside pipelines) fail the task
+    if opts.strict_bash {
+        script.push_str("set -euo pipefail\n\n");
+    }
+
+    // Ramp task starts up gradually rather than hitting shared storage all at once
+    if let Some(stagger_secs) = opts.stagger_secs {
+        script.push_str(&format!(
+            "DELAY=$(( (LSB_JOBINDEX - 1) * {} ))\n\
+             if [ \"$DELAY\" -gt {} ]; then DELAY={}; fi\n\
+             sleep \"$DELAY\"\n\n",
+            stagger_secs, MAX_STAGGER_SECS, MAX_STAGGER_SECS
+        ));
+    }
+
+    // Mirror stdout into the shared run log one prefixed line at a time. Each
+    // line is a single O_APPEND write, so lines from concurrent tasks don't mix.
+    if let Some(tee_path) = &opts.tee {
+        script.push_str(&format!(
+            "exec > >(tee >(while IFS= read -r LINE; do printf '[%s] %s\\n' \"$LSB_JOBINDEX\" \"$LINE\" >> {}; done))\n\n",
+            jobs::shell_quote(tee_path)
+        ));
+    }
+
+    // Any failure during the prolog aborts the task before the command runs
+    if let Some(prolog_path) = &opts.prolog {
+        let prolog = fs::read_to_string(prolog_path)?;
+        if !opts.strict_bash {
+            script.push_str("set -e\n");
+        }
+        script.push_str(prolog.trim_end());
+        script.push('\n');
+        if !opts.strict_bash {
+            script.push_str("set +e\n");
+        }
+        script.push('\n');
+    }
+
+    if let CommandSource::Embedded(jobs) = source {
+        script.push_str("COMMANDS=(\n");
+        for job in jobs {
+            script.push_str(&format!("    {}\n", jobs::shell_quote(job)));
+        }
+        script.push_str(")\n");
+    }
+
+    // Retry transient failures in place, reporting the last attempt's status
+    let runner = command_runner(opts.interpreter.as_deref());
+    let command = if opts.task_retries > 0 {
+        script.push_str(&format!(
+            r#"run_command() {{
+    local ATTEMPT=1
+    until {}; do
+        local CODE=$?
+        if [ "$ATTEMPT" -gt {} ]; then return "$CODE"; fi
+        echo "arrayify: attempt $ATTEMPT failed with exit code $CODE, retrying" >&2
+        ATTEMPT=$((ATTEMPT + 1))
+        sleep {}
+    done
+}}
+"#,
+            runner, opts.task_retries, opts.retry_sleep_secs
+        ));
+        "run_command".to_string()
+    } else {
+        runner
+    };
 
-    // Generate the script that uses `sed` to extract the job command from the file
-    let script = format!(
-        r#"#!/bin/bash
+    // The epilog always runs, but the task reports the command's exit status
+    let track_status = opts.epilog.is_some() || opts.exit_codes;
+    let run = if track_status {
+        format!("{} || STATUS=$?", command)
+    } else {
+        command
+    };
 
-INDEX=$((LSB_JOBINDEX - 1))
-COMMAND=$(sed -n "$((INDEX + 1))p" {})
-$COMMAND
+    if opts.chunk > 1 {
+        // Run this task's block of consecutive lines one after another. The lines
+        // come in on fd 3 so commands reading stdin can't swallow them.
+        script.push_str(&format!(
+            r#"INDEX=$((LSB_JOBINDEX - 1))
+FIRST=$((INDEX * {} + 1))
+LAST=$((FIRST + {} - 1))
 "#,
-        job_file_path
+            opts.chunk, opts.chunk
+        ));
+        if track_status {
+            script.push_str("STATUS=0\n");
+        }
+        let lines = match source {
+            CommandSource::File(job_file_path) if header_lines(opts) > 0 => format!(
+                "sed -n \"$((FIRST + {0})),$((LAST + {0}))p\" {1}",
+                header_lines(opts),
+                jobs::shell_quote(job_file_path)
+            ),
+            CommandSource::File(job_file_path) => {
+                format!(
+                    "sed -n \"${{FIRST}},${{LAST}}p\" {}",
+                    jobs::shell_quote(job_file_path)
+                )
+            }
+            CommandSource::Embedded(_) => format!(
+                "printf '%s\\n' \"${{COMMANDS[@]:INDEX * {}:{}}}\"",
+                opts.chunk, opts.chunk
+            ),
+        };
+        script.push_str(&format!(
+            "while IFS= read -r COMMAND <&3; do\n    {}\ndone 3< <({})\n",
+            run, lines
+        ));
+    } else {
+        let command = match source {
+            CommandSource::File(job_file_path) => {
+                // Commands start after the log header, if there is one
+                let first_line = 1 + header_lines(opts);
+                format!(
+                    "$(sed -n \"$((INDEX + {}))p\" {})",
+                    first_line,
+                    jobs::shell_quote(job_file_path)
+                )
+            }
+            CommandSource::Embedded(_) => "${COMMANDS[INDEX]}".to_string(),
+        };
+        script.push_str(&format!(
+            "INDEX=$((LSB_JOBINDEX - 1))\nCOMMAND={}\n",
+            command
+        ));
+        if track_status {
+            script.push_str("STATUS=0\n");
+        }
+        script.push_str(&run);
+        script.push('\n');
+    }
+
+    // Written before the epilog, so it reflects the command alone
+    if opts.exit_codes {
+        script.push_str(&format!(
+            "echo \"$STATUS\" > {}/job_\"$LSB_JOBID\"_\"$LSB_JOBINDEX\".code\n",
+            jobs::shell_quote(&opts.log_dir)
+        ));
+    }
+
+    if let Some(epilog_path) = &opts.epilog {
+        let epilog = fs::read_to_string(epilog_path)?;
+        script.push('\n');
+        script.push_str(epilog.trim_end());
+        script.push_str("\n\nexit $STATUS\n");
+    } else if opts.exit_codes {
+        script.push_str("exit $STATUS\n");
+    }
+
+    Ok(script)
+}
+
+/// Runs a shell command locally, or on `--submit-host` over SSH.
+fn submit_host_command(opts: &SubmitOptions, shell_command: &str) -> Command {
+    match &opts.submit_host {
+        Some(host) => {
+            let mut command = Command::new("ssh");
+            command.arg(host).arg(shell_command);
+            command
+        }
+        None => {
+            let mut command = Command::new("bash");
+            command.arg("-c").arg(shell_command);
+            command
+        }
+    }
+}
+
+/// Runs `command` with `input` on stdin and returns its stdout. ssh exits
+/// 255 when it can't reach the host, which is reported as an SSH error rather
+/// than left for the scheduler output parsing to trip over.
+fn run_with_input(mut command: Command, input: &str, opts: &SubmitOptions) -> io::Result<String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match &opts.submit_host {
+        Some(host) if output.status.code() == Some(255) => {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("SSH to {} failed: {}", host, stderr.trim()),
+            ));
+        }
+        _ => eprint!("{}", stderr),
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Copies the command log to the same path on `--submit-host`, where the
+/// tasks will read it.
+fn copy_to_submit_host(opts: &SubmitOptions, job_file_path: &str) -> io::Result<()> {
+    let dir = Path::new(job_file_path)
+        .parent()
+        .and_then(Path::to_str)
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or(".");
+    let copy = format!(
+        "mkdir -p {} && cat > {}",
+        jobs::shell_quote(dir),
+        jobs::shell_quote(job_file_path)
     );
+    run_with_input(
+        submit_host_command(opts, &copy),
+        &fs::read_to_string(job_file_path)?,
+        opts,
+    )?;
+    Ok(())
+}
 
-    // Submit the job using the bsub command
-    let child = Command::new("bash")
-        .arg("-c")
-        .arg(format!("echo '{}' | {}", script, bsub_cmd))
-        .output()?;
+fn submit_jobs_to_scheduler(
+    job_file_path: &str,
+    num_jobs: usize,
+    opts: &SubmitOptions,
+    batch_size: usize,
+    script: &str,
+) -> io::Result<String> {
+    let bsub_cmd = build_bsub_command(opts, num_jobs, batch_size);
+
+    // Without a log the commands travel inside the script
+    if opts.submit_host.is_some() && !opts.no_log {
+        copy_to_submit_host(opts, job_file_path)?;
+    }
+
+    // Submit the job using the bsub command, feeding the script on stdin
+    let bsub_output = run_with_input(submit_host_command(opts, &bsub_cmd), script, opts)?;
 
     // Extract the job ID from the bsub output
-    let bsub_output = String::from_utf8_lossy(&child.stdout);
     let re = Regex::new(r"Job <(\d+)>").unwrap();
     let job_id = re
         .captures(&bsub_output)
@@ -97,101 +960,3298 @@ $COMMAND
     Ok(job_id.to_string())
 }
 
-pub fn submit_jobs(
-    input_path: &str,
-    command_template: &str,
-    job_prefix: &str,
-    log_dir: &str,
-    memory_gb: u32,
-    threads: u32,
-    queue: &str,
-    batch_size: Option<usize>,
-    format: InputFormat,
+/// Prints what would be submitted without writing the log or calling the scheduler.
+fn print_dry_run(
+    opts: &SubmitOptions,
+    jobs: &[String],
+    log_file_path: &str,
+    batch_size: usize,
 ) -> io::Result<()> {
-    let memory_mb = memory_gb * 1000;
-    fs::create_dir_all(log_dir)?;
+    outln!("🧪 Dry run: nothing will be submitted");
+    outln!("📌 {} jobs", jobs.len());
+    outln!(
+        "⚖️ Concurrency: {}",
+        format_batch_summary(batch_size, jobs.len())
+    );
+    outln!(
+        "📡 Scheduler command:\n   {}",
+        build_bsub_command(opts, jobs.len(), batch_size)
+    );
+    outln!(
+        "📜 Task script:\n{}",
+        build_job_script(log_file_path, opts)?
+    );
+    if let Some(previous_log) = &opts.diff_against {
+        let previous: Vec<String> = strip_log_header(&fs::read_to_string(previous_log)?)
+            .lines()
+            .map(String::from)
+            .collect();
+        let diff = jobs::diff_commands(&previous, jobs, previous_log, "(new commands)");
+        if diff.is_empty() {
+            outln!("🔍 Commands are unchanged from {}", previous_log);
+        } else {
+            outln!("🔍 Changes from {}:\n{}", previous_log, diff.trim_end());
+        }
+        return Ok(());
+    }
+    outln!("📝 Commands:");
+    for job in jobs {
+        println!("   {}", job);
+    }
+    Ok(())
+}
+
+/// Inserts a sub-array suffix before the file name's extension, if it has one.
+/// Dots in the directories leading to it are left alone.
+fn with_suffix(path: &str, suffix: &str) -> String {
+    let file = Path::new(path);
+    match (file.file_stem(), file.extension()) {
+        (Some(stem), Some(ext)) if !suffix.is_empty() => file
+            .with_file_name(format!(
+                "{}{}.{}",
+                stem.to_string_lossy(),
+                suffix,
+                ext.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{}{}", path, suffix),
+    }
+}
 
-    // Read jobs based on the input format
-    let jobs = match format {
-        InputFormat::Csv => jobs::read_jobs_from_csv(input_path, command_template)?,
-        InputFormat::Directory => jobs::read_jobs_from_dir(input_path, command_template)?,
-        // Add new formats here in the future
+/// Writes the task script exactly as it is handed to bsub, for auditing and
+/// manual reruns. Returns where it was written.
+fn keep_submitted_script(
+    keep_path: &str,
+    log_file_path: &str,
+    suffix: &str,
+    script: &str,
+) -> io::Result<String> {
+    let script_path = if keep_path.is_empty() {
+        format!("{}.sh", log_file_path.trim_end_matches(".log"))
+    } else {
+        with_suffix(keep_path, suffix)
     };
+    fs::write(&script_path, script)?;
+    Ok(script_path)
+}
 
-    if jobs.is_empty() {
-        eprintln!("No jobs found.");
-        return Ok(());
+/// The time stamped into log names and generated headers. With `--seed` it is
+/// fixed at that many seconds after the epoch, so two runs with the same seed
+/// write identical files.
+pub fn generated_at(opts: &SubmitOptions) -> NaiveDateTime {
+    match opts.seed {
+        Some(seed) => DateTime::from_timestamp(i64::from(seed), 0)
+            .unwrap_or_default()
+            .naive_utc(),
+        None => Local::now().naive_local(),
     }
+}
 
-    // Log the jobs
-    let timestamp = Local::now().format("%Y-%m-%d-%H-%M").to_string();
-    let log_file_path = format!("{}/arrayify-{}.log", log_dir, timestamp);
-    write_job_log(&log_file_path, &jobs)?;
+/// Heredoc delimiters used to embed the job list and task script in an emitted script.
+const JOBS_DELIMITER: &str = "ARRAYIFY_JOBS";
+const SCRIPT_DELIMITER: &str = "ARRAYIFY_SCRIPT";
 
-    // Submit jobs to the scheduler
-    let batch_size = calculate_batch_size(jobs.len(), batch_size);
-    let job_id = submit_jobs_to_scheduler(&log_file_path, log_dir, job_prefix,  memory_mb, threads, queue, batch_size)?;
+/// Builds a standalone script that recreates the command log and submits the
+/// array without arrayify. Quoted heredocs keep the commands byte-for-byte.
+fn build_emitted_script(
+    opts: &SubmitOptions,
+    jobs: &[String],
+    log_file_path: &str,
+    batch_size: usize,
+) -> io::Result<String> {
+    let task_script = build_job_script(log_file_path, opts)?;
+    for line in jobs.iter().map(String::as_str).chain(task_script.lines()) {
+        if line == JOBS_DELIMITER || line == SCRIPT_DELIMITER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("A command line matches the heredoc delimiter {}", line),
+            ));
+        }
+    }
 
-    // Print run statistics
-    print_run_stats(jobs.len(), log_dir, &log_file_path, &job_id);
+    let mut script = format!(
+        "#!/bin/bash\n# Generated by arrayify {} on {}\nset -euo pipefail\n\n",
+        env!("CARGO_PKG_VERSION"),
+        generated_at(opts).format("%Y-%m-%d %H:%M")
+    );
+    script.push_str(&format!(
+        "mkdir -p {}\n\n",
+        jobs::shell_quote(&opts.log_dir)
+    ));
+    script.push_str(&format!(
+        "cat > {} <<'{}'\n",
+        jobs::shell_quote(log_file_path),
+        JOBS_DELIMITER
+    ));
+    if header_lines(opts) > 0 {
+        let timestamp = generated_at(opts)
+            .format(manifest::TIMESTAMP_FORMAT)
+            .to_string();
+        script.push_str(&log_header(opts, "pending", &timestamp));
+    }
+    for job in jobs {
+        script.push_str(job);
+        script.push('\n');
+    }
+    script.push_str(JOBS_DELIMITER);
+    script.push_str("\n\n");
+    script.push_str(&format!(
+        "{} <<'{}'\n{}{}\n",
+        build_bsub_command(opts, jobs.len(), batch_size),
+        SCRIPT_DELIMITER,
+        task_script,
+        SCRIPT_DELIMITER
+    ));
+    Ok(script)
+}
+
+fn write_emitted_script(
+    script_path: &str,
+    opts: &SubmitOptions,
+    jobs: &[String],
+    log_file_path: &str,
+    batch_size: usize,
+) -> io::Result<()> {
+    let script = build_emitted_script(opts, jobs, log_file_path, batch_size)?;
+    fs::write(script_path, script)?;
+    let mut permissions = fs::metadata(script_path)?.permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(script_path, permissions)?;
+
+    outln!(
+        "📜 Submission script for {} jobs written to: {}",
+        jobs.len(),
+        script_path
+    );
+    println!(
+        "   Nothing was submitted; run it with - bash {}",
+        script_path
+    );
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+/// Result of linting a rendered command with shellcheck.
+#[derive(Debug, PartialEq)]
+enum ShellcheckOutcome {
+    NotInstalled,
+    Clean,
+    Issues { has_errors: bool, report: String },
+}
 
-    #[test]
-    fn test_read_jobs_from_csv() {
-        let mut csv_file = NamedTempFile::new().unwrap();
-        writeln!(csv_file, "header1,header2\nvalue1,value2").unwrap();
+/// Runs `shellcheck_bin` over a single rendered command, read as bash from stdin.
+fn run_shellcheck(shellcheck_bin: &str, command: &str) -> io::Result<ShellcheckOutcome> {
+    let child = Command::new(shellcheck_bin)
+        .args(["--shell=bash", "--format=gcc", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(ShellcheckOutcome::NotInstalled);
+        }
+        Err(e) => return Err(e),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", command)?;
+    }
+    let output = child.wait_with_output()?;
 
-        let jobs = jobs::read_jobs_from_csv(
-            csv_file.path().to_str().unwrap(),
-            "echo {header1} {header2}",
-        )
-        .unwrap();
-        assert_eq!(jobs, vec!["echo value1 value2"]);
+    let report = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    if output.status.success() && report.is_empty() {
+        return Ok(ShellcheckOutcome::Clean);
     }
+    let has_errors = report.lines().any(|line| line.contains(": error:"));
+    Ok(ShellcheckOutcome::Issues { has_errors, report })
+}
 
-    #[test]
-    fn test_calculate_batch_size() {
-        assert_eq!(calculate_batch_size(10, None), 2); // 20% of 10, rounded up
-        assert_eq!(calculate_batch_size(10, Some(5)), 5); // Custom batch size
-        assert_eq!(calculate_batch_size(1, None), 1); // Minimum batch size
+/// Lints the first rendered command, refusing to submit on errors unless forced.
+fn shellcheck_first_job(shellcheck_bin: &str, job: &str, force: bool) -> io::Result<()> {
+    match run_shellcheck(shellcheck_bin, job)? {
+        ShellcheckOutcome::NotInstalled => {
+            eoutln!("⚠️ shellcheck not found on PATH, skipping template check");
+        }
+        ShellcheckOutcome::Clean => outln!("🐚 shellcheck found no issues in the first command"),
+        ShellcheckOutcome::Issues { has_errors, report } => {
+            eoutln!("🐚 shellcheck report for the first command:\n{}", report);
+            if has_errors && !force {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "shellcheck found errors in the command template (use --force to submit anyway)",
+                ));
+            }
+        }
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_write_job_log() {
-        let log_file = NamedTempFile::new().unwrap();
-        let jobs = vec!["job1".to_string(), "job2".to_string()];
+/// Runs the first rendered command on the submit host so a missing binary or
+/// bad path fails here rather than in every task. With a timeout, a command
+/// still running when it expires is killed and counted as a clean start.
+fn run_smoke_test(
+    command: &str,
+    interpreter: Option<&str>,
+    timeout_secs: Option<u64>,
+) -> io::Result<()> {
+    outln!("💨 Smoke test: {}", command);
+    // The same line the task script would run
+    let mut child = Command::new("bash")
+        .arg("-c")
+        .arg(command_runner(interpreter))
+        .env("COMMAND", command)
+        .spawn()?;
 
-        write_job_log(log_file.path().to_str().unwrap(), &jobs).unwrap();
+    let status = match timeout_secs {
+        None => child.wait()?,
+        Some(timeout_secs) => {
+            let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if Instant::now() >= deadline {
+                    child.kill()?;
+                    child.wait()?;
+                    outln!(
+                        "💨 Smoke test still running after {}s; assuming it started cleanly",
+                        timeout_secs
+                    );
+                    return Ok(());
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    };
 
-        let contents = fs::read_to_string(log_file.path()).unwrap();
-        assert!(contents.contains("job1"));
-        assert!(contents.contains("job2"));
+    if status.success() {
+        outln!("💨 Smoke test passed");
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "Smoke test failed ({}); nothing was submitted",
+            status
+        )))
     }
+}
 
-    #[test]
-    fn test_submit_jobs_empty_csv() {
-        let mut csv_file = NamedTempFile::new().unwrap();
-        writeln!(csv_file, "header1,header2").unwrap(); // Empty CSV
+/// A run time limit this short is more often a typo (hours given as minutes)
+/// than intended, and ends in every task timing out.
+fn time_limit_warning(opts: &SubmitOptions) -> Option<String> {
+    let time_minutes = opts.time_minutes?;
+    if time_minutes >= opts.min_time_minutes {
+        return None;
+    }
+    Some(format!(
+        "⚠️ --time {} is below --min-time {} minutes; any task running longer will be killed",
+        time_minutes, opts.min_time_minutes
+    ))
+}
 
-        let result = submit_jobs(
-            csv_file.path().to_str().unwrap(),
-            "echo {header1}",
-            "arrayify",
-            "logs",
-            1,
-            1,
-            "normal",
-            None,
-            InputFormat::Csv,
+/// Limits a queue currently enforces, from `bqueues -l`; `None` where unset.
+#[derive(Debug, Default, PartialEq)]
+struct QueueLimits {
+    run_minutes: Option<f64>,
+    max_threads: Option<u32>,
+    mem_mb: Option<f64>,
+}
+
+pub fn query_queue(queue: &str) -> io::Result<String> {
+    let output = Command::new("bqueues").args(["-l", queue]).output()?;
+    // An unknown queue is reported on stderr
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(text)
+}
+
+/// Converts a `bqueues` memory limit such as `16 G` or `4000000` (KB, the
+/// default unit) to MB.
+fn parse_limit_mb(value: &str) -> Option<f64> {
+    let mut parts = value.split_whitespace();
+    let amount: f64 = parts.next()?.parse().ok()?;
+    let scale = match parts.next().unwrap_or("K") {
+        "K" | "KB" => 1.0 / 1000.0,
+        "M" | "MB" => 1.0,
+        "G" | "GB" => 1000.0,
+        "T" | "TB" => 1_000_000.0,
+        _ => 1.0 / 1000.0,
+    };
+    Some(amount * scale)
+}
+
+/// Reads the limits under `MAXIMUM LIMITS:`, where each limit's name is on
+/// its own line and its value on the next.
+fn parse_queue_limits(output: &str) -> QueueLimits {
+    let mut limits = QueueLimits::default();
+    let lines: Vec<&str> = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    for pair in lines.windows(2) {
+        let value = pair[1];
+        match pair[0] {
+            "RUNLIMIT" => {
+                limits.run_minutes = value.split_whitespace().next().and_then(|v| v.parse().ok())
+            }
+            // Given as "default max" or "min default max"; the last is the hard limit
+            "PROCLIMIT" | "TASKLIMIT" => {
+                limits.max_threads = value.split_whitespace().last().and_then(|v| v.parse().ok())
+            }
+            "MEMLIMIT" => limits.mem_mb = parse_limit_mb(value),
+            _ => {}
+        }
+    }
+    limits
+}
+
+/// Checks the options a rerun inherited against the queue's current limits,
+/// as the queue may have been reconfigured since the original submission.
+pub fn queue_limit_warnings(
+    opts: &SubmitOptions,
+    query: impl Fn(&str) -> io::Result<String>,
+) -> io::Result<Vec<String>> {
+    let output = query(&opts.queue)?;
+    if output.contains("No such queue") {
+        return Ok(vec![format!("⚠️ Queue {} no longer exists", opts.queue)]);
+    }
+
+    let limits = parse_queue_limits(&output);
+    let mut warnings = Vec::new();
+    let memory_mb = f64::from(opts.memory_gb * 1000);
+    if let Some(mem_mb) = limits.mem_mb.filter(|&limit| memory_mb > limit) {
+        warnings.push(format!(
+            "⚠️ {} GB memory exceeds queue {}'s current limit of {:.0} MB",
+            opts.memory_gb, opts.queue, mem_mb
+        ));
+    }
+    if let Some(max_threads) = limits.max_threads.filter(|&limit| opts.threads > limit) {
+        warnings.push(format!(
+            "⚠️ {} threads exceeds queue {}'s current limit of {}",
+            opts.threads, opts.queue, max_threads
+        ));
+    }
+    let too_long = opts.time_minutes.zip(limits.run_minutes);
+    if let Some((time_minutes, run_minutes)) =
+        too_long.filter(|&(time, limit)| f64::from(time) > limit)
+    {
+        warnings.push(format!(
+            "⚠️ --time {} minutes exceeds queue {}'s current run limit of {} minutes",
+            time_minutes, opts.queue, run_minutes
+        ));
+    }
+    Ok(warnings)
+}
+
+/// Stable 64-bit FNV-1a fingerprint of the rendered commands and the resources
+/// they run with, so identical submissions can be recognised across runs.
+fn submission_hash(opts: &SubmitOptions, jobs: &[String]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let resources = format!(
+        "{}|{}|{}|{:?}",
+        opts.queue, opts.memory_gb, opts.threads, opts.time_minutes
+    );
+    for part in jobs.iter().map(String::as_str).chain([resources.as_str()]) {
+        for byte in part.bytes().chain([b'\n']) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Refuses to repeat an identical recent submission unless forced.
+fn ensure_not_duplicate(opts: &SubmitOptions, submission_hash: &str) -> io::Result<()> {
+    let now = Local::now().naive_local();
+    let duplicate = manifest::find_recent_duplicate(
+        &opts.log_dir,
+        submission_hash,
+        opts.duplicate_window_hours,
+        now,
+    )?;
+    if let Some(previous) = duplicate {
+        eoutln!(
+            "⚠️ An identical submission (job {}) was made at {}",
+            previous.job_id,
+            previous.submitted_at
         );
+        if !opts.force {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Refusing to submit a duplicate (use --force to submit anyway)",
+            ));
+        }
+    }
+    Ok(())
+}
 
-        assert!(result.is_ok());
+/// Memory in GB for a task reading `input_bytes`: `mb_per_gb_input` MB for every
+/// GB of input, rounded up to a whole GB and never below `base_gb`.
+fn memory_tier_gb(input_bytes: u64, base_gb: u32, mb_per_gb_input: u32) -> u32 {
+    let input_gb = input_bytes as f64 / 1_000_000_000.0;
+    let scaled_gb = (input_gb * mb_per_gb_input as f64 / 1000.0).ceil() as u32;
+    scaled_gb.max(base_gb)
+}
+
+/// Groups jobs by memory tier, keeping their original order within each tier.
+/// `input_sizes` holds each job's input size in bytes, in the same order.
+fn group_by_memory_tier(
+    jobs: Vec<String>,
+    input_sizes: &[u64],
+    base_gb: u32,
+    mb_per_gb_input: u32,
+) -> BTreeMap<u32, Vec<String>> {
+    let mut tiers: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for (job, &input_bytes) in jobs.into_iter().zip(input_sizes) {
+        let tier_gb = memory_tier_gb(input_bytes, base_gb, mb_per_gb_input);
+        tiers.entry(tier_gb).or_default().push(job);
+    }
+    tiers
+}
+
+/// Memory in GB and thread count for one array of jobs.
+type Resources = (u32, u32);
+
+/// Reads each row's resources from `--memory-column`/`--threads-column`, in row
+/// order alongside `jobs`, or nothing if neither column is given. Resources
+/// for a column not given are the global setting.
+fn read_row_resources(opts: &SubmitOptions, jobs: &[String]) -> io::Result<Vec<Resources>> {
+    if opts.threads_column.is_none() && opts.memory_column.is_none() {
+        return Ok(Vec::new());
+    }
+    if !matches!(opts.format, InputFormat::Csv) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--memory-column and --threads-column are only supported with --csv",
+        ));
+    }
+
+    let column_placeholder = |column: &Option<String>| {
+        column
+            .as_ref()
+            .map(|column| format!("{{{}}}", column))
+            .unwrap_or_default()
+    };
+    let template = format!(
+        "{}{}{}",
+        column_placeholder(&opts.memory_column),
+        RENDER_SEPARATOR,
+        column_placeholder(&opts.threads_column)
+    );
+    let (rendered, _) = render_input(opts, &template)?;
+
+    let parse = |row: usize, column: &Option<String>, value: &str, default: u32| {
+        let Some(column) = column else {
+            return Ok(default);
+        };
+        value.trim().parse::<u32>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Row {}: column {} must be a whole number, got '{}'",
+                    row + 1,
+                    column,
+                    value
+                ),
+            )
+        })
+    };
+    let mut resources = Vec::with_capacity(jobs.len());
+    for (row, values) in rendered.iter().take(jobs.len()).enumerate() {
+        let (memory, threads) = values.split_once(RENDER_SEPARATOR).unwrap_or_default();
+        let memory_gb = parse(row, &opts.memory_column, memory, opts.memory_gb)?;
+        let threads = parse(row, &opts.threads_column, threads, opts.threads)?;
+        resources.push((memory_gb, threads));
+    }
+    Ok(resources)
+}
+
+/// Keeps the jobs `keep` accepts, along with the input sizes and row
+/// resources of those jobs when there are any, so all three stay aligned.
+fn retain_jobs(
+    jobs: &mut Vec<String>,
+    input_sizes: &mut Vec<u64>,
+    row_resources: &mut Vec<Resources>,
+    mut keep: impl FnMut(&str) -> bool,
+) {
+    let kept: Vec<bool> = jobs.iter().map(|job| keep(job)).collect();
+    retain_flagged(jobs, &kept);
+    retain_flagged(input_sizes, &kept);
+    retain_flagged(row_resources, &kept);
+}
+
+/// Keeps the values whose flag is set, leaving an empty list as it is.
+fn retain_flagged<T>(values: &mut Vec<T>, kept: &[bool]) {
+    let mut flags = kept.iter();
+    values.retain(|_| flags.next().copied().unwrap_or(true));
+}
+
+/// Splits the jobs into one array per distinct set of resources, from the
+/// input size with `--mem-per-gb-input` or the CSV resource columns.
+fn split_sub_arrays(
+    opts: &SubmitOptions,
+    jobs: Vec<String>,
+    input_sizes: &[u64],
+    row_resources: &[Resources],
+) -> Vec<(SubmitOptions, Vec<String>)> {
+    let groups: BTreeMap<Resources, Vec<String>> =
+        if let Some(mb_per_gb_input) = opts.mem_per_gb_input {
+            group_by_memory_tier(jobs, input_sizes, opts.memory_gb, mb_per_gb_input)
+                .into_iter()
+                .map(|(memory_gb, tier_jobs)| ((memory_gb, opts.threads), tier_jobs))
+                .collect()
+        } else if !row_resources.is_empty() {
+            let mut groups: BTreeMap<Resources, Vec<String>> = BTreeMap::new();
+            for (job, resources) in jobs.into_iter().zip(row_resources) {
+                groups.entry(*resources).or_default().push(job);
+            }
+            groups
+        } else {
+            return vec![(opts.clone(), jobs)];
+        };
+
+    groups
+        .into_iter()
+        .map(|((memory_gb, threads), group_jobs)| {
+            let group_opts = SubmitOptions {
+                memory_gb,
+                threads,
+                ..opts.clone()
+            };
+            (group_opts, group_jobs)
+        })
+        .collect()
+}
+
+fn csv_options(opts: &SubmitOptions) -> CsvOptions {
+    CsvOptions {
+        pad_short_rows: opts.pad_short_rows,
+        encoding: opts.encoding.clone(),
+        required_columns: opts.required_columns.clone(),
+        template_column: opts.template_column.clone(),
+        collect_errors: opts.pre_submit_validate,
+        ..Default::default()
+    }
+}
+
+fn pair_options(opts: &SubmitOptions) -> PairOptions {
+    PairOptions {
+        absolute_paths: opts.absolute_paths,
+        reads_separator: opts.reads_separator.clone(),
+        merge_lanes: opts.merge_lanes.is_some(),
+        lane_separator: opts.merge_lanes.clone().unwrap_or_default(),
+    }
+}
+
+/// Renders `command_template` for every input based on the input format, along
+/// with each command's input size in bytes, in the same order, when memory is
+/// scaled by input size.
+fn render_input(
+    opts: &SubmitOptions,
+    command_template: &str,
+) -> io::Result<(Vec<String>, Vec<u64>)> {
+    if opts.mem_per_gb_input.is_none() {
+        let jobs = match opts.format {
+            InputFormat::Csv if opts.extra_inputs.is_empty() => {
+                jobs::read_jobs_from_csv(&opts.input_path, command_template, &csv_options(opts))?
+            }
+            InputFormat::Csv => {
+                let csv_files: Vec<String> = std::iter::once(opts.input_path.clone())
+                    .chain(opts.extra_inputs.iter().cloned())
+                    .collect();
+                jobs::read_jobs_from_csvs(&csv_files, command_template, &csv_options(opts))?
+            }
+            InputFormat::Directory => {
+                jobs::read_jobs_from_dir(&opts.input_path, command_template, &pair_options(opts))?
+            }
+            InputFormat::Glob => {
+                jobs::read_jobs_from_glob(&opts.input_path, command_template, &pair_options(opts))?
+            }
+            InputFormat::List => {
+                jobs::read_jobs_from_list(&opts.input_path, command_template, opts.comment_char)?
+            }
+            InputFormat::JobsFile => read_jobs_file(&opts.input_path)?,
+            InputFormat::Json => jobs::read_jobs_from_json(&opts.input_path, command_template)?,
+            // Add new formats here in the future
+        };
+        return Ok((jobs, Vec::new()));
+    }
+
+    let sized_jobs = match opts.format {
+        InputFormat::Directory => {
+            jobs::read_sized_jobs_from_dir(&opts.input_path, command_template, &pair_options(opts))?
+        }
+        InputFormat::Glob => jobs::read_sized_jobs_from_glob(
+            &opts.input_path,
+            command_template,
+            &pair_options(opts),
+        )?,
+        InputFormat::Csv | InputFormat::List | InputFormat::JobsFile | InputFormat::Json => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--mem-per-gb-input is only supported with --dir or --glob",
+            ));
+        }
+    };
+    Ok(sized_jobs.into_iter().unzip())
+}
+
+/// Separates templates combined into one, so that all of them are rendered
+/// from the same row in one pass (e.g. the command and its task log path).
+const RENDER_SEPARATOR: char = '\u{1f}';
+
+/// Placeholders the input provides, used to resolve `--placeholder-alias`.
+fn available_placeholders(opts: &SubmitOptions) -> io::Result<Vec<String>> {
+    Ok(match opts.format {
+        InputFormat::Csv => {
+            let headers = jobs::read_csv_headers(&opts.input_path, &csv_options(opts))?;
+            let mut names: Vec<String> = headers.iter().map(|h| jobs::sanitize_header(h)).collect();
+            names.extend(headers);
+            names.push("ROW".to_string());
+            names
+        }
+        InputFormat::Directory | InputFormat::Glob => {
+            vec!["ID".into(), "R1".into(), "R2".into(), "READS".into()]
+        }
+        InputFormat::List => vec!["ITEM".into()],
+        InputFormat::JobsFile => Vec::new(),
+        InputFormat::Json => jobs::read_json_fields(&opts.input_path)?,
+    })
+}
+
+/// Rewrites aliased placeholders to whichever name of each pair the input
+/// provides, so one template works across input formats.
+fn resolve_aliases(template: &str, aliases: &[(String, String)], available: &[String]) -> String {
+    let mut template = template.to_string();
+    for (name, alias) in aliases {
+        let has = |placeholder: &String| available.contains(placeholder);
+        let (from, to) = match (has(name), has(alias)) {
+            (true, false) => (alias, name),
+            (false, true) => (name, alias),
+            _ => continue,
+        };
+        template = template.replace(&format!("{{{}}}", from), &format!("{{{}}}", to));
+    }
+    template
+}
+
+/// Reads jobs based on the input format. With `--task-log` each command is
+/// wrapped to send its stdout/stderr to its own rendered path.
+fn read_jobs(opts: &SubmitOptions) -> io::Result<(Vec<String>, Vec<u64>)> {
+    // Render with the usual braces, keeping the template's own braces aside
+    let translate = |template: &str| match &opts.delimiters {
+        Some((open, close)) => jobs::translate_delimiters(template, open, close),
+        None => template.to_string(),
+    };
+    let command_template = translate(&opts.command_template);
+    let task_log = opts.task_log.as_deref().map(translate);
+
+    // Secrets are checked now but only expanded by the task, so they never hit the log
+    let uses_env =
+        jobs::check_env_placeholders(&command_template, |name| std::env::var(name).ok())?;
+
+    let (rendered, input_sizes) = render_templates(opts, &command_template, task_log.as_deref())?;
+    let jobs = rendered
+        .into_iter()
+        .map(|job| {
+            let mut finished = if uses_env {
+                jobs::mask_env_placeholders(&job)
+            } else {
+                job
+            };
+            if opts.delimiters.is_some() {
+                finished = jobs::restore_braces(&finished);
+            }
+            finished
+        })
+        .collect();
+    Ok((jobs, input_sizes))
+}
+
+fn render_templates(
+    opts: &SubmitOptions,
+    command_template: &str,
+    task_log: Option<&str>,
+) -> io::Result<(Vec<String>, Vec<u64>)> {
+    // The list may be stdin, which can only be read once
+    let needs_available = !opts.placeholder_aliases.is_empty() || opts.template_vars.is_some();
+    let available = if needs_available && opts.input_path != "-" {
+        available_placeholders(opts)?
+    } else {
+        Vec::new()
+    };
+    let vars = match &opts.template_vars {
+        Some(vars_file) => jobs::read_template_vars(vars_file)?,
+        None => BTreeMap::new(),
+    };
+    let prepare = |template: &str| {
+        let template = resolve_aliases(template, &opts.placeholder_aliases, &available);
+        let delimiters = match &opts.delimiters {
+            Some((open, close)) => (open.as_str(), close.as_str()),
+            None => ("{", "}"),
+        };
+        jobs::apply_template_vars(&template, &vars, &available, delimiters)
+    };
+    let command_template = prepare(command_template);
+
+    let Some(task_log) = task_log else {
+        return render_input(opts, &command_template);
+    };
+
+    let task_log = prepare(task_log);
+    let template = format!("{}{}{}", command_template, RENDER_SEPARATOR, task_log);
+    let (rendered, input_sizes) = render_input(opts, &template)?;
+    let mut jobs = Vec::with_capacity(rendered.len());
+    for job in rendered {
+        let (command, log_path) = job.split_once(RENDER_SEPARATOR).unwrap_or((&job, ""));
+        // Leave an empty command empty so it can be spotted rather than wrapped
+        if command.trim().is_empty() {
+            jobs.push(String::new());
+            continue;
+        }
+        let wrapped = format!(
+            "{{ {}; }} > {} 2> {}",
+            command,
+            jobs::shell_quote(&format!("{}.out", log_path)),
+            jobs::shell_quote(&format!("{}.err", log_path))
+        );
+        jobs.push(wrapped);
+    }
+    Ok((jobs, input_sizes))
+}
+
+/// Renders the input and returns just its first command, for `doctor`.
+pub fn first_command(opts: &SubmitOptions) -> io::Result<Option<String>> {
+    let (jobs, _) = read_jobs(opts)?;
+    Ok(jobs.into_iter().next())
+}
+
+/// Renders every command of the input without submitting anything, for
+/// `export` and `--local`. Repeats are dropped with `--dedup`, as are commands
+/// that render empty unless `--no-skip-empty` is given.
+pub fn rendered_commands(opts: &SubmitOptions) -> io::Result<Vec<String>> {
+    let mut jobs = match opts.format {
+        InputFormat::JobsFile => fs::read_to_string(&opts.input_path)?
+            .lines()
+            .map(String::from)
+            .collect(),
+        _ => read_jobs(opts)?.0,
+    };
+    if opts.dedup {
+        let mut first = jobs::first_occurrences();
+        jobs.retain(|job| first(job));
+    }
+    if opts.skip_empty {
+        jobs.retain(|job| !job.trim().is_empty());
+    }
+    Ok(jobs)
+}
+
+/// The command log holds one command per line, so a value with an embedded
+/// newline, such as a quoted multi-line CSV field, would split its task in two.
+fn ensure_single_line(jobs: &[String]) -> io::Result<()> {
+    match jobs.iter().position(|job| job.contains('\n')) {
+        Some(index) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Command {} spans several lines, but each task's command must fit on one: {:?}",
+                index + 1,
+                jobs[index]
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Guesses which words of a command are file paths: anything absolute, including
+/// the value of `--opt=/path` style arguments.
+fn path_like_tokens(command: &str) -> Vec<&str> {
+    command
+        .split_whitespace()
+        .map(|token| token.rsplit('=').next().unwrap_or(token))
+        .map(|token| token.trim_matches(|c| "'\"();|&<>".contains(c)))
+        .filter(|token| token.len() > 1 && token.starts_with('/'))
+        .collect()
+}
+
+/// Lists the paths referenced by the jobs that don't exist on this host. Paths
+/// come from `--path-columns` if given, otherwise from the rendered commands.
+fn find_missing_paths(opts: &SubmitOptions, jobs: &[String]) -> io::Result<Vec<String>> {
+    let candidates: Vec<String> = if opts.path_columns.is_empty() {
+        jobs.iter()
+            .flat_map(|job| path_like_tokens(job))
+            .map(String::from)
+            .collect()
+    } else {
+        let template: Vec<String> = opts
+            .path_columns
+            .iter()
+            .map(|column| format!("{{{}}}", column))
+            .collect();
+        let template = template.join(&RENDER_SEPARATOR.to_string());
+        let (rendered, _) = render_input(opts, &template)?;
+        rendered
+            .iter()
+            .flat_map(|row| row.split(RENDER_SEPARATOR))
+            .filter(|path| !path.is_empty())
+            .map(String::from)
+            .collect()
+    };
+
+    let mut missing = Vec::new();
+    for path in candidates {
+        if !std::path::Path::new(&path).exists() && !missing.contains(&path) {
+            missing.push(path);
+        }
+    }
+    Ok(missing)
+}
+
+/// Guesses which words of a command are output paths: redirection targets and
+/// the values of `-o` and `--output`.
+fn output_tokens(command: &str) -> Vec<&str> {
+    let mut outputs = Vec::new();
+    let mut previous = "";
+    for token in command.split_whitespace() {
+        let output = if matches!(previous, ">" | ">>" | "2>" | "-o" | "--output") {
+            Some(token)
+        } else if let Some(path) = token.strip_prefix("--output=") {
+            Some(path)
+        } else {
+            token
+                .strip_prefix(">>")
+                .or_else(|| token.strip_prefix('>'))
+                .filter(|path| !path.is_empty())
+        };
+        if let Some(output) = output {
+            let output = output.trim_matches(|c| "'\"();|&<>".contains(c));
+            if !output.is_empty() && output != "/dev/null" {
+                outputs.push(output);
+            }
+        }
+        previous = token;
+    }
+    outputs
+}
+
+/// Lists the output paths written by more than one job, with how many write
+/// each. Paths come from `--output-columns` if given, otherwise from the
+/// rendered commands.
+fn find_output_collisions(
+    opts: &SubmitOptions,
+    jobs: &[String],
+) -> io::Result<Vec<(String, usize)>> {
+    let per_job: Vec<Vec<String>> = if opts.output_columns.is_empty() {
+        jobs.iter()
+            .map(|job| output_tokens(job).into_iter().map(String::from).collect())
+            .collect()
+    } else {
+        let template: Vec<String> = opts
+            .output_columns
+            .iter()
+            .map(|column| format!("{{{}}}", column))
+            .collect();
+        let template = template.join(&RENDER_SEPARATOR.to_string());
+        let (rendered, _) = render_input(opts, &template)?;
+        rendered
+            .iter()
+            .map(|row| {
+                row.split(RENDER_SEPARATOR)
+                    .filter(|path| !path.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .collect()
+    };
+
+    // A job writing the same path twice, e.g. `-o out.bam > out.bam`, is its own business
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for mut outputs in per_job {
+        outputs.sort();
+        outputs.dedup();
+        for output in outputs {
+            match counts.iter_mut().find(|(path, _)| *path == output) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((output, 1)),
+            }
+        }
+    }
+    counts.retain(|(_, count)| *count > 1);
+    Ok(counts)
+}
+
+fn warn_output_collisions(collisions: &[(String, usize)]) {
+    if collisions.is_empty() {
+        return;
+    }
+    eoutln!(
+        "⚠️ {} output paths are written by more than one task, which will overwrite each other:",
+        collisions.len()
+    );
+    for (path, count) in collisions.iter().take(MAX_MISSING_PATHS_SHOWN) {
+        eprintln!("   {} x{}", path, count);
+    }
+    if collisions.len() > MAX_MISSING_PATHS_SHOWN {
+        eprintln!(
+            "   ...and {} more",
+            collisions.len() - MAX_MISSING_PATHS_SHOWN
+        );
+    }
+}
+
+/// Most paths listed by --check-paths or --warn-collisions before the rest are
+/// summarised.
+const MAX_MISSING_PATHS_SHOWN: usize = 20;
+
+fn warn_missing_paths(missing: &[String]) {
+    if missing.is_empty() {
+        outln!("📂 All referenced paths exist");
+        return;
+    }
+    eoutln!("⚠️ {} referenced paths don't exist:", missing.len());
+    for path in missing.iter().take(MAX_MISSING_PATHS_SHOWN) {
+        eprintln!("   {}", path);
+    }
+    if missing.len() > MAX_MISSING_PATHS_SHOWN {
+        eprintln!("   ...and {} more", missing.len() - MAX_MISSING_PATHS_SHOWN);
+    }
+}
+
+/// Times the input reader on its own, without logging or submitting anything.
+fn profile_reader(opts: &SubmitOptions) -> io::Result<String> {
+    let start = Instant::now();
+    let (jobs, _) = read_jobs(opts)?;
+    let elapsed = start.elapsed().as_secs_f64();
+    let rate = if elapsed > 0.0 {
+        jobs.len() as f64 / elapsed
+    } else {
+        f64::INFINITY
+    };
+    Ok(format!(
+        "⏱️ {:?} reader: {} jobs in {:.3}s ({:.0} rows/sec)",
+        opts.format,
+        jobs.len(),
+        elapsed,
+        rate
+    ))
+}
+
+fn read_jobs_file(jobs_file: &str) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(jobs_file)?
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// Whether the options submit to the scheduler, rather than only showing or
+/// emitting what would be submitted.
+fn is_submitting(opts: &SubmitOptions) -> bool {
+    !opts.dry_run && opts.emit_script.is_none()
+}
+
+/// `--after-array` pairs task N with element N of the upstream array, which only
+/// holds while task N runs line N of the input. Refuses it when the tasks were
+/// renumbered, `renumbered_by` saying how.
+fn ensure_after_array_aligned(opts: &SubmitOptions, renumbered_by: Option<&str>) -> io::Result<()> {
+    match (opts.after_array, renumbered_by) {
+        (Some(upstream), Some(cause)) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "--after-array {} makes task N wait for task N of the upstream array, but {} \
+                 renumbers the tasks so they no longer line up",
+                upstream, cause
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the jobs before anything is submitted: the time limit, shellcheck,
+/// `--no-duplicate` and the smoke test. Returns the submission's hash.
+fn pre_submit_checks(opts: &SubmitOptions, jobs: &[String]) -> io::Result<String> {
+    if let Some(warning) = time_limit_warning(opts) {
+        eoutln!("{}", warning);
+    }
+
+    // Catch shell mistakes before they reach the farm
+    if opts.shellcheck {
+        shellcheck_first_job("shellcheck", &jobs[0], opts.force)?;
+    }
+
+    // Guard against accidentally submitting the same thing twice
+    let submission_hash = submission_hash(opts, jobs);
+    if is_submitting(opts) && opts.no_duplicate {
+        ensure_not_duplicate(opts, &submission_hash)?;
+    }
+
+    // Make sure the command can at least start before queuing the whole array
+    if is_submitting(opts) && opts.smoke_test {
+        run_smoke_test(
+            &jobs[0],
+            opts.interpreter.as_deref(),
+            opts.smoke_timeout_secs,
+        )?;
+    }
+    Ok(submission_hash)
+}
+
+/// Refuses `num_tasks` more tasks past `--max-pending`, to be a good citizen
+/// on shared clusters.
+fn check_pending_capacity(opts: &SubmitOptions, num_tasks: usize) -> io::Result<()> {
+    match opts.max_pending {
+        Some(max_pending) if is_submitting(opts) => {
+            ensure_pending_capacity(query_pending_jobs()?, num_tasks, max_pending)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Warns if bjobs doesn't list the `num_tasks` elements an array was submitted
+/// with, to catch silent partial submissions.
+fn verify_submission(job_id: &str, num_tasks: usize) {
+    match check::query_bjobs(job_id) {
+        Ok(output) => {
+            if let Some(warning) = check::verify_element_count(&output, num_tasks) {
+                eoutln!("{}", warning);
+            }
+        }
+        Err(e) => eoutln!("⚠️ Could not verify the submission with bjobs: {}", e),
+    }
+}
+
+/// Submits a ready-made file of commands, one per line. Nothing is rendered
+/// or re-logged: the tasks read their commands straight from the file.
+fn submit_jobs_file(opts: &SubmitOptions) -> io::Result<Vec<Manifest>> {
+    let jobs_file = &opts.input_path;
+    let num_jobs = count_lines_in_file(jobs_file)?;
+    if num_jobs == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Jobs file {} is empty", jobs_file),
+        ));
+    }
+    let chunked = match opts.index_range {
+        Some(_) => None,
+        None => fit_array_limit(opts, num_jobs, resolve_array_limit(opts, query_array_limit)),
+    };
+    ensure_after_array_aligned(opts, (opts.chunk > 1).then_some("--chunk"))?;
+    if chunked.is_some() {
+        ensure_after_array_aligned(opts, Some("chunking to fit the array size limit"))?;
+    }
+    let opts = chunked.as_ref().unwrap_or(opts);
+    let num_tasks = match opts.index_range {
+        Some((start, end)) if end > num_jobs => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "--index-range {}-{} is beyond the {} lines of {}",
+                    start, end, num_jobs, jobs_file
+                ),
+            ));
+        }
+        Some((start, end)) => end - start + 1,
+        None => array_size(opts, num_jobs),
+    };
+    let batch_size = resolve_batch_size(opts, num_tasks, query_queue_slots);
+
+    let all_jobs = read_jobs_file(jobs_file)?;
+    let jobs = match opts.index_range {
+        Some((start, end)) => &all_jobs[start - 1..end],
+        None => &all_jobs[..],
+    };
+    let submission_hash = pre_submit_checks(opts, jobs)?;
+    check_pending_capacity(opts, num_tasks)?;
+
+    if opts.dry_run {
+        print_dry_run(opts, jobs, jobs_file, batch_size)?;
+        return Ok(Vec::new());
+    }
+    if let Some(script_path) = &opts.emit_script {
+        write_emitted_script(script_path, opts, &all_jobs, jobs_file, batch_size)?;
+        return Ok(Vec::new());
+    }
+
+    // Task stdout/stderr still go to the log directory
+    fs::create_dir_all(&opts.log_dir)?;
+    let timestamp = generated_at(opts)
+        .format(manifest::TIMESTAMP_FORMAT)
+        .to_string();
+    let script = build_job_script(jobs_file, opts)?;
+    // Kept in the log directory, as the jobs file may be somewhere shared
+    let mut kept_script = None;
+    if let Some(keep_path) = &opts.keep_script {
+        let log_name = format!("{}/arrayify-{}.log", opts.log_dir, timestamp);
+        let script_path = keep_submitted_script(keep_path, &log_name, "", &script)?;
+        cleanup::TRACKED.track(&script_path);
+        outln!("📜 Task script kept at: {}", script_path);
+        kept_script = Some(script_path);
+    }
+    let job_id = submit_jobs_to_scheduler(jobs_file, num_jobs, opts, batch_size, &script)?;
+    if let Some(kept_script) = &kept_script {
+        cleanup::TRACKED.forget(kept_script);
+    }
+    if opts.verify {
+        verify_submission(&job_id, num_tasks);
+    }
+    let summary = RunSummary {
+        job_id: job_id.clone(),
+        submitted_at: timestamp.clone(),
+        num_jobs,
+        batch_size,
+        log_file: jobs_file.to_string(),
+        log_dir: opts.log_dir.clone(),
+    };
+    report_run(opts, &summary, "")?;
+
+    let manifest = Manifest {
+        job_id,
+        submitted_at: timestamp.clone(),
+        num_jobs,
+        log_file: jobs_file.to_string(),
+        options: opts.clone(),
+        submission_hash,
+        note: opts.note.clone(),
+        requested_memory_gb: Some(opts.memory_gb),
+        requested_threads: Some(opts.threads),
+    };
+    let manifest_path = format!("{}/arrayify-{}.json", opts.log_dir, timestamp);
+    manifest::write_manifest(&manifest_path, &manifest)?;
+    Ok(vec![manifest])
+}
+
+/// Renders and submits the input, returning the manifest of each array
+/// submitted (none for a dry run or an emitted script).
+pub fn submit_jobs(opts: &SubmitOptions) -> io::Result<Vec<Manifest>> {
+    if opts.profile_readers {
+        println!("{}", profile_reader(opts)?);
+        return Ok(Vec::new());
+    }
+
+    if opts.local {
+        let jobs = rendered_commands(opts)?;
+        ensure_single_line(&jobs)?;
+        let workers = opts
+            .local_jobs
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        let job_id = local_job_id();
+        outln!(
+            "🚀 Running {} tasks locally as job {}, {} at a time",
+            jobs.len().div_ceil(opts.chunk.max(1)),
+            job_id,
+            workers
+        );
+        let codes = run_locally(&jobs, workers, &job_id, opts)?;
+        return report_local_run(&job_id, &codes, &opts.log_dir);
+    }
+
+    if let InputFormat::JobsFile = opts.format {
+        return submit_jobs_file(opts);
+    }
+
+    let (mut jobs, mut input_sizes) = read_jobs(opts)?;
+    let mut row_resources = read_row_resources(opts, &jobs)?;
+    let mut renumbered_by = (opts.chunk > 1).then_some("--chunk");
+
+    if opts.dedup {
+        let before = jobs.len();
+        retain_jobs(
+            &mut jobs,
+            &mut input_sizes,
+            &mut row_resources,
+            jobs::first_occurrences(),
+        );
+        let dropped = before - jobs.len();
+        if dropped > 0 {
+            outln!("🧹 Dropped {} duplicate commands", dropped);
+            renumbered_by = Some("--dedup");
+        }
+    }
+
+    // An empty command would run as a no-op task that confuses check
+    if opts.skip_empty {
+        let before = jobs.len();
+        retain_jobs(&mut jobs, &mut input_sizes, &mut row_resources, |job| {
+            !job.trim().is_empty()
+        });
+        if jobs.len() < before {
+            eoutln!(
+                "⚠️ Skipped {} commands that rendered empty",
+                before - jobs.len()
+            );
+            renumbered_by = Some("skipping empty commands");
+        }
+    }
+
+    if jobs.is_empty() {
+        eprintln!("No jobs found.");
+        return Ok(Vec::new());
+    }
+    ensure_single_line(&jobs)?;
+
+    // Warn about inputs that aren't where the commands expect them
+    if opts.check_paths {
+        warn_missing_paths(&find_missing_paths(opts, &jobs)?);
+    }
+    if opts.warn_collisions {
+        warn_output_collisions(&find_output_collisions(opts, &jobs)?);
+    }
+
+    // Split into one array per memory tier or per set of row resources
+    let sub_arrays = split_sub_arrays(opts, jobs.clone(), &input_sizes, &row_resources);
+    if sub_arrays.len() > 1 {
+        renumbered_by = Some("splitting the submission into several arrays");
+    }
+    ensure_after_array_aligned(opts, renumbered_by)?;
+
+    let submission_hash = pre_submit_checks(opts, &jobs)?;
+    check_pending_capacity(opts, total_array_size(&sub_arrays))?;
+
+    let timestamp = generated_at(opts)
+        .format(manifest::TIMESTAMP_FORMAT)
+        .to_string();
+    let tiered = sub_arrays.len() > 1;
+    let arrays: Vec<(&SubmitOptions, &[String], String, String)> = sub_arrays
+        .iter()
+        .map(|(array_opts, array_jobs)| {
+            let suffix = match (tiered, &opts.threads_column) {
+                (false, _) => String::new(),
+                (true, None) => format!("-mem{}G", array_opts.memory_gb),
+                (true, Some(_)) => {
+                    format!("-mem{}G-n{}", array_opts.memory_gb, array_opts.threads)
+                }
+            };
+            let log_file_path = format!("{}/arrayify-{}{}.log", opts.log_dir, timestamp, suffix);
+            (array_opts, array_jobs.as_slice(), log_file_path, suffix)
+        })
+        .collect();
+
+    if opts.parallel_submit && tiered {
+        return submit_arrays_in_parallel(opts, &arrays, &timestamp, &submission_hash);
+    }
+    let mut manifests = Vec::new();
+    for (array_opts, array_jobs, log_file_path, suffix) in &arrays {
+        manifests.extend(submit_array(
+            opts,
+            array_opts,
+            array_jobs,
+            log_file_path,
+            &timestamp,
+            suffix,
+            &submission_hash,
+        )?);
+    }
+    Ok(manifests)
+}
+
+/// Writes the command log and builds the task script that reads it, or with
+/// `--no-log` builds a script with the commands embedded and writes nothing.
+fn prepare_task_script(
+    log_file_path: &str,
+    jobs: &[String],
+    opts: &SubmitOptions,
+    timestamp: &str,
+) -> io::Result<String> {
+    // Task stdout/stderr still go to the log directory
+    fs::create_dir_all(&opts.log_dir)?;
+    if opts.no_log {
+        return build_task_script(CommandSource::Embedded(jobs), opts);
+    }
+    let header = opts
+        .log_header
+        .then(|| log_header(opts, "pending", timestamp));
+    write_job_log(log_file_path, header.as_deref(), jobs)?;
+    cleanup::TRACKED.track(log_file_path);
+    build_job_script(log_file_path, opts)
+}
+
+/// A sub-array whose command log and task script are written, ready for bsub.
+struct PreparedArray {
+    /// Resources of this array, after any automatic chunking
+    array_opts: SubmitOptions,
+    batch_size: usize,
+    script: String,
+    log_file_path: String,
+    suffix: String,
+    kept_script: Option<String>,
+}
+
+/// Writes what one array needs before it is handed to bsub. Returns `None`
+/// for a dry run or `--emit-script`, which stop short of submitting.
+fn prepare_array(
+    array_opts: &SubmitOptions,
+    jobs: &[String],
+    log_file_path: &str,
+    timestamp: &str,
+    suffix: &str,
+) -> io::Result<Option<PreparedArray>> {
+    // Larger arrays are rejected or truncated, so run several commands per task
+    let limit = resolve_array_limit(array_opts, query_array_limit);
+    let chunked = fit_array_limit(array_opts, jobs.len(), limit);
+    if chunked.is_some() {
+        ensure_after_array_aligned(array_opts, Some("chunking to fit the array size limit"))?;
+    }
+    let array_opts = chunked.as_ref().unwrap_or(array_opts);
+    let batch_size = resolve_batch_size(
+        array_opts,
+        array_size(array_opts, jobs.len()),
+        query_queue_slots,
+    );
+
+    if array_opts.dry_run {
+        print_dry_run(array_opts, jobs, log_file_path, batch_size)?;
+        return Ok(None);
+    }
+
+    if let Some(script_path) = &array_opts.emit_script {
+        let script_path = with_suffix(script_path, suffix);
+        write_emitted_script(&script_path, array_opts, jobs, log_file_path, batch_size)?;
+        return Ok(None);
+    }
+
+    let script = prepare_task_script(log_file_path, jobs, array_opts, timestamp)?;
+    let mut kept_script = None;
+    if let Some(keep_path) = &array_opts.keep_script {
+        let script_path = keep_submitted_script(keep_path, log_file_path, suffix, &script)?;
+        cleanup::TRACKED.track(&script_path);
+        outln!("📜 Task script kept at: {}", script_path);
+        kept_script = Some(script_path);
+    }
+
+    Ok(Some(PreparedArray {
+        array_opts: array_opts.clone(),
+        batch_size,
+        script,
+        log_file_path: log_file_path.to_string(),
+        suffix: suffix.to_string(),
+        kept_script,
+    }))
+}
+
+/// Hands a prepared array to bsub, returning its job ID. Once accepted its
+/// files belong to the array, so an interrupt leaves them alone.
+fn submit_prepared(prepared: &PreparedArray, num_jobs: usize) -> io::Result<String> {
+    let job_id = submit_jobs_to_scheduler(
+        &prepared.log_file_path,
+        num_jobs,
+        &prepared.array_opts,
+        prepared.batch_size,
+        &prepared.script,
+    )?;
+    cleanup::TRACKED.forget(&prepared.log_file_path);
+    if let Some(kept_script) = &prepared.kept_script {
+        cleanup::TRACKED.forget(kept_script);
+    }
+    Ok(job_id)
+}
+
+/// Reports a submitted array and records its manifest. `opts` are the options
+/// as given, which the manifest keeps for reruns next to the array's own
+/// memory and threads.
+fn finish_array(
+    opts: &SubmitOptions,
+    prepared: &PreparedArray,
+    jobs: &[String],
+    job_id: String,
+    timestamp: &str,
+    submission_hash: &str,
+) -> io::Result<Manifest> {
+    let array_opts = &prepared.array_opts;
+    let log_file_path = prepared.log_file_path.as_str();
+    if array_opts.log_header && !array_opts.no_log {
+        fill_in_log_header(log_file_path, &job_id)?;
+    }
+
+    if array_opts.verify {
+        verify_submission(&job_id, array_size(array_opts, jobs.len()));
+    }
+
+    // Print run statistics
+    let summary = RunSummary {
+        job_id: job_id.clone(),
+        submitted_at: timestamp.to_string(),
+        num_jobs: jobs.len(),
+        batch_size: prepared.batch_size,
+        log_file: log_file_path.to_string(),
+        log_dir: array_opts.log_dir.clone(),
+    };
+    report_run(opts, &summary, &prepared.suffix)?;
+
+    // Record the submission so it can be rerun later
+    let manifest = Manifest {
+        job_id,
+        submitted_at: timestamp.to_string(),
+        num_jobs: jobs.len(),
+        log_file: log_file_path.to_string(),
+        // The chunk actually used, which check needs to map indices to commands
+        options: SubmitOptions {
+            chunk: array_opts.chunk,
+            ..opts.clone()
+        },
+        submission_hash: submission_hash.to_string(),
+        note: opts.note.clone(),
+        requested_memory_gb: Some(array_opts.memory_gb),
+        requested_threads: Some(array_opts.threads),
+    };
+    let manifest_path = format!("{}.json", log_file_path.trim_end_matches(".log"));
+    manifest::write_manifest(&manifest_path, &manifest)?;
+    Ok(manifest)
+}
+
+/// Logs and submits one array. `opts` are the options as given and
+/// `array_opts` the resources this particular array uses; the manifest
+/// records both.
+fn submit_array(
+    opts: &SubmitOptions,
+    array_opts: &SubmitOptions,
+    jobs: &[String],
+    log_file_path: &str,
+    timestamp: &str,
+    suffix: &str,
+    submission_hash: &str,
+) -> io::Result<Option<Manifest>> {
+    let Some(prepared) = prepare_array(array_opts, jobs, log_file_path, timestamp, suffix)? else {
+        return Ok(None);
+    };
+    let job_id = submit_prepared(&prepared, jobs.len())?;
+    finish_array(opts, &prepared, jobs, job_id, timestamp, submission_hash).map(Some)
+}
+
+/// Most bsub calls `--parallel-submit` has in flight at once.
+const MAX_PARALLEL_SUBMISSIONS: usize = 4;
+
+/// Calls `run` on every item, at most `max_threads` at a time, and returns
+/// the results in the order of `items` however the calls interleave.
+fn run_bounded<T: Sync, R: Send>(
+    items: &[T],
+    max_threads: usize,
+    run: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..max_threads.min(items.len()) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    let result = run(item);
+                    results.lock().unwrap()[i] = Some(result);
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is run"))
+        .collect()
+}
+
+/// Job ID the tasks of a `--local` run see, e.g. in their log and `--exit-codes`
+/// file names: the process ID, so that concurrent runs keep apart.
+fn local_job_id() -> String {
+    std::process::id().to_string()
+}
+
+/// Runs the tasks on this machine, at most `workers` at a time, with the same
+/// task script the array would get, so the prolog, epilog, strict bash,
+/// retries, chunking and exit code files all apply. The script is kept in the
+/// log directory while the tasks run. Each task's output goes to
+/// `<LOG_DIR>/job_<JOB_ID>_<INDEX>.out` and `.err`, as on the scheduler.
+/// Returns the exit codes in index order, 128 plus the signal for a killed task.
+fn run_locally(
+    jobs: &[String],
+    workers: usize,
+    job_id: &str,
+    opts: &SubmitOptions,
+) -> io::Result<Vec<i32>> {
+    fs::create_dir_all(&opts.log_dir)?;
+    let script = build_task_script(CommandSource::Embedded(jobs), opts)?;
+    let script_path = format!("{}/arrayify-local-{}.sh", opts.log_dir, job_id);
+    cleanup::TRACKED.track(&script_path);
+    fs::write(&script_path, script)?;
+
+    let tasks: Vec<usize> = (1..=jobs.len().div_ceil(opts.chunk.max(1))).collect();
+    let codes = run_bounded(&tasks, workers.max(1), |index| {
+        let log = |stream: &str| {
+            File::create(format!(
+                "{}/job_{}_{}.{}",
+                opts.log_dir, job_id, index, stream
+            ))
+        };
+        let status = Command::new("bash")
+            .arg(&script_path)
+            .env("LSB_JOBID", job_id)
+            .env("LSB_JOBINDEX", index.to_string())
+            .stdin(Stdio::null())
+            .stdout(log("out")?)
+            .stderr(log("err")?)
+            .status()?;
+        Ok(status
+            .code()
+            .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+    })
+    .into_iter()
+    .collect();
+    fs::remove_file(&script_path)?;
+    cleanup::TRACKED.forget(&script_path);
+    codes
+}
+
+fn report_local_run(job_id: &str, codes: &[i32], log_dir: &str) -> io::Result<Vec<Manifest>> {
+    let failed: Vec<String> = codes
+        .iter()
+        .enumerate()
+        .filter(|(_, code)| **code != 0)
+        .map(|(i, _)| (i + 1).to_string())
+        .collect();
+    if failed.is_empty() {
+        outln!("✅ All {} tasks completed successfully!", codes.len());
+        return Ok(Vec::new());
+    }
+    Err(io::Error::other(format!(
+        "{} of {} local tasks failed (indices {}); see {}/job_{}_<INDEX>.err",
+        failed.len(),
+        codes.len(),
+        failed.join(","),
+        log_dir,
+        job_id
+    )))
+}
+
+/// Submits several independent arrays with their bsub calls in parallel.
+/// Everything printed before and after comes in array order. Arrays that
+/// were accepted are reported and recorded even if others failed.
+fn submit_arrays_in_parallel(
+    opts: &SubmitOptions,
+    arrays: &[(&SubmitOptions, &[String], String, String)],
+    timestamp: &str,
+    submission_hash: &str,
+) -> io::Result<Vec<Manifest>> {
+    let mut prepared = Vec::new();
+    for (array_opts, jobs, log_file_path, suffix) in arrays {
+        if let Some(array) = prepare_array(array_opts, jobs, log_file_path, timestamp, suffix)? {
+            prepared.push((array, *jobs));
+        }
+    }
+
+    let job_ids = run_bounded(&prepared, MAX_PARALLEL_SUBMISSIONS, |(array, jobs)| {
+        submit_prepared(array, jobs.len())
+    });
+
+    let mut manifests = Vec::new();
+    let mut errors = Vec::new();
+    for ((array, jobs), job_id) in prepared.iter().zip(job_ids) {
+        match job_id {
+            Ok(job_id) => manifests.push(finish_array(
+                opts,
+                array,
+                jobs,
+                job_id,
+                timestamp,
+                submission_hash,
+            )?),
+            Err(e) => errors.push(format!("{}: {}", array.log_file_path, e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(io::Error::other(format!(
+            "{} of {} arrays failed to submit:\n  {}",
+            errors.len(),
+            prepared.len(),
+            errors.join("\n  ")
+        )));
+    }
+    Ok(manifests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_read_jobs_from_csv() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "header1,header2\nvalue1,value2").unwrap();
+
+        let jobs = jobs::read_jobs_from_csv(
+            csv_file.path().to_str().unwrap(),
+            "echo {header1} {header2}",
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(jobs, vec!["echo value1 value2"]);
+    }
+
+    #[test]
+    fn test_calculate_batch_size() {
+        assert_eq!(calculate_batch_size(10, None, None), 2); // 20% of 10, rounded up
+        assert_eq!(calculate_batch_size(10, Some(5), None), 5); // Custom batch size
+        assert_eq!(calculate_batch_size(1, None, None), 1); // Minimum batch size
+    }
+
+    #[test]
+    fn test_total_limits_cap_batch() {
+        let opts = SubmitOptions {
+            memory_gb: 8,
+            threads: 4,
+            max_total_mem_gb: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(cap_batch_size(50, &opts), 12); // 12 x 8 GB fits in 100
+        assert_eq!(cap_batch_size(5, &opts), 5); // Already within the limit
+
+        let slots = SubmitOptions {
+            max_total_slots: Some(30),
+            ..opts.clone()
+        };
+        assert_eq!(cap_batch_size(50, &slots), 7); // Slots bind before memory
+        assert_eq!(resolve_batch_size(&slots, 1000, |_| unreachable!()), 7);
+
+        let tiny = SubmitOptions {
+            max_total_mem_gb: Some(4),
+            ..opts
+        };
+        assert_eq!(cap_batch_size(50, &tiny), 1); // One task still runs
+        assert_eq!(cap_batch_size(50, &SubmitOptions::default()), 50);
+    }
+
+    #[test]
+    fn test_min_batch_floor() {
+        assert_eq!(calculate_batch_size(30, None, Some(10)), 10); // Floor beats 20% (6)
+        assert_eq!(calculate_batch_size(100, None, Some(10)), 20); // 20% beats the floor
+        assert_eq!(calculate_batch_size(4, None, Some(10)), 4); // Capped at the job count
+        assert_eq!(calculate_batch_size(30, Some(3), Some(10)), 3); // Explicit batch wins
+    }
+
+    #[test]
+    fn test_submit_host_wraps_bsub_in_ssh() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let work = work_dir.path().to_str().unwrap();
+        // Records what it was asked to run and what it was given on stdin
+        let fake_ssh = work_dir.path().join("ssh");
+        fs::write(
+            &fake_ssh,
+            format!(
+                "#!/bin/bash\n\
+                 if [ \"$1\" = unreachable ]; then echo 'ssh: Could not resolve hostname' >&2; exit 255; fi\n\
+                 printf '%s\\n' \"$@\" > {0}/ssh_args\n\
+                 cat > {0}/ssh_stdin\n\
+                 echo 'Job <42> is submitted to queue <normal>.'\n",
+                work
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&fake_ssh, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = format!("{}:{}", work, std::env::var("PATH").unwrap_or_default());
+        let opts = SubmitOptions {
+            submit_host: Some("me@login1".to_string()),
+            ..Default::default()
+        };
+        let bsub_cmd = build_bsub_command(&opts, 3, 1);
+        let mut command = submit_host_command(&opts, &bsub_cmd);
+        command.env("PATH", &path);
+        let output = run_with_input(command, "#!/bin/bash\necho task\n", &opts).unwrap();
+
+        assert!(output.contains("Job <42>"));
+        assert_eq!(
+            fs::read_to_string(format!("{}/ssh_args", work)).unwrap(),
+            format!("me@login1\n{}\n", bsub_cmd)
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{}/ssh_stdin", work)).unwrap(),
+            "#!/bin/bash\necho task\n"
+        );
+
+        let unreachable = SubmitOptions {
+            submit_host: Some("unreachable".to_string()),
+            ..Default::default()
+        };
+        let mut command = submit_host_command(&unreachable, &bsub_cmd);
+        command.env("PATH", &path);
+        let err = run_with_input(command, "", &unreachable).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(err.to_string().contains("SSH to unreachable failed"));
+    }
+
+    #[test]
+    fn test_batch_of_queue_slots() {
+        let opts = SubmitOptions {
+            queue: "long".to_string(),
+            batch_of_queue: Some(10),
+            ..Default::default()
+        };
+        let bqueues = |queue: &str| {
+            assert_eq!(queue, "long");
+            Ok("500\n".to_string())
+        };
+        assert_eq!(resolve_batch_size(&opts, 1000, bqueues), 50); // 10% of 500 slots
+        assert_eq!(resolve_batch_size(&opts, 20, bqueues), 20); // Clamped to the array
+
+        // Unlimited or unreachable queues fall back to 20% of the array
+        assert_eq!(
+            resolve_batch_size(&opts, 1000, |_| Ok("-\n".to_string())),
+            200
+        );
+        let down = |_: &str| Err(io::Error::other("bqueues: LSF is down"));
+        assert_eq!(resolve_batch_size(&opts, 1000, down), 200);
+
+        let explicit = SubmitOptions {
+            batch_size: Some(7),
+            ..opts
+        };
+        assert_eq!(resolve_batch_size(&explicit, 1000, bqueues), 7);
+    }
+
+    #[test]
+    fn test_write_job_log() {
+        let log_file = NamedTempFile::new().unwrap();
+        let jobs = vec!["job1".to_string(), "job2".to_string()];
+
+        write_job_log(log_file.path().to_str().unwrap(), None, &jobs).unwrap();
+
+        let contents = fs::read_to_string(log_file.path()).unwrap();
+        assert!(contents.contains("job1"));
+        assert!(contents.contains("job2"));
+    }
+
+    #[test]
+    fn test_run_bounded_collects_every_result_in_order() {
+        let chunks: Vec<usize> = (0..10).collect();
+        let in_flight = AtomicUsize::new(0);
+        let most_in_flight = AtomicUsize::new(0);
+        let job_ids = run_bounded(&chunks, 3, |&chunk| {
+            let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            most_in_flight.fetch_max(now, Ordering::SeqCst);
+            // Later chunks answer sooner, so completion order differs from input order
+            thread::sleep(Duration::from_millis(5 * (10 - chunk as u64)));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            if chunk == 4 {
+                Err(format!("chunk {} rejected", chunk))
+            } else {
+                Ok(format!("{}", 1000 + chunk))
+            }
+        });
+
+        assert_eq!(job_ids.len(), 10);
+        for (chunk, job_id) in job_ids.iter().enumerate() {
+            match chunk {
+                4 => assert_eq!(job_id, &Err("chunk 4 rejected".to_string())),
+                _ => assert_eq!(job_id, &Ok(format!("{}", 1000 + chunk))),
+            }
+        }
+        assert!(most_in_flight.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn test_log_header_skipped_by_tasks() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let log_file_path = format!("{}/arrayify-test.log", work_dir.path().display());
+        let jobs: Vec<String> = (1..=3).map(|i| format!("echo cmd{}", i)).collect();
+        let opts = SubmitOptions {
+            log_dir: work_dir.path().to_str().unwrap().to_string(),
+            queue: "long".to_string(),
+            command_template: "echo cmd{ROW}".to_string(),
+            log_header: true,
+            ..Default::default()
+        };
+        let script = prepare_task_script(&log_file_path, &jobs, &opts, "2026-01-01-10-00").unwrap();
+        fill_in_log_header(&log_file_path, "4242").unwrap();
+
+        let log = fs::read_to_string(&log_file_path).unwrap();
+        assert!(log.starts_with(
+            "# arrayify job_id=4242\n\
+             # arrayify submitted_at=2026-01-01-10-00\n\
+             # arrayify queue=long\n\
+             # arrayify template=echo cmd{ROW}\n\
+             echo cmd1\n"
+        ));
+        assert_eq!(strip_log_header(&log), "echo cmd1\necho cmd2\necho cmd3\n");
+
+        let run_task = |script: &str, index: &str| {
+            let mut child = Command::new("bash")
+                .env("LSB_JOBINDEX", index)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(script.as_bytes())
+                .unwrap();
+            String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+        };
+        assert_eq!(run_task(&script, "1"), "cmd1\n");
+        assert_eq!(run_task(&script, "3"), "cmd3\n");
+
+        let chunked = SubmitOptions { chunk: 2, ..opts };
+        let script = build_job_script(&log_file_path, &chunked).unwrap();
+        assert_eq!(run_task(&script, "2"), "cmd3\n");
+    }
+
+    #[test]
+    fn test_submit_jobs_empty_csv() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "header1,header2").unwrap(); // Empty CSV
+
+        let result = submit_jobs(&SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "echo {header1}".to_string(),
+            ..Default::default()
+        });
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_dedup_duplicate_rows() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID\na\nb\na\nc\nb").unwrap();
+
+        let work_dir = tempfile::tempdir().unwrap();
+        let script_path = work_dir.path().join("submit.sh");
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "echo {ID}".to_string(),
+            dedup: true,
+            dry_run: true,
+            ..Default::default()
+        };
+        assert!(submit_jobs(&opts).is_ok());
+
+        let emitting = SubmitOptions {
+            dry_run: false,
+            emit_script: Some(script_path.to_str().unwrap().to_string()),
+            ..opts
+        };
+        submit_jobs(&emitting).unwrap();
+        let script = fs::read_to_string(&script_path).unwrap();
+        assert!(script.contains("\necho a\necho b\necho c\nARRAYIFY_JOBS"));
+        assert!(script.contains("_job_array[1-3]%"));
+        assert_eq!(
+            rendered_commands(&emitting).unwrap(),
+            vec!["echo a", "echo b", "echo c"]
+        );
+    }
+
+    #[test]
+    fn test_build_job_script_prolog_epilog() {
+        let mut prolog = NamedTempFile::new().unwrap();
+        writeln!(prolog, "module load samtools").unwrap();
+        let mut epilog = NamedTempFile::new().unwrap();
+        writeln!(epilog, "rm -rf $TMPDIR/scratch").unwrap();
+
+        let opts = SubmitOptions {
+            prolog: Some(prolog.path().to_str().unwrap().to_string()),
+            epilog: Some(epilog.path().to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let script = build_job_script("jobs.log", &opts).unwrap();
+
+        let prolog_at = script.find("module load samtools").unwrap();
+        let command_at = script.find("eval \"$COMMAND\"").unwrap();
+        let epilog_at = script.find("rm -rf $TMPDIR/scratch").unwrap();
+        assert!(prolog_at < command_at && command_at < epilog_at);
+        assert!(script[..prolog_at].contains("set -e"));
+        assert!(script.ends_with("exit $STATUS\n"));
+    }
+
+    #[test]
+    fn test_build_job_script_strict_bash() {
+        let script = build_job_script("jobs.log", &SubmitOptions::default()).unwrap();
+        assert!(script.starts_with("#!/bin/bash\n\nset -euo pipefail\n"));
+
+        let loose = SubmitOptions {
+            strict_bash: false,
+            ..Default::default()
+        };
+        let script = build_job_script("jobs.log", &loose).unwrap();
+        assert!(!script.contains("pipefail"));
+    }
+
+    #[test]
+    fn test_max_pending_refuses_submission() {
+        let bjobs_output = "PEND\nRUN\nPEND\nPEND\nDONE\n";
+        let pending = count_pending_jobs(bjobs_output);
+        assert_eq!(pending, 3);
+
+        assert!(ensure_pending_capacity(pending, 7, 10).is_ok());
+        let err = ensure_pending_capacity(pending, 8, 10).unwrap_err();
+        assert!(err.to_string().contains("--max-pending 10"));
+
+        // Tasks count, not commands: 10 commands 3 to a task over two arrays
+        let chunked = SubmitOptions {
+            chunk: 3,
+            ..Default::default()
+        };
+        let commands = |n: usize| vec!["echo".to_string(); n];
+        let sub_arrays = vec![(chunked.clone(), commands(4)), (chunked, commands(6))];
+        assert_eq!(total_array_size(&sub_arrays), 4);
+    }
+
+    #[test]
+    fn test_format_batch_summary() {
+        let batch_size = calculate_batch_size(1000, None, None);
+        assert_eq!(
+            format_batch_summary(batch_size, 1000),
+            "batch=200 (20% of 1000)"
+        );
+        assert_eq!(format_batch_summary(5, 10), "batch=5 (50% of 10)");
+    }
+
+    #[test]
+    fn test_build_job_script_stagger() {
+        let opts = SubmitOptions {
+            stagger_secs: Some(5),
+            ..Default::default()
+        };
+        let script = build_job_script("jobs.log", &opts).unwrap();
+
+        assert!(script.contains("DELAY=$(( (LSB_JOBINDEX - 1) * 5 ))"));
+        assert!(script.contains("if [ \"$DELAY\" -gt 600 ]; then DELAY=600; fi"));
+        assert!(script.find("sleep \"$DELAY\"").unwrap() < script.find("eval").unwrap());
+    }
+
+    #[test]
+    fn test_seed_makes_artifacts_reproducible() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let work = work_dir.path().to_str().unwrap();
+        let csv_path = format!("{}/samples.csv", work);
+        fs::write(&csv_path, "ID\ns1\ns2\n").unwrap();
+
+        let emit = |name: &str, seed: Option<u32>| {
+            let opts = SubmitOptions {
+                input_path: csv_path.clone(),
+                command_template: "echo {ID}".to_string(),
+                log_dir: format!("{}/logs", work),
+                emit_script: Some(format!("{}/{}", work, name)),
+                log_header: true,
+                seed,
+                ..Default::default()
+            };
+            submit_jobs(&opts).unwrap();
+            fs::read_to_string(format!("{}/{}", work, name)).unwrap()
+        };
+        let first = emit("first.sh", Some(1_700_000_000));
+        assert_eq!(first, emit("second.sh", Some(1_700_000_000)));
+        assert!(first.contains("arrayify-2023-11-14-22-13.log"));
+        assert_ne!(first, emit("third.sh", Some(1)));
+    }
+
+    #[test]
+    fn test_emitted_script_runs_with_stub_bsub() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let work = work_dir.path().to_str().unwrap();
+
+        // A stub bsub that records its arguments and the task script it is given
+        let bin_dir = work_dir.path().join("bin");
+        fs::create_dir(&bin_dir).unwrap();
+        let stub = bin_dir.join("bsub");
+        fs::write(
+            &stub,
+            format!(
+                "#!/bin/bash\nprintf '%s\\n' \"$@\" > {0}/args\ncat > {0}/task.sh\necho 'Job <42> is submitted'\n",
+                work
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let opts = SubmitOptions {
+            log_dir: format!("{}/my logs", work),
+            ..Default::default()
+        };
+        let jobs = vec![
+            "echo 'single quotes' \"double\" $HOME | wc -l".to_string(),
+            "echo second".to_string(),
+        ];
+        let log_file_path = format!("{}/my logs/arrayify-test.log", work);
+        let script_path = format!("{}/run.sh", work);
+        write_emitted_script(&script_path, &opts, &jobs, &log_file_path, 1).unwrap();
+
+        let path = format!("{}:{}", bin_dir.display(), std::env::var("PATH").unwrap());
+        let output = Command::new("bash")
+            .arg(&script_path)
+            .env("PATH", path)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Job <42>"));
+        assert_eq!(
+            fs::read_to_string(&log_file_path).unwrap(),
+            format!("{}\n{}\n", jobs[0], jobs[1])
+        );
+        let args = fs::read_to_string(format!("{}/args", work)).unwrap();
+        assert!(
+            args.lines()
+                .any(|arg| arg == format!("{}/my logs/job_%J_%I.out", work))
+        );
+
+        // The task finds its command in the log despite the space in its path
+        let task = Command::new("bash")
+            .arg(format!("{}/task.sh", work))
+            .env("LSB_JOBINDEX", "2")
+            .output()
+            .unwrap();
+        assert!(task.status.success());
+        assert_eq!(String::from_utf8_lossy(&task.stdout), "second\n");
+    }
+
+    #[test]
+    fn test_build_resource_string_excludes_hosts() {
+        let opts = SubmitOptions {
+            memory_gb: 4,
+            exclude_hosts: vec!["node7".to_string(), "node9".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            build_resource_string(&opts, 4000),
+            "select[mem>4000 && hname!=node7 && hname!=node9] rusage[mem=4000]"
+        );
+        assert!(
+            build_bsub_command(&opts, 10, 2).contains(
+                "-R \"select[mem>4000 && hname!=node7 && hname!=node9] rusage[mem=4000]\""
+            )
+        );
+        assert_eq!(
+            build_resource_string(&SubmitOptions::default(), 1000),
+            "select[mem>1000] rusage[mem=1000]"
+        );
+    }
+
+    #[test]
+    fn test_array_limit_triggers_chunking() {
+        assert_eq!(
+            parse_array_limit("MBD configuration:\nMAX_JOB_ARRAY_SIZE = 5000\n"),
+            Some(5000)
+        );
+        let unreadable = SubmitOptions::default();
+        assert_eq!(
+            resolve_array_limit(&unreadable, || Err(io::Error::other("no badmin"))),
+            DEFAULT_MAX_ARRAY_SIZE
+        );
+
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID").unwrap();
+        for i in 1..=10 {
+            writeln!(csv_file, "s{}", i).unwrap();
+        }
+        let work_dir = tempfile::tempdir().unwrap();
+        let script_path = work_dir.path().join("submit.sh");
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "echo {ID}".to_string(),
+            emit_script: Some(script_path.to_str().unwrap().to_string()),
+            max_array_size: Some(4),
+            ..Default::default()
+        };
+        submit_jobs(&opts).unwrap();
+
+        // 10 commands over at most 4 tasks: 3 per task, in 4 tasks
+        let script = fs::read_to_string(&script_path).unwrap();
+        assert!(script.contains("_job_array[1-4]%"));
+        assert!(script.contains("FIRST=$((INDEX * 3 + 1))"));
+    }
+
+    #[test]
+    fn test_priority_renders_sp() {
+        let opts = SubmitOptions {
+            priority: Some(80),
+            ..Default::default()
+        };
+        assert!(build_bsub_command(&opts, 10, 2).contains(" -sp 80 "));
+        assert!(!build_bsub_command(&SubmitOptions::default(), 10, 2).contains("-sp"));
+    }
+
+    #[test]
+    fn test_group_renders_g() {
+        let opts = SubmitOptions {
+            group: Some("/pathogens/amr".to_string()),
+            ..Default::default()
+        };
+        assert!(build_bsub_command(&opts, 10, 2).contains(" -g /pathogens/amr "));
+        assert!(!build_bsub_command(&SubmitOptions::default(), 10, 2).contains(" -g "));
+    }
+
+    #[test]
+    fn test_after_array_renders_element_dependency() {
+        let opts = SubmitOptions {
+            after_array: Some(4242),
+            ..Default::default()
+        };
+        assert!(build_bsub_command(&opts, 10, 2).contains(" -w \"done(4242[*])\" "));
+        assert!(!build_bsub_command(&SubmitOptions::default(), 10, 2).contains(" -w "));
+    }
+
+    #[test]
+    fn test_reservation_renders_in_dry_run_command() {
+        let opts = SubmitOptions {
+            reservation: Some("sam#12".to_string()),
+            ..Default::default()
+        };
+        assert!(build_bsub_command(&opts, 10, 2).contains(" -U sam#12 "));
+        assert!(!build_bsub_command(&SubmitOptions::default(), 10, 2).contains(" -U "));
+    }
+
+    #[test]
+    fn test_resource_select_joins_mem_select() {
+        let opts = SubmitOptions {
+            memory_gb: 4,
+            resource_select: Some("select[avx512]".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            build_bsub_command(&opts, 10, 2)
+                .contains("-R \"select[mem>4000 && avx512] rusage[mem=4000]\"")
+        );
+
+        // Alternatives are grouped so they don't swallow the memory requirement
+        let either = SubmitOptions {
+            resource_select: Some("avx512 || avx2".to_string()),
+            exclude_hosts: vec!["node7".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            build_resource_string(&either, 1000),
+            "select[mem>1000 && hname!=node7 && (avx512 || avx2)] rusage[mem=1000]"
+        );
+    }
+
+    #[test]
+    fn test_mem_per_gb_input_tiers() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("small_1.fq"), vec![b'A'; 100]).unwrap();
+        fs::write(dir.path().join("small_2.fq"), vec![b'A'; 100]).unwrap();
+        fs::write(dir.path().join("big_1.fq"), vec![b'A'; 3_000_000]).unwrap();
+        fs::write(dir.path().join("big_2.fq"), vec![b'A'; 3_000_000]).unwrap();
+
+        let opts = SubmitOptions {
+            input_path: dir.path().to_str().unwrap().to_string(),
+            command_template: "bwa mem {R1} {R2} > {ID}.sam".to_string(),
+            format: InputFormat::Directory,
+            mem_per_gb_input: Some(1_000_000),
+            ..Default::default()
+        };
+        let (jobs, input_sizes) = read_jobs(&opts).unwrap();
+        let tiers = group_by_memory_tier(jobs, &input_sizes, 1, 1_000_000);
+
+        // 6MB of input at 1TB per GB of input needs 6GB; 200 bytes stays at the 1GB floor
+        assert_eq!(tiers.keys().copied().collect::<Vec<_>>(), vec![1, 6]);
+        assert!(tiers[&1][0].contains("small_1.fq"));
+        assert!(tiers[&6][0].contains("big_1.fq"));
+    }
+
+    #[test]
+    fn test_shellcheck_broken_template() {
+        let bin_dir = tempfile::tempdir().unwrap();
+        let stub = bin_dir.path().join("shellcheck");
+        fs::write(
+            &stub,
+            "#!/bin/bash\ncat > /dev/null\necho \"-:1:6: error: Couldn't parse this double quoted string. [SC1073]\"\nexit 1\n",
+        )
+        .unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+        let stub = stub.to_str().unwrap();
+
+        let broken = "echo \"unbalanced";
+        match run_shellcheck(stub, broken).unwrap() {
+            ShellcheckOutcome::Issues { has_errors, report } => {
+                assert!(has_errors);
+                assert!(report.contains("SC1073"));
+            }
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        assert!(shellcheck_first_job(stub, broken, false).is_err());
+        assert!(shellcheck_first_job(stub, broken, true).is_ok());
+
+        let missing = bin_dir.path().join("no-shellcheck");
+        assert_eq!(
+            run_shellcheck(missing.to_str().unwrap(), broken).unwrap(),
+            ShellcheckOutcome::NotInstalled
+        );
+        assert!(shellcheck_first_job(missing.to_str().unwrap(), broken, false).is_ok());
+    }
+
+    #[test]
+    fn test_profile_reader_large_csv() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,R1,R2").unwrap();
+        for i in 0..20_000 {
+            writeln!(csv_file, "s{},s{}_1.fq,s{}_2.fq", i, i, i).unwrap();
+        }
+
+        let report = profile_reader(&SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "bwa mem {R1} {R2} > {ID}.sam".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(report.starts_with("⏱️ Csv reader: 20000 jobs in "));
+        assert!(report.ends_with(" rows/sec)"));
+    }
+
+    #[test]
+    fn test_hold_and_release() {
+        let held = SubmitOptions {
+            hold: true,
+            ..Default::default()
+        };
+        assert!(build_bsub_command(&held, 10, 2).contains(" -H "));
+        assert!(!build_bsub_command(&SubmitOptions::default(), 10, 2).contains(" -H "));
+
+        let release = build_release_command("12345");
+        assert_eq!(release.get_program(), "bresume");
+        assert_eq!(release.get_args().collect::<Vec<_>>(), vec!["12345"]);
+    }
+
+    #[test]
+    fn test_task_log_paths_per_job() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,R1\ns1,/data/s1.fq\ns2,/data/s2.fq").unwrap();
+
+        let (jobs, _) = read_jobs(&SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "fastqc {R1}".to_string(),
+            task_log: Some("{R1}".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            jobs,
+            vec![
+                "{ fastqc /data/s1.fq; } > '/data/s1.fq.out' 2> '/data/s1.fq.err'",
+                "{ fastqc /data/s2.fq; } > '/data/s2.fq.out' 2> '/data/s2.fq.err'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_second_identical_submission_warns() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let opts = SubmitOptions {
+            log_dir: log_dir.path().to_str().unwrap().to_string(),
+            no_duplicate: true,
+            ..Default::default()
+        };
+        let jobs = vec!["echo a".to_string(), "echo b".to_string()];
+        let hash = submission_hash(&opts, &jobs);
+        assert_eq!(hash, submission_hash(&opts.clone(), &jobs.clone()));
+
+        // First submission has nothing to clash with
+        assert!(ensure_not_duplicate(&opts, &hash).is_ok());
+        let timestamp = Local::now().format(manifest::TIMESTAMP_FORMAT).to_string();
+        let manifest = Manifest {
+            job_id: "42".to_string(),
+            submitted_at: timestamp.clone(),
+            num_jobs: jobs.len(),
+            log_file: format!("{}/arrayify-{}.log", opts.log_dir, timestamp),
+            options: opts.clone(),
+            submission_hash: hash.clone(),
+            note: None,
+            requested_memory_gb: None,
+            requested_threads: None,
+        };
+        manifest::write_manifest(
+            &format!("{}/arrayify-{}.json", opts.log_dir, timestamp),
+            &manifest,
+        )
+        .unwrap();
+
+        let err = ensure_not_duplicate(&opts, &hash).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        let forced = SubmitOptions {
+            force: true,
+            ..opts.clone()
+        };
+        assert!(ensure_not_duplicate(&forced, &hash).is_ok());
+
+        // More memory is a different submission
+        let bigger = SubmitOptions {
+            memory_gb: 8,
+            ..opts.clone()
+        };
+        assert_ne!(submission_hash(&bigger, &jobs), hash);
+    }
+
+    #[test]
+    fn test_summary_file_written() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let summary_file = format!("{}/summary.json", work_dir.path().display());
+        let opts = SubmitOptions {
+            summary_file: Some(summary_file.clone()),
+            ..Default::default()
+        };
+        let summary = RunSummary {
+            job_id: "4242".to_string(),
+            submitted_at: "2026-01-01-10-00".to_string(),
+            num_jobs: 120,
+            batch_size: 24,
+            log_file: "logs/arrayify-2026-01-01-10-00.log".to_string(),
+            log_dir: "logs".to_string(),
+        };
+        report_run(&opts, &summary, "").unwrap();
+        report_run(&opts, &summary, "-mem8G").unwrap();
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&summary_file).unwrap()).unwrap();
+        assert_eq!(written["job_id"], "4242");
+        assert_eq!(written["num_jobs"], 120);
+        assert_eq!(written["batch_size"], 24);
+        assert!(work_dir.path().join("summary-mem8G.json").exists());
+    }
+
+    #[test]
+    fn test_run_stats_without_emoji() {
+        let summary = RunSummary {
+            job_id: "4242".to_string(),
+            submitted_at: "2026-01-01-10-00".to_string(),
+            num_jobs: 120,
+            batch_size: 24,
+            log_file: "logs/arrayify-2026-01-01-10-00.log".to_string(),
+            log_dir: "logs".to_string(),
+        };
+        let plain = crate::output::plain(&format_run_stats(&summary));
+        assert!(plain.is_ascii());
+        assert!(plain.starts_with("[RUN] Job submission complete!\n[JOB] Job ID is: 4242\n"));
+        assert!(plain.contains("\n[INFO] Track with -\n   arrayify check 4242"));
+    }
+
+    #[test]
+    fn test_interpreter_runs_commands() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let jobs_path = work_dir.path().join("jobs.log");
+        fs::write(&jobs_path, "print(6 * 7)\necho not python\n").unwrap();
+        let opts = SubmitOptions {
+            interpreter: Some("/usr/bin/env python3".to_string()),
+            ..Default::default()
+        };
+        let script = build_job_script(jobs_path.to_str().unwrap(), &opts).unwrap();
+        assert!(script.starts_with("#!/bin/bash\n"));
+        assert!(
+            script
+                .trim_end()
+                .ends_with("/usr/bin/env python3 <(printf '%s\\n' \"$COMMAND\")")
+        );
+        assert!(!script.contains("eval"));
+
+        // sh stands in for python3, which the test host may lack
+        let sh = SubmitOptions {
+            interpreter: Some("sh".to_string()),
+            ..Default::default()
+        };
+        fs::write(&jobs_path, "echo $((6 * 7))\n").unwrap();
+        let script = build_job_script(jobs_path.to_str().unwrap(), &sh).unwrap();
+        let mut child = Command::new("bash")
+            .env("LSB_JOBINDEX", "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+    }
+
+    #[test]
+    fn test_exit_code_file_written() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let log_dir = work_dir.path().to_str().unwrap().to_string();
+        let jobs_path = work_dir.path().join("jobs.log");
+        fs::write(&jobs_path, "true\nbash -c 'exit 3'\n").unwrap();
+        let opts = SubmitOptions {
+            log_dir: log_dir.clone(),
+            exit_codes: true,
+            ..Default::default()
+        };
+        let script = build_job_script(jobs_path.to_str().unwrap(), &opts).unwrap();
+        assert!(script.contains(&format!(
+            "eval \"$COMMAND\" || STATUS=$?\necho \"$STATUS\" > '{}'/job_\"$LSB_JOBID\"_\"$LSB_JOBINDEX\".code\nexit $STATUS\n",
+            log_dir
+        )));
+
+        for index in ["1", "2"] {
+            let mut child = Command::new("bash")
+                .env("LSB_JOBID", "42")
+                .env("LSB_JOBINDEX", index)
+                .stdin(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(script.as_bytes())
+                .unwrap();
+            let status = child.wait().unwrap();
+            assert_eq!(status.code(), Some(if index == "1" { 0 } else { 3 }));
+        }
+        let code = |index| fs::read_to_string(format!("{}/job_42_{}.code", log_dir, index));
+        assert_eq!(code(1).unwrap(), "0\n");
+        assert_eq!(code(2).unwrap(), "3\n");
+    }
+
+    #[test]
+    fn test_task_retries_loop() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let counter = work_dir.path().join("attempts");
+        let jobs_path = work_dir.path().join("jobs.log");
+        // Fails on the first two attempts, then succeeds
+        fs::write(
+            &jobs_path,
+            format!(
+                "echo x >> {0}; [ $(wc -l < {0}) -ge 3 ]\n",
+                counter.display()
+            ),
+        )
+        .unwrap();
+        let opts = SubmitOptions {
+            task_retries: 2,
+            ..Default::default()
+        };
+        let script = build_job_script(jobs_path.to_str().unwrap(), &opts).unwrap();
+        assert!(script.contains("until eval \"$COMMAND\"; do"));
+        assert!(script.contains("if [ \"$ATTEMPT\" -gt 2 ]"));
+        assert!(script.contains("sleep 0\n"));
+        assert!(script.trim_end().ends_with("run_command"));
+
+        let status = |script: &str| {
+            let mut child = Command::new("bash")
+                .env("LSB_JOBINDEX", "1")
+                .stdin(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(script.as_bytes())
+                .unwrap();
+            child.wait().unwrap()
+        };
+        assert!(status(&script).success());
+        assert_eq!(fs::read_to_string(&counter).unwrap().lines().count(), 3);
+
+        // Out of retries: the task fails with the command's exit code
+        fs::write(&jobs_path, "exit 3\n").unwrap();
+        assert_eq!(status(&script).code(), Some(3));
+    }
+
+    #[test]
+    fn test_no_log_embeds_commands() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_file_path = format!("{}/arrayify-test.log", log_dir.path().display());
+        let jobs = vec![
+            "echo one".to_string(),
+            "echo 'two too'".to_string(),
+            "echo three".to_string(),
+        ];
+        let opts = SubmitOptions {
+            log_dir: log_dir.path().to_str().unwrap().to_string(),
+            no_log: true,
+            ..Default::default()
+        };
+        let script = prepare_task_script(&log_file_path, &jobs, &opts, "").unwrap();
+        assert!(!std::path::Path::new(&log_file_path).exists());
+        assert_eq!(fs::read_dir(log_dir.path()).unwrap().count(), 0);
+
+        // The script runs its task's command with nothing to read from disk
+        let run_task = |script: &str, index: &str| {
+            let mut child = Command::new("bash")
+                .env("LSB_JOBINDEX", index)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(script.as_bytes())
+                .unwrap();
+            String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap()
+        };
+        assert_eq!(run_task(&script, "2"), "two too\n");
+
+        let chunked = SubmitOptions { chunk: 2, ..opts };
+        let script = prepare_task_script(&log_file_path, &jobs, &chunked, "").unwrap();
+        assert_eq!(run_task(&script, "1"), "one\ntwo too\n");
+        assert_eq!(run_task(&script, "2"), "three\n");
+    }
+
+    #[test]
+    fn test_keep_script_written_next_to_log() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let log_file_path = format!("{}/arrayify-test.log", log_dir.path().display());
+        let script = build_job_script(&log_file_path, &SubmitOptions::default()).unwrap();
+
+        let kept = keep_submitted_script("", &log_file_path, "", &script).unwrap();
+        assert_eq!(
+            kept,
+            format!("{}/arrayify-test.sh", log_dir.path().display())
+        );
+        assert_eq!(fs::read_to_string(&kept).unwrap(), script);
+
+        let custom = format!("{}/task.sh", log_dir.path().display());
+        let kept = keep_submitted_script(&custom, &log_file_path, "_mem8", &script).unwrap();
+        assert!(kept.ends_with("/task_mem8.sh"));
+        assert!(std::path::Path::new(&kept).exists());
+    }
+
+    #[test]
+    fn test_failing_smoke_test_aborts_submission() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID\ns1\ns2").unwrap();
+
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "arrayify-no-such-binary {ID}".to_string(),
+            log_dir: log_dir.path().to_str().unwrap().to_string(),
+            smoke_test: true,
+            ..Default::default()
+        };
+        let err = submit_jobs(&opts).unwrap_err();
+        assert!(err.to_string().contains("Smoke test failed"));
+        assert_eq!(fs::read_dir(log_dir.path()).unwrap().count(), 0);
+
+        assert!(run_smoke_test("true", None, None).is_ok());
+        assert!(run_smoke_test("sleep 5", None, Some(0)).is_ok());
+        assert!(run_smoke_test("exit 3", Some("sh"), None).is_err());
+    }
+
+    #[test]
+    fn test_count_lines_in_large_file() {
+        let mut log_file = NamedTempFile::new().unwrap();
+        {
+            let mut writer = io::BufWriter::new(log_file.as_file_mut());
+            for i in 0..250_000 {
+                writeln!(writer, "echo job {}", i).unwrap();
+            }
+        }
+        let path = log_file.path().to_str().unwrap().to_string();
+        assert_eq!(count_lines_in_file(&path).unwrap(), 250_000);
+
+        // A last line without a newline still counts
+        write!(log_file, "echo last").unwrap();
+        assert_eq!(count_lines_in_file(&path).unwrap(), 250_001);
+
+        let empty = NamedTempFile::new().unwrap();
+        assert_eq!(
+            count_lines_in_file(empty.path().to_str().unwrap()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_hand_written_jobs_file() {
+        let mut jobs_file = NamedTempFile::new().unwrap();
+        writeln!(
+            jobs_file,
+            "samtools index a.bam\nsamtools index b.bam | tee out"
+        )
+        .unwrap();
+        let jobs_path = jobs_file.path().to_str().unwrap().to_string();
+
+        let opts = SubmitOptions {
+            input_path: jobs_path.clone(),
+            format: InputFormat::JobsFile,
+            dry_run: true,
+            ..Default::default()
+        };
+        assert!(submit_jobs(&opts).is_ok());
+        assert_eq!(
+            read_jobs_file(&jobs_path).unwrap(),
+            vec!["samtools index a.bam", "samtools index b.bam | tee out"]
+        );
+
+        // The tasks read their commands straight from the given file
+        let script = build_job_script(&jobs_path, &opts).unwrap();
+        assert!(script.contains(&format!("p\" '{}'", jobs_path)));
+
+        let empty = NamedTempFile::new().unwrap();
+        let err = submit_jobs(&SubmitOptions {
+            input_path: empty.path().to_str().unwrap().to_string(),
+            ..opts
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_check_paths_reports_missing() {
+        let present = NamedTempFile::new().unwrap();
+        let present = present.path().to_str().unwrap();
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(
+            csv_file,
+            "ID,R1,REF\ns1,{},/no/such/ref.fa\ns2,/no/such/s2.fq,/no/such/ref.fa",
+            present
+        )
+        .unwrap();
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "bwa mem --ref=/no/such/ref.fa {R1} > out/{ID}.sam".to_string(),
+            check_paths: true,
+            ..Default::default()
+        };
+        let (jobs, _) = read_jobs(&opts).unwrap();
+
+        // Guessed from the commands: absolute paths only, each reported once
+        assert_eq!(
+            find_missing_paths(&opts, &jobs).unwrap(),
+            vec!["/no/such/ref.fa", "/no/such/s2.fq"]
+        );
+
+        let by_column = SubmitOptions {
+            path_columns: vec!["R1".to_string()],
+            ..opts
+        };
+        assert_eq!(
+            find_missing_paths(&by_column, &jobs).unwrap(),
+            vec!["/no/such/s2.fq"]
+        );
+    }
+
+    #[test]
+    fn test_multi_line_command_is_rejected() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        write!(csv_file, "ID,NOTE\ns1,\"a, b\"\ns2,\"two\nlines\"\n").unwrap();
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "echo {ID} {NOTE}".to_string(),
+            dry_run: true,
+            ..Default::default()
+        };
+        let err = submit_jobs(&opts).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("Command 2 spans several lines"));
+    }
+
+    #[test]
+    fn test_warn_collisions_finds_shared_outputs() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,OUT\ns1,s1.bam\ns2,out.bam\ns3,out.bam").unwrap();
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "samtools sort -o out.bam {ID}.sam > {ID}.log 2> /dev/null"
+                .to_string(),
+            warn_collisions: true,
+            ..Default::default()
+        };
+        let (jobs, _) = read_jobs(&opts).unwrap();
+
+        // Guessed from the commands: every row sorts into out.bam
+        assert_eq!(
+            find_output_collisions(&opts, &jobs).unwrap(),
+            vec![("out.bam".to_string(), 3)]
+        );
+
+        let by_column = SubmitOptions {
+            output_columns: vec!["OUT".to_string()],
+            ..opts
+        };
+        assert_eq!(
+            find_output_collisions(&by_column, &jobs).unwrap(),
+            vec![("out.bam".to_string(), 2)]
+        );
+        let distinct = SubmitOptions {
+            output_columns: vec!["ID".to_string()],
+            ..by_column
+        };
+        assert!(find_output_collisions(&distinct, &jobs).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_custom_rusage_template() {
+        let opts = SubmitOptions {
+            memory_gb: 4,
+            rusage: Some("select[mem>{mem}] rusage[mem={mem},tmp=20000]".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            build_bsub_command(&opts, 10, 2)
+                .contains(" -M 4000 -R \"select[mem>4000] rusage[mem=4000,tmp=20000]\" ")
+        );
+    }
+
+    #[test]
+    fn test_threads_column_splits_arrays() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,cpus\ns1,4\ns2,8\ns3,4").unwrap();
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "run -t {cpus} {ID}".to_string(),
+            threads_column: Some("cpus".to_string()),
+            ..Default::default()
+        };
+
+        let (jobs, input_sizes) = read_jobs(&opts).unwrap();
+        let row_resources = read_row_resources(&opts, &jobs).unwrap();
+        let sub_arrays = split_sub_arrays(&opts, jobs, &input_sizes, &row_resources);
+
+        assert_eq!(sub_arrays.len(), 2);
+        assert_eq!(sub_arrays[0].0.threads, 4);
+        assert_eq!(sub_arrays[0].1, vec!["run -t 4 s1", "run -t 4 s3"]);
+        assert_eq!(sub_arrays[1].0.threads, 8);
+        assert_eq!(sub_arrays[1].1, vec!["run -t 8 s2"]);
+        assert_eq!(sub_arrays[1].0.memory_gb, opts.memory_gb);
+
+        let mut bad_csv = NamedTempFile::new().unwrap();
+        writeln!(bad_csv, "ID,cpus\ns1,four").unwrap();
+        let bad = SubmitOptions {
+            input_path: bad_csv.path().to_str().unwrap().to_string(),
+            ..opts
+        };
+        let (jobs, _) = read_jobs(&bad).unwrap();
+        let err = read_row_resources(&bad, &jobs).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("column cpus must be a whole number")
+        );
+    }
+
+    #[test]
+    fn test_time_limit_below_floor_warns() {
+        let opts = SubmitOptions {
+            time_minutes: Some(5),
+            ..Default::default()
+        };
+        let warning = time_limit_warning(&opts).unwrap();
+        assert!(warning.contains("--time 5 is below --min-time 10"));
+
+        let at_floor = SubmitOptions {
+            time_minutes: Some(10),
+            ..opts.clone()
+        };
+        assert!(time_limit_warning(&at_floor).is_none());
+        let lower_floor = SubmitOptions {
+            min_time_minutes: 1,
+            ..opts
+        };
+        assert!(time_limit_warning(&lower_floor).is_none());
+        assert!(time_limit_warning(&SubmitOptions::default()).is_none());
+    }
+
+    #[test]
+    fn test_chunked_tasks_run_consecutive_lines() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let log_file = work_dir.path().join("arrayify-test.log");
+        let out = work_dir.path().join("out");
+        let jobs: Vec<String> = (1..=5)
+            .map(|i| format!("echo {} >> {}", i, out.display()))
+            .collect();
+        write_job_log(log_file.to_str().unwrap(), None, &jobs).unwrap();
+
+        let opts = SubmitOptions {
+            chunk: 2,
+            ..Default::default()
+        };
+        assert!(build_bsub_command(&opts, 5, 1).contains("[1-3]%1"));
+        let script = build_job_script(log_file.to_str().unwrap(), &opts).unwrap();
+
+        // Task 2 runs lines 3-4, task 3 only the leftover line 5
+        for (index, expected) in [("2", "3\n4\n"), ("3", "3\n4\n5\n")] {
+            let status = Command::new("bash")
+                .arg("-c")
+                .arg(&script)
+                .env("LSB_JOBINDEX", index)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            assert_eq!(fs::read_to_string(&out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_index_range_submits_only_those_lines() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let jobs_file = work_dir.path().join("commands.txt");
+        let out = work_dir.path().join("out");
+        let jobs: Vec<String> = (1..=5)
+            .map(|i| format!("echo {} >> {}", i, out.display()))
+            .collect();
+        write_job_log(jobs_file.to_str().unwrap(), None, &jobs).unwrap();
+
+        assert_eq!(parse_index_range("2-4"), Ok((2, 4)));
+        assert!(parse_index_range("4-2").is_err());
+        assert!(parse_index_range("0-3").is_err());
+
+        let opts = SubmitOptions {
+            index_range: Some((2, 4)),
+            ..Default::default()
+        };
+        assert!(build_bsub_command(&opts, 5, 3).contains("_job_array[2-4]%3"));
+
+        // The highest index in the range runs the matching line of the file
+        let script = build_job_script(jobs_file.to_str().unwrap(), &opts).unwrap();
+        let status = Command::new("bash")
+            .arg("-c")
+            .arg(&script)
+            .env("LSB_JOBINDEX", "4")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(fs::read_to_string(&out).unwrap(), "4\n");
+
+        let beyond = SubmitOptions {
+            input_path: jobs_file.to_str().unwrap().to_string(),
+            format: InputFormat::JobsFile,
+            index_range: Some((4, 6)),
+            dry_run: true,
+            ..Default::default()
+        };
+        let err = submit_jobs(&beyond).unwrap_err();
+        assert!(err.to_string().contains("beyond the 5 lines"));
+    }
+
+    #[test]
+    fn test_tee_mirrors_stdout_with_index_prefix() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let log_file = work_dir.path().join("arrayify-test.log");
+        let run_log = work_dir.path().join("run.log");
+        write_job_log(
+            log_file.to_str().unwrap(),
+            None,
+            &["echo one; echo two".to_string()],
+        )
+        .unwrap();
+
+        let opts = SubmitOptions {
+            tee: Some(run_log.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let script = build_job_script(log_file.to_str().unwrap(), &opts).unwrap();
+        assert!(script.contains("exec > >(tee >(while IFS= read -r LINE"));
+        assert!(script.contains(&format!(">> '{}'", run_log.display())));
+
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(&script)
+            .env("LSB_JOBINDEX", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "one\ntwo\n");
+        // The mirror is asynchronous; give it a moment to drain
+        for _ in 0..50 {
+            if fs::read_to_string(&run_log)
+                .unwrap_or_default()
+                .lines()
+                .count()
+                == 2
+            {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(fs::read_to_string(&run_log).unwrap(), "[1] one\n[1] two\n");
+    }
+
+    #[test]
+    fn test_rerun_warns_when_queue_limits_shrank() {
+        let bqueues = "QUEUE: normal\n  -- For normal low priority jobs\n\n\
+            MAXIMUM LIMITS:\n RUNLIMIT\n 720.0 min\n\n PROCLIMIT\n 1 4 8\n\n MEMLIMIT\n 16 G\n";
+        assert_eq!(
+            parse_queue_limits(bqueues),
+            QueueLimits {
+                run_minutes: Some(720.0),
+                max_threads: Some(8),
+                mem_mb: Some(16000.0),
+            }
+        );
+
+        let opts = SubmitOptions {
+            memory_gb: 32,
+            threads: 4,
+            time_minutes: Some(1440),
+            ..Default::default()
+        };
+        let warnings = queue_limit_warnings(&opts, |queue| {
+            assert_eq!(queue, "normal");
+            Ok(bqueues.to_string())
+        })
+        .unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("32 GB memory exceeds queue normal's current limit"));
+        assert!(warnings[1].contains("--time 1440 minutes exceeds"));
+
+        let fits = SubmitOptions::default();
+        assert!(
+            queue_limit_warnings(&fits, |_| Ok(bqueues.to_string()))
+                .unwrap()
+                .is_empty()
+        );
+        let gone =
+            queue_limit_warnings(&fits, |_| Ok("normal: No such queue\n".to_string())).unwrap();
+        assert!(gone[0].contains("no longer exists"));
+    }
+
+    #[test]
+    fn test_custom_delimiters_leave_braces_alone() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,R1\ns1,s1.txt").unwrap();
+
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "awk '{print $1, \"%%ID%%\"}' %%R1%% > {ID}.out".to_string(),
+            delimiters: Some(("%%".to_string(), "%%".to_string())),
+            ..Default::default()
+        };
+        let (jobs, _) = read_jobs(&opts).unwrap();
+        assert_eq!(jobs, vec!["awk '{print $1, \"s1\"}' s1.txt > {ID}.out"]);
+
+        let angled = SubmitOptions {
+            command_template: "jq '{id: \"<<ID>>\"}'<<?R1: < <<R1>>>>".to_string(),
+            delimiters: Some(("<<".to_string(), ">>".to_string())),
+            ..opts
+        };
+        let (jobs, _) = read_jobs(&angled).unwrap();
+        assert_eq!(jobs, vec!["jq '{id: \"s1\"}' < s1.txt"]);
+    }
+
+    #[test]
+    fn test_empty_commands_are_skipped() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "CMD\necho a\n \necho c").unwrap();
+        let work_dir = tempfile::tempdir().unwrap();
+        let script_path = work_dir.path().join("submit.sh");
+
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "{CMD}".to_string(),
+            emit_script: Some(script_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        submit_jobs(&opts).unwrap();
+        let script = fs::read_to_string(&script_path).unwrap();
+        assert!(script.contains("[1-2]"));
+        assert_eq!(rendered_commands(&opts).unwrap(), vec!["echo a", "echo c"]);
+
+        let kept = SubmitOptions {
+            skip_empty: false,
+            ..opts
+        };
+        submit_jobs(&kept).unwrap();
+        let script = fs::read_to_string(&script_path).unwrap();
+        assert!(script.contains("[1-3]"));
+        assert_eq!(
+            rendered_commands(&kept).unwrap(),
+            vec!["echo a", " ", "echo c"]
+        );
+    }
+
+    #[test]
+    fn test_placeholder_alias_resolves_both_ways() {
+        let aliases = vec![("ID".to_string(), "sample".to_string())];
+
+        // Directory input provides ID, so {sample} is rewritten to it
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("s1_1.fq"), "").unwrap();
+        fs::write(dir.path().join("s1_2.fq"), "").unwrap();
+        let dir_opts = SubmitOptions {
+            input_path: dir.path().to_str().unwrap().to_string(),
+            format: InputFormat::Directory,
+            command_template: "align {sample} {R1} {R2}".to_string(),
+            placeholder_aliases: aliases.clone(),
+            ..Default::default()
+        };
+        let (jobs, _) = read_jobs(&dir_opts).unwrap();
+        assert!(jobs[0].starts_with("align s1 "));
+
+        // A CSV with a sample column resolves {ID} to it
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "sample,R1\ns2,a.fq").unwrap();
+        let csv_opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "align {ID} {sample} {R1}".to_string(),
+            placeholder_aliases: aliases,
+            ..Default::default()
+        };
+        let (jobs, _) = read_jobs(&csv_opts).unwrap();
+        assert_eq!(jobs, vec!["align s2 s2 a.fq"]);
+    }
+
+    #[test]
+    fn test_commands_are_evaluated_by_bash() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let jobs_path = work_dir.path().join("jobs.log");
+        fs::write(&jobs_path, "echo \"a  b\" '$HOME' | tr a A\n").unwrap();
+
+        for strict_bash in [true, false] {
+            let opts = SubmitOptions {
+                strict_bash,
+                ..Default::default()
+            };
+            let script = build_job_script(jobs_path.to_str().unwrap(), &opts).unwrap();
+            let mut child = Command::new("bash")
+                .env("LSB_JOBINDEX", "1")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(script.as_bytes())
+                .unwrap();
+            let output = child.wait_with_output().unwrap();
+            // Quotes group and protect words and the pipe runs, with or without strict bash
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), "A  b $HOME\n");
+        }
+    }
+
+    #[test]
+    fn test_with_suffix_only_touches_file_name() {
+        assert_eq!(with_suffix("out/task.sh", "_1"), "out/task_1.sh");
+        assert_eq!(with_suffix("./out/task", "_1"), "./out/task_1");
+        assert_eq!(with_suffix("dir.v2/script", "_2"), "dir.v2/script_2");
+        assert_eq!(with_suffix("dir.v2/script.sh", "_2"), "dir.v2/script_2.sh");
+        assert_eq!(with_suffix("dir.v2/script.sh", ""), "dir.v2/script.sh");
+    }
+
+    #[test]
+    fn test_local_jobs_bounds_concurrency() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let trace = work_dir.path().join("trace");
+        let mut jobs: Vec<String> = (0..6)
+            .map(|_| {
+                format!(
+                    "echo start >> {0}; sleep 0.3; echo end >> {0}",
+                    trace.display()
+                )
+            })
+            .collect();
+        jobs.push("echo task $LSB_JOBINDEX; (exit 3)".to_string());
+        let prolog_path = work_dir.path().join("prolog.sh");
+        fs::write(&prolog_path, "echo prolog").unwrap();
+        let log_dir = work_dir.path().join("logs");
+        let opts = SubmitOptions {
+            log_dir: log_dir.to_str().unwrap().to_string(),
+            prolog: Some(prolog_path.to_str().unwrap().to_string()),
+            exit_codes: true,
+            ..Default::default()
+        };
+
+        let codes = run_locally(&jobs, 2, "77", &opts).unwrap();
+        assert_eq!(codes, vec![0, 0, 0, 0, 0, 0, 3]);
+        // The task script runs, prolog and exit code file included, and is then removed
+        let out = fs::read_to_string(log_dir.join("job_77_7.out")).unwrap();
+        assert_eq!(out, "prolog\ntask 7\n");
+        let code = fs::read_to_string(log_dir.join("job_77_7.code")).unwrap();
+        assert_eq!(code, "3\n");
+        assert_eq!(fs::read_dir(&log_dir).unwrap().count(), 7 * 3);
+
+        // A task's start is logged after it takes a worker and its end before it frees one
+        let (mut running, mut most) = (0, 0);
+        for line in fs::read_to_string(&trace).unwrap().lines() {
+            running += if line == "start" { 1 } else { -1 };
+            most = most.max(running);
+        }
+        assert_eq!(most, 2);
+
+        let err = report_local_run("77", &codes, &opts.log_dir).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("1 of 7 local tasks failed (indices 7)")
+        );
+
+        // With --chunk, each task runs its block of commands
+        let chunked = SubmitOptions { chunk: 3, ..opts };
+        assert_eq!(
+            run_locally(&jobs, 2, "78", &chunked).unwrap(),
+            vec![0, 0, 3]
+        );
+    }
+
+    #[test]
+    fn test_row_resources_follow_rows_not_commands() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,mem\ns1,4\ns1,16\n,8\ns2,4").unwrap();
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "run {ID}".to_string(),
+            memory_column: Some("mem".to_string()),
+            ..Default::default()
+        };
+
+        // Both rows rendering `run s1` keep their own memory
+        let (mut jobs, mut input_sizes) = read_jobs(&opts).unwrap();
+        let mut row_resources = read_row_resources(&opts, &jobs).unwrap();
+        retain_jobs(&mut jobs, &mut input_sizes, &mut row_resources, |job| {
+            job != "run "
+        });
+        let sub_arrays = split_sub_arrays(&opts, jobs, &input_sizes, &row_resources);
+        let arrays: Vec<(u32, Vec<String>)> = sub_arrays
+            .into_iter()
+            .map(|(array_opts, array_jobs)| (array_opts.memory_gb, array_jobs))
+            .collect();
+        assert_eq!(
+            arrays,
+            vec![
+                (4, vec!["run s1".to_string(), "run s2".to_string()]),
+                (16, vec!["run s1".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_input_sizes_follow_rows_not_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("empty_1.fq"), "").unwrap();
+        fs::write(dir.path().join("empty_2.fq"), "").unwrap();
+        fs::write(dir.path().join("small_1.fq"), vec![b'A'; 100]).unwrap();
+        fs::write(dir.path().join("small_2.fq"), vec![b'A'; 100]).unwrap();
+        fs::write(dir.path().join("big_1.fq"), vec![b'A'; 3_000_000]).unwrap();
+        fs::write(dir.path().join("big_2.fq"), vec![b'A'; 3_000_000]).unwrap();
+
+        // Sizes stay with their rows through --task-log wrapping and filtering
+        let opts = SubmitOptions {
+            input_path: dir.path().to_str().unwrap().to_string(),
+            command_template: "wc -c {R1} {R2}".to_string(),
+            task_log: Some(format!("{}/{{ID}}", dir.path().display())),
+            format: InputFormat::Directory,
+            mem_per_gb_input: Some(1_000_000),
+            ..Default::default()
+        };
+        let (mut jobs, mut input_sizes) = read_jobs(&opts).unwrap();
+        assert_eq!(input_sizes.len(), 3);
+        retain_jobs(&mut jobs, &mut input_sizes, &mut Vec::new(), |job| {
+            !job.contains("/empty")
+        });
+        let sub_arrays = split_sub_arrays(&opts, jobs, &input_sizes, &[]);
+        let arrays: Vec<(u32, Vec<String>)> = sub_arrays
+            .into_iter()
+            .map(|(array_opts, jobs)| (array_opts.memory_gb, jobs))
+            .collect();
+        assert_eq!(arrays.len(), 2);
+        assert_eq!(arrays[0].0, 1);
+        assert!(arrays[0].1[0].contains("/small.out"));
+        assert_eq!(arrays[1].0, 6);
+        assert!(arrays[1].1[0].contains("/big.out"));
+    }
+
+    #[test]
+    fn test_jobs_file_runs_pre_submit_checks() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let jobs_path = log_dir.path().join("jobs.txt");
+        fs::write(&jobs_path, "bash -c 'exit 4'\necho b\n").unwrap();
+        let opts = SubmitOptions {
+            input_path: jobs_path.to_str().unwrap().to_string(),
+            format: InputFormat::JobsFile,
+            log_dir: log_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        // The smoke test stops a failing first command before bsub is reached
+        let smoke = SubmitOptions {
+            smoke_test: true,
+            ..opts.clone()
+        };
+        let err = submit_jobs(&smoke).unwrap_err();
+        assert!(err.to_string().starts_with("Smoke test failed"));
+
+        // A recent identical submission is refused, by the hash of the file's commands
+        let jobs = read_jobs_file(&opts.input_path).unwrap();
+        let hash = submission_hash(&opts, &jobs);
+        let timestamp = Local::now().format(manifest::TIMESTAMP_FORMAT).to_string();
+        let manifest = Manifest {
+            job_id: "42".to_string(),
+            submitted_at: timestamp.clone(),
+            num_jobs: jobs.len(),
+            log_file: opts.input_path.clone(),
+            options: opts.clone(),
+            submission_hash: hash,
+            note: None,
+            requested_memory_gb: None,
+            requested_threads: None,
+        };
+        manifest::write_manifest(
+            &format!("{}/arrayify-{}.json", opts.log_dir, timestamp),
+            &manifest,
+        )
+        .unwrap();
+        let err = submit_jobs(&SubmitOptions {
+            no_duplicate: true,
+            ..opts
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn test_after_array_refuses_renumbered_tasks() {
+        let mut csv_file = NamedTempFile::new().unwrap();
+        writeln!(csv_file, "ID,MEM\ns1,4\ns2,8\ns1,4\n,4").unwrap();
+        let opts = SubmitOptions {
+            input_path: csv_file.path().to_str().unwrap().to_string(),
+            command_template: "sort {ID}".to_string(),
+            after_array: Some(4242),
+            skip_empty: false,
+            dry_run: true,
+            ..Default::default()
+        };
+        assert!(submit_jobs(&opts).is_ok());
+
+        let refusal = |changed: SubmitOptions| {
+            let err = submit_jobs(&changed).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            err.to_string()
+        };
+        assert!(
+            refusal(SubmitOptions {
+                dedup: true,
+                ..opts.clone()
+            })
+            .contains("but --dedup renumbers")
+        );
+        assert!(
+            refusal(SubmitOptions {
+                skip_empty: true,
+                command_template: "{ID}".to_string(),
+                ..opts.clone()
+            })
+            .contains("but skipping empty commands renumbers")
+        );
+        assert!(
+            refusal(SubmitOptions {
+                chunk: 2,
+                ..opts.clone()
+            })
+            .contains("but --chunk renumbers")
+        );
+        assert!(
+            refusal(SubmitOptions {
+                memory_column: Some("MEM".to_string()),
+                ..opts.clone()
+            })
+            .contains("but splitting the submission into several arrays renumbers")
+        );
+        assert!(
+            refusal(SubmitOptions {
+                max_array_size: Some(2),
+                ..opts
+            })
+            .contains("but chunking to fit the array size limit renumbers")
+        );
     }
 }
@@ -0,0 +1,244 @@
+use crate::check::{self, ArrayStatus};
+use crate::output;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Bar, BarChart, BarGroup, Block, List, ListItem, Paragraph};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// How often key presses are checked for between refreshes.
+const KEY_POLL: Duration = Duration::from_millis(200);
+
+/// What the dashboard shows for one array, rebuilt from each `bjobs` poll.
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    pub job_id: String,
+    pub status: ArrayStatus,
+    /// Exit code, reason and number of tasks, most common first
+    pub reasons: Vec<(String, String, u64)>,
+    /// One line per failed task, in index order
+    pub failed: Vec<String>,
+    /// First failed task shown in the list
+    pub scroll: usize,
+}
+
+impl Dashboard {
+    pub fn new(job_id: &str) -> Dashboard {
+        Dashboard {
+            job_id: job_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Replaces the shown state with a fresh `bjobs` report.
+    pub fn update(&mut self, bjobs_output: &str) {
+        self.status = check::parse_bjobs(bjobs_output);
+        self.reasons = check::group_failures(&self.status.failed)
+            .into_iter()
+            .map(|group| (group.exit_code, group.reason, group.indices.len() as u64))
+            .collect();
+
+        let mut failed: Vec<(usize, String)> = self
+            .status
+            .failed
+            .iter()
+            .map(|job| {
+                let index = check::array_index(&job.array_name).unwrap_or(0);
+                let line = format!(
+                    "[{}] exit {} on {}: {}",
+                    index, job.exit_code, job.host, job.reason
+                );
+                (index, line)
+            })
+            .collect();
+        failed.sort();
+        self.failed = failed.into_iter().map(|(_, line)| line).collect();
+        self.scroll_by(0);
+    }
+
+    /// Moves the failed task list, staying within it.
+    pub fn scroll_by(&mut self, delta: isize) {
+        let last = self.failed.len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(last);
+    }
+}
+
+fn draw(frame: &mut Frame, dashboard: &Dashboard) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(dashboard.reasons.len().clamp(1, 8) as u16 + 2),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .split(frame.area());
+
+    let status = &dashboard.status;
+    let counts = Line::from(vec![
+        output::render(&format!("🚀 {} running  ", status.running))
+            .into_owned()
+            .fg(Color::Cyan),
+        output::render(&format!("⏳ {} pending  ", status.pending))
+            .into_owned()
+            .fg(Color::Yellow),
+        output::render(&format!("✅ {} done  ", status.done))
+            .into_owned()
+            .fg(Color::Green),
+        output::render(&format!("❌ {} failed  ", status.failed.len()))
+            .into_owned()
+            .fg(Color::Red),
+        format!("{} total", status.total()).into(),
+    ]);
+    let title = if status.is_finished() {
+        format!(" Array {} (finished) ", dashboard.job_id)
+    } else {
+        format!(" Array {} ", dashboard.job_id)
+    };
+    frame.render_widget(
+        Paragraph::new(counts).block(Block::bordered().title(title)),
+        areas[0],
+    );
+
+    let bars: Vec<Bar> = dashboard
+        .reasons
+        .iter()
+        .map(|(exit_code, reason, count)| {
+            Bar::default()
+                .value(*count)
+                .label(Line::from(exit_code.as_str()))
+                .text_value(format!("{} {}", count, reason))
+                .style(Style::default().fg(Color::Red))
+        })
+        .collect();
+    frame.render_widget(
+        BarChart::default()
+            .block(Block::bordered().title(" Exit reasons "))
+            .direction(Direction::Horizontal)
+            .bar_width(1)
+            .bar_gap(0)
+            .data(BarGroup::default().bars(&bars)),
+        areas[1],
+    );
+
+    let items: Vec<ListItem> = dashboard
+        .failed
+        .iter()
+        .skip(dashboard.scroll)
+        .map(|line| ListItem::new(line.as_str()))
+        .collect();
+    frame.render_widget(
+        List::new(items)
+            .block(Block::bordered().title(format!(" Failed tasks ({}) ", dashboard.failed.len()))),
+        areas[2],
+    );
+
+    frame.render_widget(
+        Paragraph::new("↑/↓ or j/k scroll · PgUp/PgDn page · q quit").dim(),
+        areas[3],
+    );
+}
+
+/// Shows a live dashboard of the array, refreshed from `query` every
+/// `interval`, until `q` is pressed. Returns the last status seen.
+pub fn run_dashboard(
+    job_id: &str,
+    interval: Duration,
+    query: impl Fn(&str) -> io::Result<String>,
+) -> io::Result<ArrayStatus> {
+    let mut terminal = ratatui::try_init()?;
+    let mut dashboard = Dashboard::new(job_id);
+    let mut refreshed: Option<Instant> = None;
+
+    let result = loop {
+        if refreshed.is_none_or(|at| at.elapsed() >= interval) {
+            match query(job_id) {
+                Ok(output) => dashboard.update(&output),
+                Err(e) => break Err(e),
+            }
+            refreshed = Some(Instant::now());
+        }
+        if let Err(e) = terminal.draw(|frame| draw(frame, &dashboard)) {
+            break Err(e);
+        }
+
+        match event::poll(KEY_POLL) {
+            Ok(false) => continue,
+            Ok(true) => {}
+            Err(e) => break Err(e),
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        };
+        let page = terminal.size().map_or(10, |size| size.height as isize / 2);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => dashboard.scroll_by(1),
+            KeyCode::Up | KeyCode::Char('k') => dashboard.scroll_by(-1),
+            KeyCode::PageDown => dashboard.scroll_by(page),
+            KeyCode::PageUp => dashboard.scroll_by(-page),
+            _ => {}
+        }
+    };
+
+    // Always hand the terminal back, even when polling failed
+    ratatui::restore();
+    result.map(|()| dashboard.status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn test_dashboard_from_bjobs() {
+        let output = "\
+arrayify_job_array[1] DONE - node01
+arrayify_job_array[7] EXIT 137 node02
+arrayify_job_array[2] EXIT 137 node01
+arrayify_job_array[3] EXIT 1 node03
+arrayify_job_array[4] RUN - node03
+arrayify_job_array[5] PEND - -
+";
+        let mut dashboard = Dashboard::new("123");
+        dashboard.update(output);
+        assert_eq!(
+            (
+                dashboard.status.running,
+                dashboard.status.pending,
+                dashboard.status.done
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(dashboard.reasons[0].0, "137");
+        assert_eq!(dashboard.reasons[0].2, 2);
+        assert_eq!(dashboard.reasons[1].2, 1);
+        assert_eq!(dashboard.failed.len(), 3);
+        assert!(dashboard.failed[0].starts_with("[2] exit 137 on node01"));
+        assert!(dashboard.failed[2].starts_with("[7] exit 137 on node02"));
+
+        dashboard.scroll_by(10);
+        assert_eq!(dashboard.scroll, 2);
+        dashboard.scroll_by(-5);
+        assert_eq!(dashboard.scroll, 0);
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|frame| draw(frame, &dashboard)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Array 123"));
+        assert!(screen.contains("Failed tasks (3)"));
+    }
+}